@@ -1,5 +1,16 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use huffc::tally_frequency;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+#[cfg(target_os = "linux")]
+use huffc::fs::{read_file_direct, ReadAheadBuffer};
+use huffc::{
+    build_huffman_array, build_huffman_array_two_queue,
+    cache::{cache_stats, cached_encode_array, clear_cache},
+    compress, deserialize_huffman, encode_huffman_array, find_and_pop_min_two, huff_encode_bitvec,
+    is_stored_passthrough,
+    parallel::{compress_parallel, tally_frequency_parallel},
+    tally_frequency, FrequencyBuffer,
+};
+
+const DNA_FILE_PATH: &str = "./tests/resources/dna_seq_test.txt";
 
 pub fn criterion_benchmark(c: &mut Criterion) {
     let bytes = b"abcdefghij";
@@ -10,8 +21,282 @@ pub fn criterion_benchmark(c: &mut Criterion) {
         vec_.extend_from_slice(bytes);
     }
 
-    c.bench_function("unsafe", |b| b.iter(|| tally_frequency(black_box(&vec_))));
+    c.bench_function("tally_frequency", |b| {
+        b.iter(|| tally_frequency(black_box(&vec_)))
+    });
+}
+
+pub fn bench_cached_encode_array(c: &mut Criterion) {
+    // A handful of documents that keep repeating, as a server encoding similar payloads would see
+    let documents: Vec<Vec<u8>> = (0..8u8)
+        .map(|i| (0..4096).map(|j| (j % (16 + i as usize)) as u8).collect())
+        .collect();
+    let freq_buffers: Vec<_> = documents.iter().map(|d| tally_frequency(d)).collect();
+
+    clear_cache();
+    c.bench_function("cached_encode_array", |b| {
+        b.iter(|| {
+            for freq_buffer in &freq_buffers {
+                black_box(cached_encode_array(freq_buffer, 8));
+            }
+        })
+    });
+    let stats = cache_stats();
+    println!(
+        "cached_encode_array: {} hits, {} misses, hit rate {:.2}%",
+        stats.hits,
+        stats.misses,
+        stats.hit_rate() * 100.0
+    );
+}
+
+pub fn bench_deserialize(c: &mut Criterion) {
+    let original = std::fs::read(DNA_FILE_PATH).unwrap();
+    // The current format with canonical codes, as `compress` writes it
+    let compressed = compress(&original).unwrap();
+    assert!(!is_stored_passthrough(&compressed));
+
+    let mut group = c.benchmark_group("deserialize");
+    group.throughput(Throughput::Bytes(original.len() as u64));
+    group.bench_function("dna_seq", |b| {
+        b.iter(|| deserialize_huffman(black_box(&compressed)).unwrap())
+    });
+    group.finish();
+}
+
+pub fn bench_build_huffman_array(c: &mut Criterion) {
+    // Every byte value with a distinct count, the worst case for both approaches
+    let counts: Vec<u64> = (0..256u64)
+        .map(|symbol| (symbol * 7919) % 65_521 + 1)
+        .collect();
+    let freq_buffer = || FrequencyBuffer(counts.clone().try_into().unwrap());
+
+    // The linear scan `build_huffman_array` used before switching to a binary heap
+    let linear_scan = |mut freq_buffer: FrequencyBuffer| {
+        freq_buffer.0.reverse();
+        let mut huffman_array = Vec::with_capacity(256);
+        while let Some(((min, _), second)) = find_and_pop_min_two(&mut freq_buffer.0) {
+            huffman_array.push(u8::MAX - min);
+            if let Some((second, _)) = second {
+                huffman_array.push(u8::MAX - second);
+            }
+        }
+        huffman_array.reverse();
+        huffman_array
+    };
+    assert_eq!(
+        linear_scan(freq_buffer()),
+        build_huffman_array(&freq_buffer())
+    );
+
+    let mut group = c.benchmark_group("build_huffman_array");
+    group.bench_function("linear_scan", |b| {
+        b.iter_batched(freq_buffer, linear_scan, BatchSize::SmallInput)
+    });
+    group.bench_function("binary_heap", |b| {
+        b.iter_batched_ref(
+            freq_buffer,
+            |freq_buffer| build_huffman_array(freq_buffer),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("two_queue", |b| {
+        b.iter_batched(
+            freq_buffer,
+            build_huffman_array_two_queue,
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+pub fn bench_encode_by_code_length(c: &mut Criterion) {
+    const INPUT_LEN: usize = 4 * 1024 * 1024;
+
+    let mut group = c.benchmark_group("encode_by_code_length");
+    group.throughput(Throughput::Bytes(INPUT_LEN as u64));
+    group.sample_size(20);
+    // The unary codes of `encode_huffman_array` give n symbols a maximum code length of n - 1,
+    // equally frequent symbols make the average code length about half of that. Up to 8 bits
+    // `huff_encode_bitvec` takes the short code path, above it appends code by code
+    for max_code_length in [2, 4, 8, 12, 16] {
+        let num_symbols = max_code_length + 1;
+        let input: Vec<u8> = (0..INPUT_LEN).map(|i| (i % num_symbols) as u8).collect();
+        let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(&input)));
+        let rarest = *build_huffman_array(&tally_frequency(&input))
+            .last()
+            .unwrap();
+        assert_eq!(
+            huff_encode_bitvec(&[rarest], &encoded_map).unwrap().1,
+            max_code_length as u64
+        );
+
+        group.bench_function(format!("max_{}_bits", max_code_length), |b| {
+            b.iter(|| huff_encode_bitvec(black_box(&input), &encoded_map).unwrap())
+        });
+    }
+    group.finish();
+}
+
+pub fn bench_tally_frequency_parallel(c: &mut Criterion) {
+    let input: Vec<u8> = (b'a'..=b'z').cycle().take(100 * 1024 * 1024).collect();
+    let four_threads = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let mut group = c.benchmark_group("tally_frequency_parallel");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.sample_size(10);
+    group.bench_function("serial", |b| b.iter(|| tally_frequency(black_box(&input))));
+    group.bench_function("4_threads", |b| {
+        b.iter(|| four_threads.install(|| tally_frequency_parallel(black_box(&input))))
+    });
+    group.bench_function(format!("{}_threads", rayon::current_num_threads()), |b| {
+        b.iter(|| tally_frequency_parallel(black_box(&input)))
+    });
+    group.finish();
+}
+
+pub fn bench_compress_parallel_vs_serial(c: &mut Criterion) {
+    let dna = std::fs::read(DNA_FILE_PATH).unwrap();
+    let input: Vec<u8> = dna.iter().copied().cycle().take(10 * 1024 * 1024).collect();
+
+    let mut group = c.benchmark_group("compress_parallel_vs_serial");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.sample_size(10);
+    // Both use canonical codes and store what does not shrink, only the threading differs
+    group.bench_function("serial", |b| {
+        b.iter(|| compress(black_box(&input)).unwrap())
+    });
+    group.bench_function("parallel_4_threads", |b| {
+        b.iter(|| compress_parallel(black_box(&input), 4))
+    });
+    group.finish();
+}
+
+/// Writes the benchmark file for `bench_read_file_cold_cache`, `HUFFC_BENCH_READ_MB` overrides
+/// its default size of 1 GiB.
+#[cfg(target_os = "linux")]
+fn read_bench_file() -> std::path::PathBuf {
+    use std::io::Write;
+
+    let size_mb: u64 = std::env::var("HUFFC_BENCH_READ_MB")
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(1024);
+    let size = size_mb * 1024 * 1024;
+    // Not in the temporary directory, which is often tmpfs and does not support O_DIRECT
+    let path = std::path::PathBuf::from("./target/huffc_bench_read_file.bin");
+    if std::fs::metadata(&path).map(|m| m.len()).ok() != Some(size) {
+        let dna = std::fs::read(DNA_FILE_PATH).unwrap();
+        let chunk: Vec<u8> = dna.iter().copied().cycle().take(1024 * 1024).collect();
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&path).unwrap());
+        (0..size_mb).for_each(|_| file.write_all(&chunk).unwrap());
+    }
+    path
+}
+
+/// Evicts the file from the page cache so every read starts cold.
+#[cfg(target_os = "linux")]
+fn drop_page_cache(path: &std::path::Path) {
+    use std::os::unix::io::AsRawFd;
+
+    let file = std::fs::File::open(path).unwrap();
+    unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
 }
 
-criterion_group!(benches, criterion_benchmark);
+#[cfg(target_os = "linux")]
+pub fn bench_read_file_cold_cache(c: &mut Criterion) {
+    let path = read_bench_file();
+    let size = std::fs::metadata(&path).unwrap().len();
+
+    let mut group = c.benchmark_group("read_file_cold_cache");
+    group.throughput(Throughput::Bytes(size));
+    group.sample_size(10);
+    // Both variants tally the contents, a memory map is only read when it is touched
+    group.bench_function("mmap", |b| {
+        b.iter_batched(
+            || drop_page_cache(&path),
+            |_| {
+                let file = std::fs::File::open(&path).unwrap();
+                let mmap = unsafe { memmap2::Mmap::map(&file) }.unwrap();
+                tally_frequency(black_box(&mmap))
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.bench_function("o_direct", |b| {
+        b.iter_batched(
+            || drop_page_cache(&path),
+            |_| tally_frequency(black_box(&read_file_direct(&path).unwrap())),
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn bench_read_file_cold_cache(_: &mut Criterion) {}
+
+/// Sequential chunked reading against `ReadAheadBuffer`, both tally every chunk. The gain is
+/// largest on slow storage, e.g. a spinning disk, where reading and tallying take similar time.
+#[cfg(target_os = "linux")]
+pub fn bench_read_ahead(c: &mut Criterion) {
+    use std::io::Read;
+
+    const CHUNK_SIZE: usize = 8 * 1024 * 1024;
+    let path = read_bench_file();
+    let size = std::fs::metadata(&path).unwrap().len();
+
+    let mut group = c.benchmark_group("read_ahead");
+    group.throughput(Throughput::Bytes(size));
+    group.sample_size(10);
+    group.bench_function("sequential", |b| {
+        b.iter_batched(
+            || drop_page_cache(&path),
+            |_| {
+                let mut file = std::fs::File::open(&path).unwrap();
+                let mut chunk = vec![0; CHUNK_SIZE];
+                loop {
+                    let n = file.read(&mut chunk).unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    black_box(tally_frequency(&chunk[..n]));
+                }
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.bench_function("read_ahead", |b| {
+        b.iter_batched(
+            || drop_page_cache(&path),
+            |_| {
+                let file = std::fs::File::open(&path).unwrap();
+                for chunk in ReadAheadBuffer::new(file, CHUNK_SIZE, 2) {
+                    black_box(tally_frequency(&chunk.unwrap()));
+                }
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn bench_read_ahead(_: &mut Criterion) {}
+
+criterion_group!(
+    benches,
+    criterion_benchmark,
+    bench_cached_encode_array,
+    bench_deserialize,
+    bench_build_huffman_array,
+    bench_encode_by_code_length,
+    bench_tally_frequency_parallel,
+    bench_compress_parallel_vs_serial,
+    bench_read_file_cold_cache,
+    bench_read_ahead
+);
 criterion_main!(benches);
@@ -0,0 +1,115 @@
+use std::time::{Duration, Instant};
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use huffc::{
+    build_huffman_array, deserialize_huffman, encode_huffman_array, huff_encode_bitvec,
+    serialize_huffman, tally_frequency,
+};
+
+const NUM_INPUTS: usize = 10_000;
+const MIN_LEN: usize = 10;
+const MAX_LEN: usize = 10_000;
+
+/// xorshift64*, deterministic so every run measures the same inputs
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+/// Inputs with a uniformly distributed length and a random alphabet size, so the tree heights
+/// range from tiny to the 255 levels of a full byte alphabet.
+fn random_inputs() -> Vec<Vec<u8>> {
+    let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+    (0..NUM_INPUTS)
+        .map(|_| {
+            let len = MIN_LEN + rng.below(MAX_LEN - MIN_LEN + 1);
+            let alphabet = 2 + rng.below(255);
+            let mut input: Vec<u8> = (0..len).map(|_| rng.below(alphabet) as u8).collect();
+            // At least two distinct bytes, a single symbol gets an empty code
+            input[0] = 0;
+            input[1] = 1;
+            input
+        })
+        .collect()
+}
+
+fn round_trip(bytes: &[u8]) -> Vec<u8> {
+    let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(bytes)));
+    let (bit_buffer, total_bits) = huff_encode_bitvec(bytes, &encoded_map).unwrap();
+    deserialize_huffman(&serialize_huffman(&encoded_map, bit_buffer, total_bits)).unwrap()
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let idx = ((sorted.len() as f64 * p / 100.0).ceil() as usize).clamp(1, sorted.len());
+    sorted[idx - 1]
+}
+
+pub fn bench_round_trip_latency(c: &mut Criterion) {
+    let inputs = random_inputs();
+
+    let mut latencies: Vec<(Duration, usize)> = inputs
+        .iter()
+        .enumerate()
+        .map(|(idx, input)| {
+            let start = Instant::now();
+            black_box(round_trip(black_box(input)));
+            (start.elapsed(), idx)
+        })
+        .collect();
+    latencies.sort();
+    let sorted: Vec<Duration> = latencies.iter().map(|(latency, _)| *latency).collect();
+    println!(
+        "round trip latency over {} inputs: P50 {:?}, P95 {:?}, P99 {:?}, P99.9 {:?}",
+        NUM_INPUTS,
+        percentile(&sorted, 50.0),
+        percentile(&sorted, 95.0),
+        percentile(&sorted, 99.0),
+        percentile(&sorted, 99.9)
+    );
+    let (worst, worst_idx) = latencies[latencies.len() - 1];
+    let worst_input = &inputs[worst_idx];
+    println!(
+        "slowest input: {:?}, {} bytes, {} distinct",
+        worst,
+        worst_input.len(),
+        tally_frequency(worst_input)
+            .0
+            .iter()
+            .filter(|count| **count != 0)
+            .count()
+    );
+
+    // Each iteration is a single input, cycling through the set, so the reported time is the
+    // latency of one round trip rather than of the whole set
+    let mut next = 0;
+    c.bench_function("round_trip_latency", |b| {
+        b.iter_custom(|iters| {
+            let mut total = Duration::ZERO;
+            for _ in 0..iters {
+                let input = &inputs[next % inputs.len()];
+                next += 1;
+                let start = Instant::now();
+                black_box(round_trip(black_box(input)));
+                total += start.elapsed();
+            }
+            total
+        })
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().with_output_color(true);
+    targets = bench_round_trip_latency
+}
+criterion_main!(benches);
@@ -0,0 +1,10 @@
+#![no_main]
+
+use huffc::deserialize_huffman;
+use libfuzzer_sys::fuzz_target;
+
+// `.huff` files come from disk and are untrusted, malformed input must return an error instead
+// of panicking.
+fuzz_target!(|data: &[u8]| {
+    let _ = deserialize_huffman(data);
+});
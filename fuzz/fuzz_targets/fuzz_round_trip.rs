@@ -0,0 +1,11 @@
+#![no_main]
+
+use huffc::{compress, decompress};
+use libfuzzer_sys::fuzz_target;
+
+// Compresses arbitrary input with the public API, stored passthrough included, and checks that
+// decompressing gives it back.
+fuzz_target!(|data: &[u8]| {
+    let compressed = compress(data).unwrap();
+    assert_eq!(decompress(&compressed).unwrap(), data);
+});
@@ -0,0 +1,27 @@
+#![no_main]
+
+use huffc::{
+    build_huffman_array, deserialize_huffman, encode_huffman_array, huff_encode_bitvec,
+    serialize_huffman, tally_frequency, HuffErr,
+};
+use libfuzzer_sys::fuzz_target;
+
+// Tallies arbitrary input and pushes the resulting frequency distribution through the whole
+// pipeline, adversarial distributions (all zero, one symbol, all equal) must not panic. Empty
+// input, the all zero case, has nothing to encode and must be rejected by huff_encode_bitvec.
+fuzz_target!(|data: &[u8]| {
+    let freq_buff = tally_frequency(data);
+    assert_eq!(freq_buff.0.iter().sum::<u64>(), data.len() as u64);
+
+    let huffnode = build_huffman_array(&freq_buff);
+    let encoded_map = encode_huffman_array(&huffnode);
+    let (bit_buffer, total_bits) = match huff_encode_bitvec(data, &encoded_map) {
+        Ok(encoded) => encoded,
+        Err(HuffErr::NullInput(_)) if data.is_empty() => return,
+        Err(e) => panic!("encoding failed: {}", e),
+    };
+    let serialized_buffer = serialize_huffman(&encoded_map, bit_buffer, total_bits);
+    let deserialized_bytes = deserialize_huffman(&serialized_buffer).unwrap();
+
+    assert_eq!(deserialized_bytes, data);
+});
@@ -0,0 +1,344 @@
+//! Huffc Archive - Named Files in a Single `.huff` Buffer
+//!
+//! `create_archive` compresses several named files into one `.huff` buffer, every file on its own
+//! like a block of `parallel::compress_parallel`, so each can be read without the others.
+//! `HuffArchive` reads an archive held in memory and decompresses a single file with
+//! `extract_file`. `HuffArchiveMapped` reads an archive from a memory-mapped file and also hands
+//! out the compressed bytes of a file with `get_file_slice`, a slice into the mapping that is
+//! neither decompressed nor copied, e.g. to inspect its header or copy it into another archive.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use huffc::archive::{create_archive, HuffArchive};
+//!
+//! let files = [("a.txt", &b"abracadabra"[..]), ("empty.txt", &b""[..])];
+//! let archive = HuffArchive::from_bytes(create_archive(files).unwrap()).unwrap();
+//! assert_eq!(archive.names().collect::<Vec<_>>(), ["a.txt", "empty.txt"]);
+//! assert_eq!(archive.extract_file("a.txt").unwrap(), b"abracadabra");
+//! ```
+//!
+//! Layout: `HUFF_MAGIC`, the format version and a checksum like those of `serialize_huffman`,
+//! then the format byte (`0x20`), the number of files (8 bytes), for every file the length of its
+//! name (8 bytes), the name in UTF-8 and the length of the compressed file (8 bytes), then the
+//! compressed files in the same order. A compressed file is a Huffman buffer, or the stored file
+//! if encoding would not make it smaller, without the magic bytes, version and checksum.
+//!
+use std::{fs::File, ops::Range, path::Path};
+
+use memmap2::Mmap;
+
+use crate::{
+    huff_payload_offset, level::decompress_in_block, parallel::compress_own_block, u64_to_u8,
+    u8_to_u64, validate_huff_bytes, wrap_huff_payload, HuffErr,
+};
+
+/// Format byte of a buffer holding named, independently compressed files.
+pub(crate) const FORMAT_ARCHIVE: u8 = 0x20;
+
+/// Compresses `files`, pairs of a name and the file contents, into an archive. The files keep
+/// their order.
+///
+/// # Errors
+///
+/// Returns `HuffErr::DuplicateArchiveEntry` if two files have the same name.
+pub fn create_archive<'a, I>(files: I) -> Result<Vec<u8>, HuffErr>
+where
+    I: IntoIterator<Item = (&'a str, &'a [u8])>,
+{
+    let files: Vec<(&str, &[u8])> = files.into_iter().collect();
+    for (idx, (name, _)) in files.iter().enumerate() {
+        if files[..idx].iter().any(|(other, _)| other == name) {
+            return Err(HuffErr::DuplicateArchiveEntry(name.to_string()));
+        }
+    }
+    let compressed: Vec<Vec<u8>> = files
+        .iter()
+        .map(|(_, contents)| compress_own_block(contents))
+        .collect();
+
+    let mut payload = vec![FORMAT_ARCHIVE];
+    payload.extend_from_slice(&u64_to_u8(files.len() as u64));
+    for ((name, _), compressed) in files.iter().zip(&compressed) {
+        payload.extend_from_slice(&u64_to_u8(name.len() as u64));
+        payload.extend_from_slice(name.as_bytes());
+        payload.extend_from_slice(&u64_to_u8(compressed.len() as u64));
+    }
+    compressed
+        .into_iter()
+        .for_each(|compressed| payload.extend(compressed));
+    Ok(wrap_huff_payload(&payload))
+}
+
+/// A file of an archive, `range` is the position of its compressed bytes in the whole buffer.
+#[derive(Debug)]
+struct ArchiveEntry {
+    name: String,
+    range: Range<usize>,
+}
+
+/// Archive held in memory.
+#[derive(Debug)]
+pub struct HuffArchive {
+    bytes: Vec<u8>,
+    entries: Vec<ArchiveEntry>,
+}
+
+impl HuffArchive {
+    /// Reads the file table of an archive written by `create_archive`, the files are decompressed
+    /// on demand.
+    ///
+    /// # Errors
+    ///
+    /// * `HuffErr::NullInput` - `bytes` is empty.
+    /// * `HuffErr::NotAHuffFile` - The buffer does not start with `HUFF_MAGIC`.
+    /// * `HuffErr::ChecksumMismatch` - The archive does not match its checksum.
+    /// * `HuffErr::MalformedData` - The buffer is not an archive, or its file table is truncated
+    ///   or inconsistent.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, HuffErr> {
+        let entries = read_entries(&bytes)?;
+        Ok(Self { bytes, entries })
+    }
+
+    /// Names of the files in the archive, in archive order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|entry| entry.name.as_str())
+    }
+
+    /// Decompresses the file `name` into a new buffer.
+    ///
+    /// # Errors
+    ///
+    /// * `HuffErr::FileNotInArchive` - The archive has no file `name`.
+    /// * `HuffErr::MalformedData` - The compressed file does not decode.
+    pub fn extract_file(&self, name: &str) -> Result<Vec<u8>, HuffErr> {
+        decompress_in_block(file_slice(&self.entries, &self.bytes, name)?)
+    }
+}
+
+/// Archive read from a memory-mapped file.
+#[derive(Debug)]
+pub struct HuffArchiveMapped {
+    mmap: Mmap,
+    entries: Vec<ArchiveEntry>,
+}
+
+impl HuffArchiveMapped {
+    /// Maps the archive at `path` into memory and reads its file table.
+    ///
+    /// # Errors
+    ///
+    /// * `HuffErr::Io` - The file cannot be opened or mapped.
+    /// * Any error of `HuffArchiveMapped::from_mmap`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, HuffErr> {
+        let file = File::open(path)?;
+        Self::from_mmap(unsafe { Mmap::map(&file) }?)
+    }
+
+    /// Reads the file table of the archive in `mmap`.
+    ///
+    /// # Errors
+    ///
+    /// See `HuffArchive::from_bytes`.
+    pub fn from_mmap(mmap: Mmap) -> Result<Self, HuffErr> {
+        let entries = read_entries(&mmap)?;
+        Ok(Self { mmap, entries })
+    }
+
+    /// Names of the files in the archive, in archive order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|entry| entry.name.as_str())
+    }
+
+    /// Returns the compressed bytes of the file `name` without decompressing them. The slice
+    /// points into the mapping, nothing is copied. It starts with the format byte of the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns `HuffErr::FileNotInArchive` if the archive has no file `name`.
+    pub fn get_file_slice(&self, name: &str) -> Result<&[u8], HuffErr> {
+        file_slice(&self.entries, &self.mmap, name)
+    }
+
+    /// Decompresses the file `name` into a new buffer, reading the compressed bytes in place.
+    ///
+    /// # Errors
+    ///
+    /// See `HuffArchive::extract_file`.
+    pub fn extract_file(&self, name: &str) -> Result<Vec<u8>, HuffErr> {
+        decompress_in_block(self.get_file_slice(name)?)
+    }
+}
+
+fn file_slice<'a>(
+    entries: &[ArchiveEntry],
+    bytes: &'a [u8],
+    name: &str,
+) -> Result<&'a [u8], HuffErr> {
+    entries
+        .iter()
+        .find(|entry| entry.name == name)
+        .map(|entry| &bytes[entry.range.clone()])
+        .ok_or_else(|| HuffErr::FileNotInArchive(name.to_string()))
+}
+
+/// Validates the archive in `bytes` and returns its files, checking that the compressed files
+/// fill the rest of the buffer.
+fn read_entries(bytes: &[u8]) -> Result<Vec<ArchiveEntry>, HuffErr> {
+    let header = validate_huff_bytes(bytes)?;
+    if header.format != FORMAT_ARCHIVE {
+        return Err(HuffErr::MalformedData("not an archive".to_string()));
+    }
+    let truncated = || HuffErr::MalformedData("truncated file table".to_string());
+    let payload_start = huff_payload_offset(header.format_version);
+    let payload = &bytes[payload_start..];
+    if payload.len() < 9 {
+        return Err(truncated());
+    }
+
+    // Every file takes at least 16 bytes in the table and its format byte, so the file count is
+    // bounded by the buffer length before anything is allocated
+    let num_files = u8_to_u64(&payload[1..9]);
+    if num_files > (payload.len() as u64 - 9) / 17 {
+        return Err(truncated());
+    }
+
+    let mut names_and_lens = Vec::with_capacity(num_files as usize);
+    let mut pos = 9;
+    for _ in 0..num_files {
+        let name_len = take(payload, &mut pos, 8).ok_or_else(truncated)?;
+        let name = take(payload, &mut pos, u8_to_u64(name_len)).ok_or_else(truncated)?;
+        let name = std::str::from_utf8(name)
+            .map_err(|_| HuffErr::MalformedData("file name is not UTF-8".to_string()))?;
+        let compressed_len = take(payload, &mut pos, 8).ok_or_else(truncated)?;
+        names_and_lens.push((name.to_string(), u8_to_u64(compressed_len)));
+    }
+
+    let mut entries: Vec<ArchiveEntry> = Vec::with_capacity(names_and_lens.len());
+    for (name, compressed_len) in names_and_lens {
+        if compressed_len == 0 {
+            return Err(HuffErr::MalformedData("empty compressed file".to_string()));
+        }
+        if entries.iter().any(|entry| entry.name == name) {
+            return Err(HuffErr::MalformedData(format!(
+                "duplicate file name {}",
+                name
+            )));
+        }
+        let start = pos;
+        take(payload, &mut pos, compressed_len).ok_or(HuffErr::MalformedData(
+            "truncated compressed file".to_string(),
+        ))?;
+        entries.push(ArchiveEntry {
+            name,
+            range: payload_start + start..payload_start + pos,
+        });
+    }
+    if pos != payload.len() {
+        return Err(HuffErr::MalformedData(
+            "trailing bytes after the last file".to_string(),
+        ));
+    }
+    Ok(entries)
+}
+
+/// The `len` bytes of `bytes` at `pos`, advancing `pos` past them. `None` if they run past the
+/// end of `bytes`.
+fn take<'a>(bytes: &'a [u8], pos: &mut usize, len: u64) -> Option<&'a [u8]> {
+    let end = pos.checked_add(usize::try_from(len).ok()?)?;
+    let field = bytes.get(*pos..end)?;
+    *pos = end;
+    Some(field)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::write_file;
+
+    fn files(original: &[u8]) -> [(&str, &[u8]); 3] {
+        [
+            ("dna.txt", original),
+            ("empty.txt", b""),
+            ("notes/short.txt", b"xyz"),
+        ]
+    }
+
+    #[test]
+    fn files_round_trip() {
+        let original = std::fs::read("./tests/resources/dna_seq_test.txt").unwrap();
+        let archive = HuffArchive::from_bytes(create_archive(files(&original)).unwrap()).unwrap();
+        assert_eq!(
+            archive.names().collect::<Vec<_>>(),
+            ["dna.txt", "empty.txt", "notes/short.txt"]
+        );
+        for (name, contents) in files(&original) {
+            assert_eq!(archive.extract_file(name).unwrap(), contents);
+        }
+        assert!(matches!(
+            archive.extract_file("missing.txt"),
+            Err(HuffErr::FileNotInArchive(name)) if name == "missing.txt"
+        ));
+
+        let empty = HuffArchive::from_bytes(create_archive([]).unwrap()).unwrap();
+        assert_eq!(empty.names().count(), 0);
+    }
+
+    #[test]
+    fn file_slices_point_into_the_mapping() {
+        let original = std::fs::read("./tests/resources/dna_seq_test.txt").unwrap();
+        let path = std::env::temp_dir().join(format!("huffc-archive-{}.huff", std::process::id()));
+        write_file(&path, create_archive(files(&original)).unwrap()).unwrap();
+        let archive = HuffArchiveMapped::open(&path);
+        std::fs::remove_file(&path).unwrap();
+        let archive = archive.unwrap();
+
+        let mapping = archive.mmap.as_ptr_range();
+        for (name, contents) in files(&original) {
+            let slice = archive.get_file_slice(name).unwrap();
+            assert!(mapping.contains(&slice.as_ptr()));
+            assert!(slice.as_ptr_range().end <= mapping.end);
+            assert_eq!(decompress_in_block(slice).unwrap(), contents);
+            assert_eq!(archive.extract_file(name).unwrap(), contents);
+        }
+        // The DNA file compresses, the three distinct bytes are stored
+        assert!(archive.get_file_slice("dna.txt").unwrap().len() < original.len());
+        assert_eq!(
+            archive.get_file_slice("notes/short.txt").unwrap(),
+            b"\x00xyz"
+        );
+        assert!(matches!(
+            archive.get_file_slice("missing.txt"),
+            Err(HuffErr::FileNotInArchive(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_archives_are_rejected() {
+        assert!(matches!(
+            create_archive([("a", &b"1"[..]), ("a", &b"2"[..])]),
+            Err(HuffErr::DuplicateArchiveEntry(name)) if name == "a"
+        ));
+        assert!(matches!(
+            HuffArchive::from_bytes(crate::compress(b"abracadabra").unwrap()),
+            Err(HuffErr::MalformedData(_))
+        ));
+
+        let mut payload = vec![FORMAT_ARCHIVE];
+        payload.extend_from_slice(&u64_to_u8(u64::MAX));
+        assert!(HuffArchive::from_bytes(wrap_huff_payload(&payload)).is_err());
+
+        // A name length running past the end of the buffer
+        let mut payload = vec![FORMAT_ARCHIVE];
+        payload.extend_from_slice(&u64_to_u8(1));
+        payload.extend_from_slice(&u64_to_u8(u64::MAX));
+        payload.extend_from_slice(&[0; 9]);
+        assert!(HuffArchive::from_bytes(wrap_huff_payload(&payload)).is_err());
+
+        let archive = create_archive([("a", &b"abc"[..])]).unwrap();
+        let mut trailing = archive[huff_payload_offset(crate::HUFF_FORMAT_VERSION)..].to_vec();
+        trailing.push(0);
+        assert!(HuffArchive::from_bytes(wrap_huff_payload(&trailing)).is_err());
+        assert!(HuffArchive::from_bytes(archive[..archive.len() - 1].to_vec()).is_err());
+    }
+}
@@ -0,0 +1,183 @@
+//! Huffc Batch - Encoding Eight Bytes per Table Lookup
+//!
+//! For small alphabets whose codes are all at most 8 bits, the codes of every sequence of eight
+//! symbols can be precomputed. Encoding then takes one lookup per eight input bytes, keyed by the
+//! bytes read as a little endian `u64`, and one shift to append the packed bits. The table has
+//! `k^8` entries for `k` symbols, so it is only built for alphabets of up to 5 symbols, e.g. DNA.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use huffc::{
+//!     batch::{huff_encode_batch, precompute_batch_table},
+//!     build_encoded_array, build_huffman_array, encode_huffman_array, huff_encode_bitvec,
+//!     tally_frequency,
+//! };
+//!
+//! let bytes = b"GATTACAGATTACA";
+//! let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(bytes)));
+//! let expected = huff_encode_bitvec(bytes, &encoded_map).unwrap();
+//!
+//! let table = precompute_batch_table(&build_encoded_array(encoded_map));
+//! assert_eq!(huff_encode_batch(bytes, &table), expected);
+//! ```
+//!
+use std::collections::HashMap;
+
+use crate::Encoded;
+
+/// Largest table `precompute_batch_table` builds.
+pub const MAX_BATCH_ENTRIES: usize = 1 << 20;
+
+/// Packed codes of eight consecutive symbols.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodedBatch {
+    /// The codes, right aligned, the first symbol in the most significant bits.
+    pub bits: u64,
+    pub total_bits: u8,
+    /// Number of bits after each of the eight symbols, used to encode a final partial batch.
+    pub ends: [u8; 8],
+}
+
+/// Precomputes the packed codes of every sequence of eight symbols of `map`.
+///
+/// Returns an empty table if a code is empty or longer than 8 bits, or the table would exceed
+/// `MAX_BATCH_ENTRIES` entries.
+pub fn precompute_batch_table(map: &[Option<Encoded>; 256]) -> HashMap<u64, EncodedBatch> {
+    let mut codes = Vec::new();
+    for encoded in map.iter().flatten() {
+        if encoded.bits.is_empty() || encoded.bits.len() > 8 {
+            return HashMap::new();
+        }
+        let code = encoded
+            .bits
+            .iter()
+            .by_vals()
+            .fold(0u64, |code, bit| code << 1 | bit as u64);
+        codes.push((encoded.value, code, encoded.bits.len() as u8));
+    }
+
+    let num_entries = match codes.len().checked_pow(8) {
+        Some(num_entries) if num_entries > 0 && num_entries <= MAX_BATCH_ENTRIES => num_entries,
+        _ => return HashMap::new(),
+    };
+
+    let mut table = HashMap::with_capacity(num_entries);
+    for combination in 0..num_entries {
+        let mut key = [0u8; 8];
+        let mut batch = EncodedBatch {
+            bits: 0,
+            total_bits: 0,
+            ends: [0; 8],
+        };
+        let mut digits = combination;
+        for (key_byte, end) in key.iter_mut().zip(batch.ends.iter_mut()) {
+            let (symbol, code, len) = codes[digits % codes.len()];
+            digits /= codes.len();
+
+            *key_byte = symbol;
+            batch.bits = batch.bits << len | code;
+            batch.total_bits += len;
+            *end = batch.total_bits;
+        }
+        table.insert(u64::from_le_bytes(key), batch);
+    }
+    table
+}
+
+/// Encodes `bytes` eight at a time with a table from `precompute_batch_table`. The output is
+/// identical to `huff_encode_bitvec`, the last byte is padded with zero bits.
+///
+/// # Panics
+///
+/// Panics if the table is empty or `bytes` contains a byte without a code.
+pub fn huff_encode_batch(bytes: &[u8], table: &HashMap<u64, EncodedBatch>) -> (Vec<u8>, u64) {
+    let mut out = Vec::with_capacity(bytes.len() + 16);
+    // Bits not yet written, right aligned, fewer than 8 between batches
+    let mut acc: u128 = 0;
+    let mut acc_bits: u32 = 0;
+    let mut total_bits: u64 = 0;
+    let mut append = |bits: u64, num_bits: u32| {
+        acc = acc << num_bits | bits as u128;
+        acc_bits += num_bits;
+        total_bits += num_bits as u64;
+
+        let full_bytes = acc_bits / 8;
+        let remaining = acc_bits % 8;
+        let full = (acc >> remaining).to_be_bytes();
+        out.extend_from_slice(&full[16 - full_bytes as usize..]);
+        acc &= (1 << remaining) - 1;
+        acc_bits = remaining;
+    };
+
+    let mut chunks = bytes.chunks_exact(8);
+    for chunk in &mut chunks {
+        let key = u64::from_le_bytes(chunk.try_into().unwrap());
+        let batch = table.get(&key).expect("every byte must have a code");
+        append(batch.bits, batch.total_bits as u32);
+    }
+
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        // Pad with a symbol of the table and keep only the bits of the tail
+        let padding = table
+            .keys()
+            .next()
+            .expect("the table must not be empty")
+            .to_le_bytes()[0];
+        let mut key = [padding; 8];
+        key[..tail.len()].copy_from_slice(tail);
+        let batch = table
+            .get(&u64::from_le_bytes(key))
+            .expect("every byte must have a code");
+        let tail_bits = batch.ends[tail.len() - 1] as u32;
+        append(
+            batch.bits >> (batch.total_bits as u32 - tail_bits),
+            tail_bits,
+        );
+    }
+
+    if acc_bits > 0 {
+        out.push((acc << (8 - acc_bits)) as u8);
+    }
+    (out, total_bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        build_encoded_array, build_huffman_array, encode_huffman_array, huff_encode_bitvec,
+        tally_frequency,
+    };
+
+    #[test]
+    fn batch_encoding_matches_huff_encode_bitvec() {
+        let original = std::fs::read("./tests/resources/dna_seq_test.txt").unwrap();
+        let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(&original)));
+        let table = precompute_batch_table(&build_encoded_array(encode_huffman_array(
+            &build_huffman_array(&tally_frequency(&original)),
+        )));
+        assert_eq!(table.len(), 4usize.pow(8));
+
+        for len in [1, 7, 8, 9, 15, 16, 1001, original.len()] {
+            let bytes = &original[..len];
+            let (expected_buffer, expected_bits) = huff_encode_bitvec(bytes, &encoded_map).unwrap();
+            let (buffer, total_bits) = huff_encode_batch(bytes, &table);
+
+            assert_eq!(total_bits, expected_bits);
+            assert_eq!(buffer, expected_buffer);
+        }
+    }
+
+    #[test]
+    fn no_table_for_large_alphabets_or_long_codes() {
+        let six_symbols = build_encoded_array(encode_huffman_array(b"abcdef"));
+        assert!(precompute_batch_table(&six_symbols).is_empty());
+
+        let long_codes = build_encoded_array(encode_huffman_array(b"abcdefghij"));
+        assert!(precompute_batch_table(&long_codes).is_empty());
+
+        assert!(precompute_batch_table(&std::array::from_fn(|_| None)).is_empty());
+    }
+}
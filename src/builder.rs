@@ -0,0 +1,263 @@
+//! Huffc Builder - Configurable Compression
+//!
+//! `CompressOptions` runs the whole compression pipeline like `compress`, with options set
+//! through builder methods. New options are added as methods, so callers only name the options
+//! they change. The output decompresses with `decompress`.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use huffc::CompressOptions;
+//!
+//! let bytes = b"abracadabra".repeat(100);
+//! let compressed = CompressOptions::new()
+//!     .with_block_size(256)
+//!     .with_canonical_codes(true)
+//!     .encode(&bytes)
+//!     .unwrap();
+//! assert_eq!(huffc::decompress(&compressed).unwrap(), bytes);
+//! ```
+//!
+use std::collections::HashMap;
+
+use crate::{
+    build_huffman_tree, canonicalize_encoding, encode_huffman_tree, huff_encode_bitvec,
+    parallel::{block_buffer, compress_block},
+    serialize_huffman_or_passthrough, serialize_stored, tally_frequency,
+    timing::StageTimer,
+    wrap_huff_payload, Encoded, FrequencyBuffer, HuffErr, HuffNode,
+};
+
+/// Compression options, the defaults give the same output as `compress`.
+#[derive(Debug, Clone)]
+pub struct CompressOptions {
+    block_size: Option<usize>,
+    canonical_codes: bool,
+    freq_buffer: Option<FrequencyBuffer>,
+}
+
+/// Canonical codes and no blocks, like `compress`.
+impl Default for CompressOptions {
+    fn default() -> Self {
+        Self {
+            block_size: None,
+            canonical_codes: true,
+            freq_buffer: None,
+        }
+    }
+}
+
+impl CompressOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cuts the input into blocks of `bytes` bytes that are compressed independently, in the
+    /// block format of `parallel::compress_parallel`. Every block gets its own code and is stored
+    /// if that code would not make it smaller. A block size of 0 is treated as 1.
+    pub fn with_block_size(mut self, bytes: usize) -> Self {
+        self.block_size = Some(bytes.max(1));
+        self
+    }
+
+    /// Whether the codes of the Huffman tree are replaced by canonical codes of the same lengths,
+    /// see `canonicalize_encoding`. On by default, the compressed size does not change.
+    pub fn with_canonical_codes(mut self, yes: bool) -> Self {
+        self.canonical_codes = yes;
+        self
+    }
+
+    /// Builds the code from `freq_buffer` instead of the frequencies of the input, like
+    /// `compress_with_frequencies`. In block mode every block uses this code.
+    pub fn with_frequencies(mut self, freq_buffer: FrequencyBuffer) -> Self {
+        self.freq_buffer = Some(freq_buffer);
+        self
+    }
+
+    /// Compresses `input` with the configured options.
+    ///
+    /// # Errors
+    ///
+    /// * `HuffErr::SymbolNotInCodebook` - `input` contains a byte with a count of zero in the
+    ///   table given to `with_frequencies`.
+    /// * `HuffErr::MalformedData` - `canonicalize_encoding` rejected the code lengths of the tree.
+    pub fn encode(self, input: &[u8]) -> Result<Vec<u8>, HuffErr> {
+        self.encode_timed(input, &mut StageTimer::new())
+    }
+
+    /// Compresses `input` like `encode` and records the time of each stage in `timer`, as
+    /// `build_huffman_tree`, `encode_huffman_tree`, `huff_encode_bitvec` and `serialize_huffman`,
+    /// or as a single `compress_blocks` in block mode.
+    ///
+    /// # Errors
+    ///
+    /// See `encode`.
+    pub fn encode_timed(self, input: &[u8], timer: &mut StageTimer) -> Result<Vec<u8>, HuffErr> {
+        let Some(block_size) = self.block_size else {
+            if input.is_empty() {
+                return Ok(serialize_stored(input));
+            }
+            let tree = self.tree(input);
+            timer.lap("build_huffman_tree");
+            let encoded_map = self.encode_tree(tree)?;
+            timer.lap("encode_huffman_tree");
+            let (bit_buffer, total_bits) = huff_encode_bitvec(input, &encoded_map)?;
+            timer.lap("huff_encode_bitvec");
+            let serialized_buffer =
+                serialize_huffman_or_passthrough(&encoded_map, bit_buffer, total_bits, input);
+            timer.lap("serialize_huffman");
+            return Ok(serialized_buffer);
+        };
+
+        // A given frequency table gives every block the same code, built once
+        let shared_map = match self.freq_buffer {
+            Some(ref freq_buffer) => Some(self.encode_tree(build_huffman_tree(freq_buffer))?),
+            None => None,
+        };
+        let blocks = input
+            .chunks(block_size)
+            .map(|block| match shared_map {
+                Some(ref encoded_map) => compress_block(block, encoded_map),
+                None => compress_block(block, &self.encode_tree(self.tree(block))?),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let serialized_buffer = wrap_huff_payload(&block_buffer(blocks));
+        timer.lap("compress_blocks");
+        Ok(serialized_buffer)
+    }
+
+    fn tree(&self, bytes: &[u8]) -> Option<HuffNode> {
+        match self.freq_buffer {
+            Some(ref freq_buffer) => build_huffman_tree(freq_buffer),
+            None => build_huffman_tree(&tally_frequency(bytes)),
+        }
+    }
+
+    fn encode_tree(&self, tree: Option<HuffNode>) -> Result<HashMap<u8, Encoded>, HuffErr> {
+        let Some(tree) = tree else {
+            return Ok(HashMap::new());
+        };
+        let mut encoded_map = encode_huffman_tree(&tree);
+        if self.canonical_codes {
+            canonicalize_encoding(&mut encoded_map)?;
+        }
+        Ok(encoded_map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compress, decompress, deserialize_huffman, parallel::compress_parallel};
+
+    #[test]
+    fn default_options_match_compress() {
+        let bytes = b"abracadabra".repeat(20);
+        let compressed = CompressOptions::new().encode(&bytes).unwrap();
+        assert_eq!(compressed, compress(&bytes).unwrap());
+
+        let empty = CompressOptions::new().encode(b"").unwrap();
+        assert_eq!(empty, compress(b"").unwrap());
+    }
+
+    #[test]
+    fn encode_timed_laps_every_stage() {
+        let bytes = b"abracadabra".repeat(20);
+        let mut timer = StageTimer::new();
+        let compressed = CompressOptions::new()
+            .encode_timed(&bytes, &mut timer)
+            .unwrap();
+        assert_eq!(compressed, compress(&bytes).unwrap());
+        let stages: Vec<&str> = timer.stages().iter().map(|(stage, _)| *stage).collect();
+        assert_eq!(
+            stages,
+            [
+                "build_huffman_tree",
+                "encode_huffman_tree",
+                "huff_encode_bitvec",
+                "serialize_huffman"
+            ]
+        );
+    }
+
+    #[test]
+    fn tree_codes_keep_the_size() {
+        let bytes = b"abracadabra".repeat(20);
+        let tree_codes = CompressOptions::new()
+            .with_canonical_codes(false)
+            .encode(&bytes)
+            .unwrap();
+        assert_eq!(tree_codes.len(), compress(&bytes).unwrap().len());
+        assert_ne!(tree_codes, compress(&bytes).unwrap());
+        assert_eq!(deserialize_huffman(&tree_codes).unwrap(), bytes);
+    }
+
+    #[test]
+    fn blocks_round_trip() {
+        let bytes = std::fs::read("./tests/resources/dna_seq_test.txt").unwrap();
+        for (block_size, canonical) in [(1000, false), (1000, true), (7, false), (0, true)] {
+            let compressed = CompressOptions::new()
+                .with_block_size(block_size)
+                .with_canonical_codes(canonical)
+                .encode(&bytes)
+                .unwrap();
+            assert_eq!(decompress(&compressed).unwrap(), bytes);
+        }
+
+        // Four blocks of a quarter each are the blocks of four threads
+        let blocks = CompressOptions::new()
+            .with_block_size(bytes.len() / 4)
+            .encode(&bytes)
+            .unwrap();
+        assert_eq!(blocks, compress_parallel(&bytes, 4));
+
+        let empty = CompressOptions::new()
+            .with_block_size(16)
+            .encode(b"")
+            .unwrap();
+        assert!(decompress(&empty).unwrap().is_empty());
+    }
+
+    #[test]
+    fn blocks_have_a_checksum() {
+        let bytes = b"abracadabra".repeat(20);
+        let mut blocks = CompressOptions::new()
+            .with_block_size(64)
+            .encode(&bytes)
+            .unwrap();
+        let last = blocks.len() - 1;
+        blocks[last] ^= 0x01;
+        assert!(matches!(
+            decompress(&blocks),
+            Err(HuffErr::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn frequencies_give_the_code() {
+        let freq_buffer = tally_frequency(b"abcd");
+        let compressed = CompressOptions::new()
+            .with_frequencies(freq_buffer.clone())
+            .encode(b"abba")
+            .unwrap();
+        assert_eq!(decompress(&compressed).unwrap(), b"abba");
+
+        for block_size in [None, Some(2)] {
+            let mut encoder = CompressOptions::new().with_frequencies(freq_buffer.clone());
+            if let Some(block_size) = block_size {
+                encoder = encoder.with_block_size(block_size);
+            }
+            assert!(matches!(
+                encoder.encode(b"abz"),
+                Err(HuffErr::SymbolNotInCodebook(b'z'))
+            ));
+        }
+
+        let blocks = CompressOptions::new()
+            .with_frequencies(freq_buffer)
+            .with_block_size(2)
+            .encode(b"abcddcba")
+            .unwrap();
+        assert_eq!(decompress(&blocks).unwrap(), b"abcddcba");
+    }
+}
@@ -0,0 +1,202 @@
+//! Huffc Codebook Cache - Thread-Local Caching of Encoded Arrays
+//!
+//! Rebuilding the encoding for every document is wasteful when many documents share the same
+//! frequency distribution. This module caches the result of `build_huffman_array`,
+//! `encode_huffman_array` and `build_encoded_array` per thread, keyed by the
+//! `FrequencyBuffer` fingerprint.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use huffc::{cache::cached_encode_array, tally_frequency};
+//!
+//! let freq_buff = tally_frequency(b"abracadabra");
+//! let encoded_array = cached_encode_array(&freq_buff, 16);
+//! assert!(encoded_array[b'a' as usize].is_some());
+//! ```
+//!
+use std::{cell::RefCell, num::NonZeroUsize, rc::Rc};
+
+use lru::LruCache;
+
+use crate::{
+    build_encoded_array, build_huffman_array, encode_huffman_array, Encoded, FrequencyBuffer,
+};
+
+/// Encoded array shared between the cache and its callers.
+///
+/// A reference counted pointer rather than a `&'static` reference, an evicted encoding would
+/// otherwise have to be leaked to keep outstanding references valid.
+pub type EncodedArray = Rc<[Option<Encoded>; 256]>;
+
+/// Hit and miss counters of the calling thread's cache.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// Fraction of lookups that were served from the cache, 0.0 when there were no lookups.
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / lookups as f64
+    }
+}
+
+struct EncodeCache {
+    // The counts are kept alongside the encoding so a fingerprint collision is treated as a miss
+    // instead of handing out the wrong encoding.
+    entries: LruCache<u64, ([u64; 256], EncodedArray)>,
+    stats: CacheStats,
+}
+
+thread_local! {
+    static ENCODE_CACHE: RefCell<Option<EncodeCache>> = const { RefCell::new(None) };
+}
+
+/// Returns the encoded array for `freq_buffer`, building and caching it on a miss.
+///
+/// # Arguments
+///
+/// * `freq_buffer` - Frequencies to build the encoding from.
+/// * `cache_size` - Maximum number of encodings kept by the calling thread, the cache is resized
+///   if it differs from the previous call. A size of 0 is treated as 1.
+///
+/// # Returns
+///
+/// * `EncodedArray` - The encoding indexed by symbol, shared with the cache.
+pub fn cached_encode_array(freq_buffer: &FrequencyBuffer, cache_size: usize) -> EncodedArray {
+    let capacity = NonZeroUsize::new(cache_size).unwrap_or(NonZeroUsize::MIN);
+    let fingerprint = freq_buffer.fingerprint();
+
+    ENCODE_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        let cache = cache.get_or_insert_with(|| EncodeCache {
+            entries: LruCache::new(capacity),
+            stats: CacheStats::default(),
+        });
+        if cache.entries.cap() != capacity {
+            cache.entries.resize(capacity);
+        }
+
+        if let Some((counts, encoded_array)) = cache.entries.get(&fingerprint) {
+            if *counts == freq_buffer.0 {
+                cache.stats.hits += 1;
+                return Rc::clone(encoded_array);
+            }
+        }
+
+        cache.stats.misses += 1;
+        let huffman_array = build_huffman_array(freq_buffer);
+        let encoded_array = Rc::new(build_encoded_array(encode_huffman_array(&huffman_array)));
+        cache
+            .entries
+            .put(fingerprint, (freq_buffer.0, Rc::clone(&encoded_array)));
+        encoded_array
+    })
+}
+
+/// Returns the hit and miss counters of the calling thread's cache.
+pub fn cache_stats() -> CacheStats {
+    ENCODE_CACHE.with(|cache| {
+        cache
+            .borrow()
+            .as_ref()
+            .map(|cache| cache.stats)
+            .unwrap_or_default()
+    })
+}
+
+/// Empties the calling thread's cache and resets its counters.
+pub fn clear_cache() {
+    ENCODE_CACHE.with(|cache| *cache.borrow_mut() = None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tally_frequency;
+
+    #[test]
+    fn counts_hits_and_misses() {
+        clear_cache();
+        let abc = tally_frequency(b"aabbbc");
+        let xyz = tally_frequency(b"xyz");
+
+        let first = cached_encode_array(&abc, 4);
+        let second = cached_encode_array(&abc, 4);
+        cached_encode_array(&xyz, 4);
+
+        assert!(Rc::ptr_eq(&first, &second));
+        assert_eq!(cache_stats(), CacheStats { hits: 1, misses: 2 });
+        assert!((cache_stats().hit_rate() - 1.0 / 3.0).abs() < 1e-9);
+
+        clear_cache();
+        assert_eq!(cache_stats(), CacheStats::default());
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        clear_cache();
+        let a = tally_frequency(b"a");
+        let b = tally_frequency(b"b");
+        let c = tally_frequency(b"c");
+
+        cached_encode_array(&a, 2);
+        cached_encode_array(&b, 2);
+        cached_encode_array(&a, 2);
+        // b is the least recently used and is evicted
+        cached_encode_array(&c, 2);
+        cached_encode_array(&a, 2);
+        cached_encode_array(&b, 2);
+
+        assert_eq!(cache_stats(), CacheStats { hits: 2, misses: 4 });
+    }
+
+    #[test]
+    fn resizes_to_the_requested_capacity() {
+        clear_cache();
+        let a = tally_frequency(b"a");
+        let b = tally_frequency(b"b");
+
+        cached_encode_array(&a, 2);
+        cached_encode_array(&b, 2);
+        // Shrinking keeps only the most recent entry, b
+        cached_encode_array(&b, 1);
+        cached_encode_array(&a, 1);
+
+        assert_eq!(cache_stats(), CacheStats { hits: 1, misses: 3 });
+        ENCODE_CACHE.with(|cache| {
+            assert_eq!(cache.borrow().as_ref().unwrap().entries.cap().get(), 1);
+        });
+    }
+
+    #[test]
+    fn fingerprint_collision_is_a_miss() {
+        clear_cache();
+        let abc = tally_frequency(b"abc");
+        let other = tally_frequency(b"xxy");
+        let stale = cached_encode_array(&other, 4);
+
+        // Store the encoding of other under the fingerprint of abc, as a collision would
+        ENCODE_CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            cache
+                .as_mut()
+                .unwrap()
+                .entries
+                .put(abc.fingerprint(), (other.0, Rc::clone(&stale)));
+        });
+
+        let encoded_array = cached_encode_array(&abc, 4);
+
+        assert!(!Rc::ptr_eq(&encoded_array, &stale));
+        assert!(encoded_array[b'a' as usize].is_some());
+        assert!(encoded_array[b'x' as usize].is_none());
+        assert_eq!(cache_stats(), CacheStats { hits: 0, misses: 2 });
+    }
+}
@@ -20,6 +20,16 @@
 //!   cat input.txt | huffc --compress -o output.huff
 //!   ```
 //!
+//! - To print the metadata of a compressed file:
+//!   ```sh
+//!   huffc --info -i output.huff
+//!   ```
+//!
+//! - To check that a file survives a compression round trip:
+//!   ```sh
+//!   huffc --test -i input.txt
+//!   ```
+//!
 //! ## Error Handling
 //!
 //! The tool provides detailed error messages when invalid arguments are provided.
@@ -30,11 +40,21 @@
 //! - Enforces correct file extensions during decompression
 //! - Uses `clap` for command-line argument parsing
 //!
-use std::{error::Error, fmt::Display, path::PathBuf};
+use std::{
+    ffi::{OsStr, OsString},
+    fmt,
+    path::{Path, PathBuf},
+};
 
 use atty::Stream;
 use clap::Parser;
 
+pub use crate::error::HuffErr;
+use crate::{
+    huff_payload_offset, parse_code_header, parse_last_bit_header, validate_huff_bytes,
+    FORMAT_HUFFMAN, FORMAT_STORED, HUFF_FORMAT_VERSION,
+};
+
 /// Enum representing the mode of operation for the Huffman compression tool.
 #[derive(Debug)]
 pub enum Mode {
@@ -54,12 +74,55 @@ pub struct Args {
     /// Flag to enable decompression mode.
     #[arg(short, long)]
     pub decompress: bool,
+    /// Print the metadata of a `.huff` file without decompressing it.
+    #[arg(long)]
+    pub info: bool,
+    /// Compress and decompress the input in memory and check that the result equals the input.
+    #[arg(long)]
+    pub test: bool,
+    /// Overwrite the output file if it already exists.
+    #[arg(short, long)]
+    pub force: bool,
+    /// Print the `--info` metadata as JSON.
+    #[arg(long, requires = "info")]
+    pub json: bool,
     /// Optional input file path.
     #[arg(short, long, value_name = "INPUT", required = false)]
     pub input: Option<PathBuf>,
     /// Optional output file path.
     #[arg(short, long)]
     pub out_file: Option<PathBuf>,
+    /// Read the input from stdin, even if it is a terminal.
+    #[arg(long)]
+    pub stdin: bool,
+    /// Write the output to stdout instead of a file.
+    #[arg(long)]
+    pub stdout: bool,
+    /// Extension appended when compressing and stripped when decompressing, `.huff` by default.
+    #[arg(long, value_name = "EXT")]
+    pub output_extension: Option<OsString>,
+    /// Compression level from 0 (store only) to 9 (strongest).
+    #[arg(short, long, value_parser = clap::value_parser!(u8).range(0..=9))]
+    pub level: Option<u8>,
+    /// Split the compressed output into standalone parts of at most N bytes.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    pub split_size: Option<u64>,
+    /// Print details about the operation.
+    #[arg(short, long)]
+    pub verbose: bool,
+    /// Write the byte frequencies of the input to a CSV file, or TSV if it ends in `.tsv`.
+    #[arg(long, value_name = "FILE")]
+    pub dump_frequencies: Option<PathBuf>,
+    /// Print the time spent in each stage of the pipeline.
+    #[arg(long)]
+    pub profile: bool,
+    /// Print the entropy, the expected code length and the compression ratio.
+    #[arg(long)]
+    pub stats: bool,
+    /// Optional JSON frequency table used instead of the frequencies of the input.
+    #[cfg(feature = "serde")]
+    #[arg(long, value_name = "FILE")]
+    pub frequency_file: Option<PathBuf>,
 }
 
 /// Validates the command-line arguments and determines the operation mode.
@@ -73,18 +136,68 @@ pub struct Args {
 /// * `Ok(Mode)` - If the arguments are valid, returns the corresponding mode.
 /// * `Err(HuffErr)` - If invalid arguments are provided, returns an error.
 pub fn validate_inputs(args: &Args) -> Result<Mode, HuffErr> {
-    // Ensure that either --compress or --decompress is specified, but not both.
-    if !(args.compress ^ args.decompress) {
+    // Ensure that exactly one of --compress, --decompress, --info or --test is specified.
+    if [args.compress, args.decompress, args.info, args.test]
+        .iter()
+        .filter(|flag| **flag)
+        .count()
+        != 1
+    {
         return Err(HuffErr::CompressionFlag);
     }
 
+    if args.split_size.is_some() {
+        if args.decompress {
+            return Err(HuffErr::IncompatibleFlags("--split-size", "--decompress"));
+        }
+        if args.level.is_some() {
+            return Err(HuffErr::IncompatibleFlags("--split-size", "--level"));
+        }
+        if args.stdout {
+            return Err(HuffErr::IncompatibleFlags("--split-size", "--stdout"));
+        }
+        #[cfg(feature = "serde")]
+        if args.frequency_file.is_some() {
+            return Err(HuffErr::IncompatibleFlags(
+                "--split-size",
+                "--frequency-file",
+            ));
+        }
+    }
+
+    if let Some(ref extension) = args.output_extension {
+        let bytes = extension.as_encoded_bytes();
+        if bytes.len() < 2 || bytes[0] != b'.' {
+            return Err(HuffErr::InvalidOutputExtension(
+                extension.to_string_lossy().into_owned(),
+            ));
+        }
+    }
+
+    if args.dump_frequencies.is_some() && args.decompress {
+        return Err(HuffErr::IncompatibleFlags(
+            "--dump-frequencies",
+            "--decompress",
+        ));
+    }
+
+    if args.stdout && args.out_file.is_some() {
+        return Err(HuffErr::IncompatibleFlags("--stdout", "--out-file"));
+    }
+
+    #[cfg(feature = "serde")]
+    if args.frequency_file.is_some() && args.level.is_some() {
+        return Err(HuffErr::IncompatibleFlags("--frequency-file", "--level"));
+    }
+
     // Check if input is coming from stdin.
-    if !atty::is(Stream::Stdin) {
-        // If reading from stdin, an output file must be specified.
-        if args.out_file.is_none() {
+    if args.stdin || !atty::is(Stream::Stdin) {
+        // If reading from stdin, an output file or stdout must be specified, --info and --test
+        // print to stdout anyway.
+        if args.out_file.is_none() && !args.stdout && !args.info && !args.test {
             return Err(HuffErr::NoOutfileProvided);
         }
-        return Ok(Mode::Stdin);
+        return check_output_file(args, Mode::Stdin);
     }
 
     // If no input file is specified, return an error.
@@ -95,68 +208,311 @@ pub fn validate_inputs(args: &Args) -> Result<Mode, HuffErr> {
     if let Some(ref path) = args.input {
         // Check if the specified input file exists.
         if !path.exists() {
-            return Err(HuffErr::FileDoesNotExist);
+            return Err(HuffErr::FileDoesNotExist(
+                path.to_string_lossy().into_owned(),
+            ));
         }
 
         // If decompression mode is selected, ensure the file has the correct extension.
-        if args.decompress && path.extension().unwrap() != "huff" {
-            return Err(HuffErr::WrongFileExtension);
+        if args.decompress && !has_output_extension(path, output_extension(args)) {
+            return Err(HuffErr::WrongFileExtension(
+                path.to_string_lossy().into_owned(),
+            ));
         }
     }
 
-    Ok(Mode::FileIO)
+    check_output_file(args, Mode::FileIO)
 }
 
-/// Custom error type for handling argument validation errors.
-#[derive(Debug)]
-pub enum HuffErr {
-    /// No arguments were provided.
-    NoArgs,
-    /// No file path was provided.
-    NoFilePath,
-    /// The specified file does not exist.
-    FileDoesNotExist,
-    /// The provided file has an incorrect extension.
-    WrongFileExtension,
-    /// No output file was provided for stdin input.
-    NoOutfileProvided,
-    /// No valid arguments were provided.
-    NoValidArgs,
-    /// Both compression and decompression flags were set.
-    CompressionFlag,
+/// Returns `mode` unless the output file exists and `--force` was not given. With
+/// `--split-size` the first part is checked.
+fn check_output_file(args: &Args, mode: Mode) -> Result<Mode, HuffErr> {
+    if args.force || args.stdout || args.info || args.test {
+        return Ok(mode);
+    }
+
+    let output_path = if args.compress {
+        let mut path = compressed_file_path(args, &mode);
+        if args.split_size.is_some() {
+            path.push(".001");
+        }
+        PathBuf::from(path)
+    } else {
+        decompressed_file_path(args, &mode)
+    };
+    if output_path.exists() {
+        return Err(HuffErr::OutputFileExists(
+            output_path.to_string_lossy().into_owned(),
+        ));
+    }
+    Ok(mode)
 }
 
-/// Implement the `Display` trait to provide user-friendly error messages.
-impl Display for HuffErr {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            HuffErr::NoArgs => write!(f, "No arguments provided. Use --help for more information."),
-            HuffErr::FileDoesNotExist => write!(
-                f,
-                "File does not exist in path provided. Use --help for more information."
-            ),
-            HuffErr::WrongFileExtension => write!(
-                f,
-                "File does not have the file extension '.huff'. Use --help for more information."
-            ),
-            HuffErr::CompressionFlag => write!(
-                f,
-                "Error: You must specify either --compress or --decompress, but not both.",
-            ),
-            HuffErr::NoFilePath => {
-                write!(f, "No file path provided. Use --help for more information.")
+/// Extension given with `--output-extension`, `.huff` if there is none.
+pub fn output_extension(args: &Args) -> &OsStr {
+    args.output_extension
+        .as_deref()
+        .unwrap_or(OsStr::new(".huff"))
+}
+
+/// Whether the file name of `path` ends in `extension`, which includes its leading `.`, after at
+/// least one other byte. Compared as bytes so non-UTF-8 paths work.
+pub fn has_output_extension(path: &Path, extension: &OsStr) -> bool {
+    path.file_name().is_some_and(|name| {
+        let (name, extension) = (name.as_encoded_bytes(), extension.as_encoded_bytes());
+        name.len() > extension.len() && name.ends_with(extension)
+    })
+}
+
+/// Output file given with `--out-file`, or the input file when reading from a file.
+pub fn base_file_path<'a>(args: &'a Args, mode: &Mode) -> &'a PathBuf {
+    match mode {
+        Mode::Stdin => args.out_file.as_ref().unwrap(),
+        Mode::FileIO => {
+            if let Some(ref out_file) = args.out_file {
+                out_file
+            } else {
+                args.input.as_ref().unwrap()
             }
-            HuffErr::NoValidArgs => write!(
-                f,
-                "No valid arguments provided. Use --help for more information."
-            ),
-            HuffErr::NoOutfileProvided => write!(
-                f,
-                "No outfile path provided. Use --help for more information."
-            ),
         }
     }
 }
 
-/// Implement the `Error` trait for `HuffErr` to allow integration with Rust's error handling.
-impl Error for HuffErr {}
+/// Output file of `--compress`, the base file path with the output extension appended.
+pub fn compressed_file_path(args: &Args, mode: &Mode) -> OsString {
+    let mut write_file_path: OsString = base_file_path(args, mode).into();
+    write_file_path.push(output_extension(args));
+    write_file_path
+}
+
+/// Output file of `--decompress`, the base file path without its output extension.
+pub fn decompressed_file_path(args: &Args, mode: &Mode) -> PathBuf {
+    let mut base_file_clone = base_file_path(args, mode).clone();
+
+    let extension = output_extension(args);
+    if has_output_extension(&base_file_clone, extension) {
+        // An extension like `.tar.huf` spans one `Path` extension per `.`
+        let num_dots = extension
+            .as_encoded_bytes()
+            .iter()
+            .filter(|byte| **byte == b'.')
+            .count();
+        for _ in 0..num_dots {
+            base_file_clone.set_extension("");
+        }
+    }
+    base_file_clone
+}
+
+/// Metadata of a `.huff` buffer printed by `--info`, read from the headers without decoding the
+/// bits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HuffInfo {
+    pub format_version: u8,
+    pub symbol_count: usize,
+    pub total_bits: u64,
+    /// Bytes in front of the payload, the magic bytes, the version, the checksum, the format
+    /// byte, the sizes and the code header.
+    pub header_bytes: usize,
+    pub compressed_bytes: usize,
+    /// Exact for a stored buffer, for a Huffman buffer `total_bits` over the expected code length
+    /// if a symbol with an `n` bit code makes up `2^-n` of the input, as it would for an optimal
+    /// code. 0 for a compression level or block buffer, whose size is only known once decoded.
+    pub estimated_uncompressed_bytes: u64,
+}
+
+impl HuffInfo {
+    /// Reads the metadata of `bytes`, see `validate_huff_bytes`.
+    ///
+    /// # Errors
+    ///
+    /// See `validate_huff_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, HuffErr> {
+        let header = validate_huff_bytes(bytes)?;
+        let header_bytes = bytes.len() - header.compressed_payload_bytes;
+
+        let estimated_uncompressed_bytes = if header.format == FORMAT_STORED {
+            header.compressed_payload_bytes as u64
+        } else if header.format != FORMAT_HUFFMAN {
+            0
+        } else {
+            // Format byte, total bits and header size
+            let code_header_start = huff_payload_offset(header.format_version) + 17;
+            let code_header = &bytes[code_header_start..header_bytes];
+            let encoded_map = if header.format_version == HUFF_FORMAT_VERSION {
+                parse_code_header(code_header)?
+            } else {
+                parse_last_bit_header(code_header)?
+            };
+            let (weighted_bits, weight) =
+                encoded_map
+                    .values()
+                    .fold((0.0, 0.0), |(weighted_bits, weight), encoded| {
+                        let p = 0.5f64.powi(encoded.bits.len() as i32);
+                        (weighted_bits + p * encoded.bits.len() as f64, weight + p)
+                    });
+            (header.total_bits as f64 / (weighted_bits / weight)).round() as u64
+        };
+
+        Ok(Self {
+            format_version: header.format_version,
+            symbol_count: header.symbol_count,
+            total_bits: header.total_bits,
+            header_bytes,
+            compressed_bytes: bytes.len(),
+            estimated_uncompressed_bytes,
+        })
+    }
+
+    /// `compressed_bytes` over `estimated_uncompressed_bytes`, 0.0 if the estimate is zero.
+    pub fn compression_ratio(&self) -> f64 {
+        if self.estimated_uncompressed_bytes == 0 {
+            return 0.0;
+        }
+        self.compressed_bytes as f64 / self.estimated_uncompressed_bytes as f64
+    }
+
+    /// Formats the metadata as a single line JSON object.
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"format_version\":{},\"symbol_count\":{},\"total_bits\":{},\"header_bytes\":{},\"compressed_bytes\":{},\"estimated_uncompressed_bytes\":{},\"estimated_compression_ratio\":{:.4}}}",
+            self.format_version,
+            self.symbol_count,
+            self.total_bits,
+            self.header_bytes,
+            self.compressed_bytes,
+            self.estimated_uncompressed_bytes,
+            self.compression_ratio()
+        )
+    }
+}
+
+impl fmt::Display for HuffInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "format version: {}", self.format_version)?;
+        writeln!(f, "symbols: {}", self.symbol_count)?;
+        writeln!(f, "total bits: {}", self.total_bits)?;
+        writeln!(f, "header size: {} bytes", self.header_bytes)?;
+        writeln!(
+            f,
+            "estimated compression ratio: {:.4} ({} -> {} bytes)",
+            self.compression_ratio(),
+            self.estimated_uncompressed_bytes,
+            self.compressed_bytes
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_extension_detection() {
+        let huff = OsStr::new(".huff");
+        assert!(has_output_extension(
+            Path::new(r"C:\Users\test\file.huff"),
+            huff
+        ));
+        assert!(!has_output_extension(
+            Path::new(r"C:\Users\test\file.txt"),
+            huff
+        ));
+        assert!(!has_output_extension(
+            Path::new(r"C:\Users\test.huff\file"),
+            huff
+        ));
+        assert!(has_output_extension(
+            Path::new("/home/test/file.huff"),
+            huff
+        ));
+        assert!(!has_output_extension(Path::new("/home/test/file"), huff));
+        assert!(!has_output_extension(Path::new("/home/test/.huff"), huff));
+
+        let extension = OsStr::new(".tar.huf");
+        assert!(has_output_extension(
+            Path::new("/home/test/file.tar.huf"),
+            extension
+        ));
+        assert!(!has_output_extension(
+            Path::new("/home/test/file.huf"),
+            extension
+        ));
+        assert!(!has_output_extension(
+            Path::new("/home/test/.tar.huf"),
+            extension
+        ));
+        assert!(has_output_extension(
+            Path::new("/home/test/file.huff"),
+            OsStr::new(".huff")
+        ));
+    }
+
+    #[test]
+    fn path_errors_name_the_path() {
+        let err = HuffErr::WrongFileExtension(
+            Path::new(r"C:\Users\test\file.txt")
+                .to_string_lossy()
+                .into_owned(),
+        );
+        assert!(err.to_string().contains(r"C:\Users\test\file.txt"));
+    }
+
+    #[test]
+    fn existing_output_file_requires_force() {
+        let dir = std::env::temp_dir().join(format!("huffc-force-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let out_file = dir.join("data.txt");
+        std::fs::write(dir.join("data.txt.huff"), b"dummy").unwrap();
+
+        let args = |extra: &[&str]| {
+            let mut argv = vec!["huffc", "--stdin", "-o", out_file.to_str().unwrap()];
+            argv.extend_from_slice(extra);
+            Args::parse_from(argv)
+        };
+        let compress = validate_inputs(&args(&["--compress"]));
+        let forced = validate_inputs(&args(&["--compress", "-f"]));
+        let decompress = validate_inputs(&args(&["--decompress"]));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        match compress {
+            Err(HuffErr::OutputFileExists(path)) => assert!(path.ends_with("data.txt.huff")),
+            other => panic!("expected OutputFileExists, got {:?}", other),
+        }
+        assert!(matches!(forced, Ok(Mode::Stdin)));
+        // Decompressing writes to `data.txt` itself, which does not exist
+        assert!(matches!(decompress, Ok(Mode::Stdin)));
+    }
+
+    #[test]
+    fn huff_info_of_stored_and_huffman_buffers() {
+        let stored = crate::compress(b"xyz").unwrap();
+        let info = HuffInfo::from_bytes(&stored).unwrap();
+        assert_eq!((info.symbol_count, info.total_bits), (0, 24));
+        assert_eq!(info.estimated_uncompressed_bytes, 3);
+        assert_eq!(info.header_bytes + 3, info.compressed_bytes);
+
+        // Codes of 1, 2, 3, 4 and 4 bits are optimal for these frequencies, so the estimate is
+        // exact
+        let compressed = crate::compress(&b"aaaaaaaabbbbccde".repeat(8)).unwrap();
+        let info = HuffInfo::from_bytes(&compressed).unwrap();
+        assert_eq!((info.symbol_count, info.total_bits), (5, 8 * 30));
+        assert_eq!(info.estimated_uncompressed_bytes, 128);
+        assert!(info.to_json().contains("\"symbol_count\":5"));
+
+        // The size of a level or block buffer is unknown until it is decoded
+        let blocks = crate::parallel::compress_parallel(&b"abracadabra".repeat(8), 2);
+        let info = HuffInfo::from_bytes(&blocks).unwrap();
+        assert_eq!(info.symbol_count, 0);
+        assert_eq!(info.estimated_uncompressed_bytes, 0);
+        assert_eq!(
+            info.header_bytes + info.total_bits as usize / 8,
+            info.compressed_bytes
+        );
+
+        assert!(matches!(
+            HuffInfo::from_bytes(b"not huff"),
+            Err(HuffErr::NotAHuffFile)
+        ));
+    }
+}
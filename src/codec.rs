@@ -0,0 +1,602 @@
+//! Huffc Codec - Shared Codebooks and a Named Codec Registry
+//!
+//! A `Codebook` is an encoding built once from a representative frequency table and shared by
+//! the compressor and decompressor, so the compressed data only carries the number of bits and
+//! the bit buffer, not the symbol header. `HuffmanCodecRegistry` maps names such as
+//! `"http_headers"` to codebooks so a long-running service can pick the codebook per data type.
+//! The registry is cheap to clone and can be shared between threads, clones share the same
+//! codebooks.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use huffc::{
+//!     codec::{Codebook, HuffmanCodecRegistry},
+//!     tally_frequency,
+//! };
+//!
+//! let registry = HuffmanCodecRegistry::new();
+//! let codebook = Codebook::from_frequencies(&tally_frequency(b"GET /index.html HTTP/1.1")).unwrap();
+//! registry.register("http_headers", codebook);
+//!
+//! let compressed = registry.compress("http_headers", b"GET / HTTP/1.1").unwrap();
+//! let decompressed = registry.decompress("http_headers", &compressed).unwrap();
+//! assert_eq!(decompressed, b"GET / HTTP/1.1");
+//! ```
+//!
+//! `HuffmanCodec` compresses with a codebook passed explicitly instead of looked up by name.
+//!
+use std::{
+    collections::HashMap,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use bitvec::{order::Msb0, vec::BitVec};
+
+use crate::{
+    build_huffman_array, decoder::HuffmanDecoder, encode_huffman_array, u64_to_u8, u8_to_u64,
+    Encoded, FrequencyBuffer, HuffErr,
+};
+
+/// Encoding shared out of band between the compressor and the decompressor.
+#[derive(Debug)]
+pub struct Codebook {
+    /// Frequency table the codebook was built from
+    frequencies: FrequencyBuffer,
+    encoded_map: HashMap<u8, Encoded>,
+    decoder: HuffmanDecoder,
+}
+
+impl Codebook {
+    /// Builds the codebook from a frequency table, only symbols with a non-zero count can be
+    /// compressed with it. Use `FrequencyBuffer::apply_laplace_smoothing` first to cover every
+    /// byte value.
+    ///
+    /// # Errors
+    ///
+    /// Returns `HuffErr::NullInput` if every count is zero.
+    pub fn from_frequencies(freq_buffer: &FrequencyBuffer) -> Result<Self, HuffErr> {
+        let encoded_map = encode_huffman_array(&build_huffman_array(freq_buffer));
+        if encoded_map.is_empty() {
+            return Err(HuffErr::NullInput("Codebook::from_frequencies"));
+        }
+        let decoder = HuffmanDecoder::new(&encoded_map)?;
+        Ok(Self {
+            frequencies: FrequencyBuffer(freq_buffer.0),
+            encoded_map,
+            decoder,
+        })
+    }
+
+    /// Builds a codebook from the symbols present in both `a` and `b`, e.g. to adapt a codebook
+    /// of a source domain to a target domain. The count of each shared symbol is the average of
+    /// its counts in `a` and `b`, symbols present in only one codebook are dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns `HuffErr::NullInput` if the codebooks have no symbol in common.
+    pub fn intersect(a: &Codebook, b: &Codebook) -> Result<Codebook, HuffErr> {
+        let mut shared = FrequencyBuffer([0; 256]);
+        for ((count, a), b) in shared
+            .0
+            .iter_mut()
+            .zip(a.frequencies.0)
+            .zip(b.frequencies.0)
+        {
+            if a != 0 && b != 0 {
+                // Average without overflowing, never rounds down to zero
+                *count = a / 2 + b / 2 + (a % 2 + b % 2) / 2;
+            }
+        }
+        Codebook::from_frequencies(&shared)
+    }
+
+    /// Merges two codebooks into a two-level code. `primary` handles the frequent symbols, which
+    /// keep their codes, so no primary symbol gets a longer code than it had. The code of one
+    /// primary symbol is given up to serve as the escape: the longest primary code of at most
+    /// `escape_length` bits, the least frequent symbol on ties. Every symbol of `secondary` not
+    /// in `primary`, and the symbol whose code became the escape, is encoded as the escape
+    /// followed by its `secondary` code.
+    ///
+    /// # Errors
+    ///
+    /// * `HuffErr::DepthConstraintImpossible` - `primary` has no code of at most
+    ///   `escape_length` bits, or an escaped code is longer than 255 bits.
+    /// * `HuffErr::SymbolNotInCodebook` - The symbol whose code became the escape is not in
+    ///   `secondary`.
+    pub fn merge(
+        primary: Codebook,
+        secondary: Codebook,
+        escape_length: u8,
+    ) -> Result<Codebook, HuffErr> {
+        let num_symbols = primary.encoded_map.len() + secondary.encoded_map.len();
+        let too_long = || HuffErr::DepthConstraintImpossible {
+            max_depth: escape_length,
+            num_symbols,
+        };
+        let escaped_symbol = primary
+            .encoded_map
+            .values()
+            .filter(|encoded| encoded.bits.len() <= escape_length as usize)
+            .max_by_key(|encoded| {
+                (
+                    encoded.bits.len(),
+                    std::cmp::Reverse(primary.frequencies[encoded.value]),
+                    encoded.value,
+                )
+            })
+            .map(|encoded| encoded.value)
+            .ok_or_else(too_long)?;
+        if !secondary.encoded_map.contains_key(&escaped_symbol) {
+            return Err(HuffErr::SymbolNotInCodebook(escaped_symbol));
+        }
+
+        let mut frequencies = FrequencyBuffer([0; 256]);
+        let mut encoded_map = primary.encoded_map;
+        let escape = encoded_map.remove(&escaped_symbol).unwrap().bits;
+        for symbol in encoded_map.keys() {
+            frequencies[*symbol] = primary.frequencies[*symbol];
+        }
+        for (symbol, encoded) in secondary.encoded_map {
+            if encoded_map.contains_key(&symbol) {
+                continue;
+            }
+            let mut bits = escape.clone();
+            bits.extend_from_bitslice(&encoded.bits);
+            let num_bits_sequence = u16::try_from(bits.len()).map_err(|_| too_long())?;
+            frequencies[symbol] = secondary.frequencies[symbol];
+            encoded_map.insert(
+                symbol,
+                Encoded {
+                    bits,
+                    num_bits_sequence,
+                    value: symbol,
+                },
+            );
+        }
+
+        let decoder = HuffmanDecoder::new(&encoded_map)?;
+        Ok(Self {
+            frequencies,
+            encoded_map,
+            decoder,
+        })
+    }
+
+    /// Frequency table the codebook was built from.
+    pub fn frequencies(&self) -> &FrequencyBuffer {
+        &self.frequencies
+    }
+
+    /// Compresses `data` to the number of bits (8 bytes) followed by the bit buffer.
+    ///
+    /// # Errors
+    ///
+    /// Returns `HuffErr::SymbolNotInCodebook` if `data` contains a byte the codebook has no code
+    /// for.
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>, HuffErr> {
+        let mut bits: BitVec<u8, Msb0> = BitVec::with_capacity(data.len() * 8);
+        for byte in data {
+            let encoded = self
+                .encoded_map
+                .get(byte)
+                .ok_or(HuffErr::SymbolNotInCodebook(*byte))?;
+            bits.extend_from_bitslice(&encoded.bits);
+        }
+
+        let mut compressed = u64_to_u8(bits.len() as u64).to_vec();
+        compressed.extend(bits.into_vec());
+        Ok(compressed)
+    }
+
+    /// Decompresses a buffer produced by `compress` with the same codebook.
+    ///
+    /// # Errors
+    ///
+    /// * `HuffErr::MalformedData` - The buffer is truncated.
+    /// * `HuffErr::CorruptedBitstream` - The bit buffer contains a code of another codebook.
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, HuffErr> {
+        let mut decoder = self.decoder_for(data)?;
+        let mut decompressed = Vec::new();
+        while let Some(byte) = decoder.try_decode_symbol()? {
+            decompressed.push(byte);
+        }
+        if !decoder.is_finished() {
+            return Err(HuffErr::MalformedData(
+                "bit buffer ends inside a code".to_string(),
+            ));
+        }
+        Ok(decompressed)
+    }
+
+    /// Decoder fed with the bit buffer of a buffer produced by `compress`.
+    fn decoder_for(&self, data: &[u8]) -> Result<HuffmanDecoder, HuffErr> {
+        if data.len() < 8 {
+            return Err(HuffErr::MalformedData("missing bit count".to_string()));
+        }
+        let total_bits = u8_to_u64(&data[..8]);
+        if total_bits.div_ceil(8) > (data.len() - 8) as u64 {
+            return Err(HuffErr::MalformedData("truncated bit buffer".to_string()));
+        }
+
+        let mut decoder = self.decoder.clone().with_total_bits(total_bits);
+        decoder.feed(&data[8..]);
+        Ok(decoder)
+    }
+}
+
+/// Compression with a codebook passed explicitly, e.g. one codebook per database column. The
+/// output has no symbol header, only the number of bits (8 bytes) and the bit buffer.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HuffmanCodec;
+
+impl HuffmanCodec {
+    /// Encodes `data` with `codebook`, skipping the frequency analysis of `data`.
+    ///
+    /// # Errors
+    ///
+    /// See `Codebook::compress`.
+    pub fn compress_with_codebook(data: &[u8], codebook: &Codebook) -> Result<Vec<u8>, HuffErr> {
+        codebook.compress(data)
+    }
+
+    /// Decodes a buffer produced by `compress_with_codebook` with the same codebook.
+    ///
+    /// # Errors
+    ///
+    /// See `Codebook::decompress`.
+    pub fn decompress_with_codebook(data: &[u8], codebook: &Codebook) -> Result<Vec<u8>, HuffErr> {
+        codebook.decompress(data)
+    }
+
+    /// Re-encodes a buffer compressed with `input_codebook` to one compressed with
+    /// `output_codebook`. Each symbol is encoded as soon as it is decoded, the decompressed data
+    /// is never held in memory.
+    ///
+    /// # Errors
+    ///
+    /// * `HuffErr::MalformedData` / `HuffErr::CorruptedBitstream` - See `Codebook::decompress`.
+    /// * `HuffErr::SymbolNotInCodebook` - A decoded byte has no code in `output_codebook`.
+    pub fn transcode(
+        input_codebook: &Codebook,
+        output_codebook: &Codebook,
+        data: &[u8],
+    ) -> Result<Vec<u8>, HuffErr> {
+        let mut decoder = input_codebook.decoder_for(data)?;
+        let mut bits: BitVec<u8, Msb0> = BitVec::with_capacity(data.len() * 8);
+        while let Some(byte) = decoder.try_decode_symbol()? {
+            let encoded = output_codebook
+                .encoded_map
+                .get(&byte)
+                .ok_or(HuffErr::SymbolNotInCodebook(byte))?;
+            bits.extend_from_bitslice(&encoded.bits);
+        }
+        if !decoder.is_finished() {
+            return Err(HuffErr::MalformedData(
+                "bit buffer ends inside a code".to_string(),
+            ));
+        }
+
+        let mut transcoded = u64_to_u8(bits.len() as u64).to_vec();
+        transcoded.extend(bits.into_vec());
+        Ok(transcoded)
+    }
+
+    /// Compresses and decompresses `data` with `codebook` and checks that the result equals
+    /// `data`, e.g. on a representative sample before compressing real data with the codebook.
+    ///
+    /// # Errors
+    ///
+    /// * `HuffErr::RoundTripMismatch` - The decompressed bytes differ from `data`.
+    /// * Any error of `Codebook::compress` or `Codebook::decompress`, e.g.
+    ///   `HuffErr::SymbolNotInCodebook`.
+    pub fn verify_round_trip(codebook: &Codebook, data: &[u8]) -> Result<(), HuffErr> {
+        let decompressed = codebook.decompress(&codebook.compress(data)?)?;
+        match first_difference(data, &decompressed) {
+            Some(first_diff_offset) => Err(HuffErr::RoundTripMismatch { first_diff_offset }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Offset of the first byte that differs, or the length of the shorter slice if one is a prefix
+/// of the other. `None` if both are equal.
+fn first_difference(a: &[u8], b: &[u8]) -> Option<usize> {
+    a.iter()
+        .zip(b)
+        .position(|(a, b)| a != b)
+        .or_else(|| (a.len() != b.len()).then(|| a.len().min(b.len())))
+}
+
+/// Codebooks by name, shared between clones of the registry and across threads.
+#[derive(Debug, Clone, Default)]
+pub struct HuffmanCodecRegistry {
+    codebooks: Arc<RwLock<HashMap<String, Codebook>>>,
+}
+
+impl HuffmanCodecRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `codebook` under `name`, returning the codebook it replaced.
+    pub fn register(&self, name: &str, codebook: Codebook) -> Option<Codebook> {
+        self.codebooks
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(name.to_string(), codebook)
+    }
+
+    /// Compresses `data` with the codebook registered under `name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `HuffErr::UnknownCodec` if no codebook is registered under `name`, otherwise see
+    /// `Codebook::compress`.
+    pub fn compress(&self, name: &str, data: &[u8]) -> Result<Vec<u8>, HuffErr> {
+        self.with_codebook(name, |codebook| codebook.compress(data))
+    }
+
+    /// Decompresses `data` with the codebook registered under `name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `HuffErr::UnknownCodec` if no codebook is registered under `name`, otherwise see
+    /// `Codebook::decompress`.
+    pub fn decompress(&self, name: &str, data: &[u8]) -> Result<Vec<u8>, HuffErr> {
+        self.with_codebook(name, |codebook| codebook.decompress(data))
+    }
+
+    fn with_codebook(
+        &self,
+        name: &str,
+        f: impl FnOnce(&Codebook) -> Result<Vec<u8>, HuffErr>,
+    ) -> Result<Vec<u8>, HuffErr> {
+        let codebooks = self
+            .codebooks
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let codebook = codebooks
+            .get(name)
+            .ok_or_else(|| HuffErr::UnknownCodec(name.to_string()))?;
+        f(codebook)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tally_frequency;
+
+    fn codebook(sample: &[u8]) -> Codebook {
+        Codebook::from_frequencies(&tally_frequency(sample)).unwrap()
+    }
+
+    #[test]
+    fn registry_dispatches_by_name() {
+        let registry = HuffmanCodecRegistry::new();
+        assert!(registry
+            .register("json_payload", codebook(br#"{"key": "value"}"#))
+            .is_none());
+        registry.register("http_headers", codebook(b"Host: example.com"));
+
+        let json = br#"{"value": "key"}"#;
+        let compressed = registry.compress("json_payload", json).unwrap();
+        assert_eq!(
+            registry.decompress("json_payload", &compressed).unwrap(),
+            json
+        );
+
+        let headers = b"Host: exam.com";
+        let compressed = registry.compress("http_headers", headers).unwrap();
+        assert_eq!(
+            registry.decompress("http_headers", &compressed).unwrap(),
+            headers
+        );
+
+        assert!(matches!(
+            registry.compress("binary_blob", b"data"),
+            Err(HuffErr::UnknownCodec(name)) if name == "binary_blob"
+        ));
+        assert!(registry
+            .register("http_headers", codebook(b"Accept: */*"))
+            .is_some());
+    }
+
+    #[test]
+    fn codebook_rejects_unknown_symbols_and_truncated_data() {
+        let codebook = codebook(b"abracadabra");
+        assert!(matches!(
+            codebook.compress(b"abz"),
+            Err(HuffErr::SymbolNotInCodebook(b'z'))
+        ));
+
+        let compressed = codebook.compress(b"cabbad").unwrap();
+        assert!(codebook.decompress(&compressed[..4]).is_err());
+        assert!(codebook
+            .decompress(&compressed[..compressed.len() - 1])
+            .is_err());
+        assert_eq!(codebook.decompress(&compressed).unwrap(), b"cabbad");
+
+        assert!(matches!(
+            Codebook::from_frequencies(&FrequencyBuffer([0; 256])),
+            Err(HuffErr::NullInput(_))
+        ));
+    }
+
+    #[test]
+    fn intersect_keeps_shared_symbols_with_averaged_counts() {
+        let mut source = FrequencyBuffer([0; 256]);
+        let mut target = FrequencyBuffer([0; 256]);
+        for (symbol, source_count, target_count) in [
+            (b'a', 100, 2),
+            (b'b', 10, 300),
+            (b'c', 50, 60),
+            (b'd', 1, 1),
+            (b'x', 500, 0),
+            (b'y', 0, 500),
+        ] {
+            source[symbol] = source_count;
+            target[symbol] = target_count;
+        }
+        let source = Codebook::from_frequencies(&source).unwrap();
+        let target = Codebook::from_frequencies(&target).unwrap();
+
+        let intersected = Codebook::intersect(&source, &target).unwrap();
+        assert_eq!(intersected.frequencies()[b'a'], 51);
+        assert_eq!(intersected.frequencies()[b'b'], 155);
+        assert_eq!(intersected.frequencies()[b'c'], 55);
+        assert_eq!(intersected.frequencies()[b'd'], 1);
+
+        // The codes are those of a codebook built from the averaged counts
+        let expected = Codebook::from_frequencies(intersected.frequencies()).unwrap();
+        assert_eq!(intersected.encoded_map, expected.encoded_map);
+        let len = |symbol: u8| intersected.encoded_map[&symbol].bits.len();
+        assert!(len(b'b') <= len(b'c') && len(b'c') <= len(b'a') && len(b'a') <= len(b'd'));
+
+        assert!(matches!(
+            intersected.compress(b"abx"),
+            Err(HuffErr::SymbolNotInCodebook(b'x'))
+        ));
+        let compressed = intersected.compress(b"abcdbb").unwrap();
+        assert_eq!(intersected.decompress(&compressed).unwrap(), b"abcdbb");
+
+        assert!(matches!(
+            Codebook::intersect(&codebook(b"ab"), &codebook(b"cd")),
+            Err(HuffErr::NullInput(_))
+        ));
+    }
+
+    #[test]
+    fn codec_output_has_no_symbol_header() {
+        let codebook = codebook(b"SELECT name FROM users WHERE id = 42");
+        let data = b"SELECT id FROM users";
+
+        let compressed = HuffmanCodec::compress_with_codebook(data, &codebook).unwrap();
+        let total_bits = u8_to_u64(&compressed[..8]);
+        assert_eq!(compressed.len() as u64, 8 + total_bits.div_ceil(8));
+        assert_eq!(
+            HuffmanCodec::decompress_with_codebook(&compressed, &codebook).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn transcode_matches_recompression() {
+        let old = codebook(b"aaaaaaaabbbbcc d");
+        let new = codebook(b"dddddddd ccccbba");
+        let data = b"abcd dcba aaaa";
+
+        let compressed = HuffmanCodec::compress_with_codebook(data, &old).unwrap();
+        let transcoded = HuffmanCodec::transcode(&old, &new, &compressed).unwrap();
+        assert_eq!(
+            transcoded,
+            HuffmanCodec::compress_with_codebook(data, &new).unwrap()
+        );
+        assert_eq!(
+            HuffmanCodec::decompress_with_codebook(&transcoded, &new).unwrap(),
+            data
+        );
+
+        let narrow = codebook(b"ab");
+        assert!(matches!(
+            HuffmanCodec::transcode(&old, &narrow, &compressed),
+            Err(HuffErr::SymbolNotInCodebook(b'c'))
+        ));
+        assert!(HuffmanCodec::transcode(&old, &new, &compressed[..4]).is_err());
+    }
+
+    #[test]
+    fn verify_round_trip_reports_errors() {
+        let codebook = codebook(b"aaaaaaaabbbbcc d");
+        assert!(HuffmanCodec::verify_round_trip(&codebook, b"abcd dcba").is_ok());
+        assert!(HuffmanCodec::verify_round_trip(&codebook, b"").is_ok());
+        assert!(matches!(
+            HuffmanCodec::verify_round_trip(&codebook, b"abcx"),
+            Err(HuffErr::SymbolNotInCodebook(b'x'))
+        ));
+
+        // Encoder and decoder disagree on `a` and `b` once their codes are swapped
+        let mut corrupted = codebook;
+        let a = corrupted.encoded_map.remove(&b'a').unwrap();
+        let b = corrupted.encoded_map.remove(&b'b').unwrap();
+        corrupted.encoded_map.insert(b'a', b);
+        corrupted.encoded_map.insert(b'b', a);
+        assert!(HuffmanCodec::verify_round_trip(&corrupted, b"cd dc").is_ok());
+        assert!(matches!(
+            HuffmanCodec::verify_round_trip(&corrupted, b"cd acb"),
+            Err(HuffErr::RoundTripMismatch {
+                first_diff_offset: 3
+            })
+        ));
+
+        assert_eq!(first_difference(b"abc", b"abc"), None);
+        assert_eq!(first_difference(b"abc", b"abd"), Some(2));
+        assert_eq!(first_difference(b"abc", b"ab"), Some(2));
+        assert_eq!(first_difference(b"", b"a"), Some(0));
+    }
+
+    #[test]
+    fn merge_escapes_secondary_symbols() {
+        let primary = codebook(b"eeeeeeeettttttaaaoi");
+        let secondary = codebook(b"abcdefghijklmnopqrstuvwxyz");
+        let codes = |codebook: &Codebook| -> HashMap<u8, BitVec<u8, Msb0>> {
+            codebook
+                .encoded_map
+                .iter()
+                .map(|(symbol, encoded)| (*symbol, encoded.bits.clone()))
+                .collect()
+        };
+        let primary_map = codes(&primary);
+        let secondary_map = codes(&secondary);
+
+        let merged = Codebook::merge(primary, secondary, 4).unwrap();
+
+        // 'i' and 'o' have the longest codes, the code of 'o' wins the tie and is the escape, the other primary symbols keep their codes
+        let escape = &primary_map[&b'o'];
+        assert_eq!(escape.len(), 4);
+        for symbol in b"etai" {
+            assert_eq!(merged.encoded_map[symbol].bits, primary_map[symbol]);
+        }
+        for symbol in b"obz" {
+            let bits = &merged.encoded_map[symbol].bits;
+            assert!(bits.starts_with(escape));
+            assert_eq!(&bits[escape.len()..], secondary_map[symbol]);
+        }
+        assert_eq!(merged.encoded_map.len(), 26);
+
+        let data = b"the quiet zebra";
+        assert!(merged.compress(data).is_err());
+        let data = b"thequietzebra";
+        let compressed = merged.compress(data).unwrap();
+        assert_eq!(merged.decompress(&compressed).unwrap(), data);
+
+        assert!(matches!(
+            Codebook::merge(codebook(b"eeeeeeeettttttaaaoi"), codebook(b"xyz"), 4),
+            Err(HuffErr::SymbolNotInCodebook(b'o'))
+        ));
+        assert!(matches!(
+            Codebook::merge(codebook(b"eeeeeeeettttttaaaoi"), codebook(b"xyz"), 0),
+            Err(HuffErr::DepthConstraintImpossible { .. })
+        ));
+    }
+
+    #[test]
+    fn registry_is_shared_between_threads() {
+        let registry = HuffmanCodecRegistry::new();
+        registry.register("text", codebook(b"the quick brown fox"));
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let registry = registry.clone();
+                std::thread::spawn(move || {
+                    let compressed = registry.compress("text", b"the fox").unwrap();
+                    registry.decompress("text", &compressed).unwrap()
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), b"the fox");
+        }
+    }
+}
@@ -0,0 +1,845 @@
+//! Huffc Decoder - Incremental Huffman Decoding
+//!
+//! `HuffmanDecoder` decodes a bit stream one symbol at a time as input arrives. A symbol is only
+//! consumed once all of its bits are available, so a decode attempt on incomplete input leaves
+//! the decoder unchanged and can be retried after feeding more bytes. Corruption is reported with
+//! the bit offset of the offending code, after which the caller can skip ahead to the next block
+//! boundary and resume. `HuffmanBitReader` reads the bits of a byte slice in place, without
+//! copying them to a `BitVec`. `HuffmanStateMachine` decodes the same codes one bit at a time from a
+//! precomputed transition table. `HuffmanReader` decompresses a `.huff` stream as it is read from
+//! an `io::Read` source.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use huffc::{
+//!     build_huffman_array, decoder::HuffmanDecoder, encode_huffman_array, huff_encode_bitvec,
+//!     tally_frequency,
+//! };
+//!
+//! let bytes = b"abracadabra";
+//! let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(bytes)));
+//! let (bit_buffer, total_bits) = huff_encode_bitvec(bytes, &encoded_map).unwrap();
+//!
+//! let mut decoder = HuffmanDecoder::new(&encoded_map).unwrap().with_total_bits(total_bits);
+//! decoder.feed(&bit_buffer);
+//! let mut decoded = Vec::new();
+//! while let Some(byte) = decoder.try_decode_symbol().unwrap() {
+//!     decoded.push(byte);
+//! }
+//! assert_eq!(decoded, bytes);
+//! ```
+//!
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+};
+
+use bitvec::{order::Msb0, slice::BitSlice, vec::BitVec};
+
+use crate::{
+    parse_code_header, parse_last_bit_header, u8_to_u64, Encoded, HuffErr, FORMAT_HUFFMAN,
+    FORMAT_STORED, HUFF_FORMAT_VERSION, HUFF_FORMAT_VERSION_LAST_BIT, HUFF_MAGIC,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Link {
+    None,
+    Node(usize),
+    Leaf(u8),
+}
+
+/// Binary trie over the codes of an encoding, the root is node 0.
+#[derive(Debug, Clone)]
+pub(crate) struct DecodeTrie {
+    nodes: Vec<[Link; 2]>,
+}
+
+impl DecodeTrie {
+    /// Builds the trie, failing if the codes are empty or not prefix free.
+    pub(crate) fn new(encoded_map: &HashMap<u8, Encoded>) -> Result<Self, HuffErr> {
+        let not_prefix_free = || HuffErr::MalformedData("codes are not prefix free".to_string());
+        let mut nodes = vec![[Link::None; 2]];
+        for encoded in encoded_map.values() {
+            let Some((last, prefix)) = encoded.bits.split_last() else {
+                return Err(HuffErr::MalformedData(format!(
+                    "empty code for byte {:#04x}",
+                    encoded.value
+                )));
+            };
+
+            let mut node = 0;
+            for bit in prefix.iter().by_vals() {
+                node = match nodes[node][bit as usize] {
+                    Link::Node(next) => next,
+                    Link::None => {
+                        nodes.push([Link::None; 2]);
+                        nodes[node][bit as usize] = Link::Node(nodes.len() - 1);
+                        nodes.len() - 1
+                    }
+                    Link::Leaf(_) => return Err(not_prefix_free()),
+                };
+            }
+            match nodes[node][*last as usize] {
+                Link::None => nodes[node][*last as usize] = Link::Leaf(encoded.value),
+                _ => return Err(not_prefix_free()),
+            }
+        }
+        Ok(Self { nodes })
+    }
+
+    /// Decodes the symbol at the start of `bits`.
+    ///
+    /// Returns `Ok(Some((symbol, code_len)))` on success, `Ok(None)` if `bits` ends inside a code
+    /// and `Err(len)` with the number of bits read if they match no code.
+    pub(crate) fn decode(&self, bits: &BitSlice<u8, Msb0>) -> Result<Option<(u8, usize)>, usize> {
+        let mut node = 0;
+        for (idx, bit) in bits.iter().by_vals().enumerate() {
+            match self.nodes[node][bit as usize] {
+                Link::Node(next) => node = next,
+                Link::Leaf(symbol) => return Ok(Some((symbol, idx + 1))),
+                Link::None => return Err(idx + 1),
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Decodes a Huffman bit stream one symbol at a time.
+#[derive(Debug, Clone)]
+pub struct HuffmanDecoder {
+    trie: DecodeTrie,
+    /// Received bits, those before `pos` are decoded and dropped when `feed` compacts the buffer
+    bits: BitVec<u8, Msb0>,
+    /// Index of the next bit to decode in `bits`
+    pos: usize,
+    /// Absolute bit offset of `bits[pos]` in the stream
+    offset: u64,
+    /// Bits still to be discarded as they are fed, after skipping past the received bits
+    pending_skip: u64,
+    total_bits: Option<u64>,
+}
+
+impl HuffmanDecoder {
+    /// Creates a decoder for the codes in `encoded_map`.
+    ///
+    /// # Errors
+    ///
+    /// * `HuffErr::NullInput` - `encoded_map` is empty.
+    /// * `HuffErr::MalformedData` - The codes are not prefix free.
+    pub fn new(encoded_map: &HashMap<u8, Encoded>) -> Result<Self, HuffErr> {
+        if encoded_map.is_empty() {
+            return Err(HuffErr::NullInput("HuffmanDecoder::new"));
+        }
+        Ok(Self {
+            trie: DecodeTrie::new(encoded_map)?,
+            bits: BitVec::new(),
+            pos: 0,
+            offset: 0,
+            pending_skip: 0,
+            total_bits: None,
+        })
+    }
+
+    /// Stops decoding at `total_bits`, the padding after it is ignored.
+    pub fn with_total_bits(mut self, total_bits: u64) -> Self {
+        self.total_bits = Some(total_bits);
+        self
+    }
+
+    /// Appends received bytes to the stream.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        // Dropping the decoded bits shifts the rest, only do it once they are at least half of
+        // the buffer so every bit is shifted a bounded number of times
+        if self.pos >= 8 && self.pos * 2 >= self.bits.len() {
+            let decoded = self.pos / 8 * 8;
+            self.bits.drain(..decoded);
+            self.pos -= decoded;
+        }
+        self.bits.extend_from_raw_slice(bytes);
+        if self.pending_skip > 0 {
+            let drop = (self.pending_skip as usize).min(self.available_bits());
+            self.pos += drop;
+            self.pending_skip -= drop as u64;
+        }
+    }
+
+    /// Absolute bit offset of the next symbol.
+    pub fn bit_offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Whether every bit up to `total_bits` has been decoded.
+    pub fn is_finished(&self) -> bool {
+        self.total_bits == Some(self.offset)
+    }
+
+    /// Attempts to decode the next symbol.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(byte))` - A symbol was decoded and consumed.
+    /// * `Ok(None)` - More input is needed, or the stream is finished. Nothing is consumed.
+    /// * `Err(HuffErr::CorruptedBitstream)` - The bits at `bit_offset` match no code. Nothing is
+    ///   consumed, use `skip_to_bit` to resume at a known symbol boundary.
+    pub fn try_decode_symbol(&mut self) -> Result<Option<u8>, HuffErr> {
+        let available = match self.total_bits {
+            Some(total_bits) => {
+                let remaining = total_bits.saturating_sub(self.offset);
+                self.available_bits().min(remaining as usize)
+            }
+            None => self.available_bits(),
+        };
+
+        match self.trie.decode(&self.bits[self.pos..self.pos + available]) {
+            Ok(Some((symbol, code_len))) => {
+                self.consume(code_len);
+                Ok(Some(symbol))
+            }
+            Ok(None) => Ok(None),
+            Err(_) => Err(HuffErr::CorruptedBitstream {
+                bit_offset: self.offset,
+            }),
+        }
+    }
+
+    /// Discards the stream up to the absolute `bit_offset`, e.g. the next block boundary after a
+    /// corruption. Offsets behind the current position are ignored. Skipped bits that have not
+    /// been fed yet are discarded as they arrive.
+    pub fn skip_to_bit(&mut self, bit_offset: u64) {
+        let skip = bit_offset.saturating_sub(self.offset);
+        let drop = skip.min(self.available_bits() as u64);
+        self.consume(drop as usize);
+        self.pending_skip += skip - drop;
+        self.offset = self.offset.max(bit_offset);
+    }
+
+    /// Number of received bits not yet decoded.
+    fn available_bits(&self) -> usize {
+        self.bits.len() - self.pos
+    }
+
+    fn consume(&mut self, num_bits: usize) {
+        self.pos += num_bits;
+        self.offset += num_bits as u64;
+    }
+}
+
+/// Row offset of the leaf flags in the transition table of `HuffmanStateMachine`.
+const LEAF_ROWS: usize = 256;
+
+/// Bit-by-bit decoder driven by a flat 1 KiB transition table, simple enough to port to an FPGA
+/// or a GPU.
+///
+/// The states are the internal nodes of the code trie, state 0 is the root. `table[state][bit]`
+/// is the next state, or the decoded symbol if the flag `table[256 + state][bit]` is 1, after
+/// which the machine returns to the root.
+#[derive(Debug, Clone)]
+pub struct HuffmanStateMachine {
+    table: [[u8; 2]; 2 * LEAF_ROWS],
+    state: u8,
+}
+
+impl HuffmanStateMachine {
+    /// Precomputes the transition table for the codes in `encoded_map`.
+    ///
+    /// # Errors
+    ///
+    /// * `HuffErr::NullInput` - `encoded_map` is empty.
+    /// * `HuffErr::MalformedData` - The codes are not prefix free, some bit sequence matches no
+    ///   code, or the trie has more than 256 internal nodes.
+    pub fn new(encoded_map: &HashMap<u8, Encoded>) -> Result<Self, HuffErr> {
+        if encoded_map.is_empty() {
+            return Err(HuffErr::NullInput("HuffmanStateMachine::new"));
+        }
+        let trie = DecodeTrie::new(encoded_map)?;
+        if trie.nodes.len() > LEAF_ROWS {
+            return Err(HuffErr::MalformedData(
+                "codes need more than 256 states".to_string(),
+            ));
+        }
+
+        let mut table = [[0; 2]; 2 * LEAF_ROWS];
+        for (state, links) in trie.nodes.iter().enumerate() {
+            for (bit, link) in links.iter().enumerate() {
+                match link {
+                    Link::Node(next) => table[state][bit] = *next as u8,
+                    Link::Leaf(symbol) => {
+                        table[state][bit] = *symbol;
+                        table[LEAF_ROWS + state][bit] = 1;
+                    }
+                    Link::None => {
+                        return Err(HuffErr::MalformedData("codes are not complete".to_string()))
+                    }
+                }
+            }
+        }
+        Ok(Self { table, state: 0 })
+    }
+
+    /// Advances by one bit, returning the symbol if the bit completes a code.
+    pub fn step(&mut self, bit: bool) -> Option<u8> {
+        let state = self.state as usize;
+        let target = self.table[state][bit as usize];
+        if self.table[LEAF_ROWS + state][bit as usize] == 1 {
+            self.state = 0;
+            Some(target)
+        } else {
+            self.state = target;
+            None
+        }
+    }
+
+    /// The current state, 0 between symbols.
+    pub fn state(&self) -> u8 {
+        self.state
+    }
+
+    /// Returns to the root, discarding a partially decoded code.
+    pub fn reset(&mut self) {
+        self.state = 0;
+    }
+
+    /// The transition table, for loading into another implementation.
+    pub fn table(&self) -> &[[u8; 2]; 2 * LEAF_ROWS] {
+        &self.table
+    }
+}
+
+/// Reads the bits of a byte slice most significant bit first, across byte boundaries, without
+/// copying the slice. Keeps a window of the current and the next byte.
+#[derive(Debug, Clone)]
+pub struct HuffmanBitReader<'a> {
+    bytes: &'a [u8],
+    /// Index of `current` in `bytes`
+    byte_idx: usize,
+    bit_in_byte: u8,
+    current: u8,
+    next: u8,
+}
+
+impl<'a> HuffmanBitReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            byte_idx: 0,
+            bit_in_byte: 0,
+            current: bytes.first().copied().unwrap_or(0),
+            next: bytes.get(1).copied().unwrap_or(0),
+        }
+    }
+
+    /// Reads the next bit, `None` at the end of the slice.
+    pub fn read_bit(&mut self) -> Option<bool> {
+        if self.byte_idx >= self.bytes.len() {
+            return None;
+        }
+        let bit = (self.current >> (7 - self.bit_in_byte)) & 1;
+        self.bit_in_byte += 1;
+        if self.bit_in_byte == 8 {
+            self.bit_in_byte = 0;
+            self.byte_idx += 1;
+            self.current = self.next;
+            self.next = self.bytes.get(self.byte_idx + 1).copied().unwrap_or(0);
+        }
+        Some(bit == 1)
+    }
+
+    /// The next 8 bits without consuming them, bits past the end of the slice are zero.
+    pub fn peek_byte(&self) -> u8 {
+        let window = (self.current as u16) << 8 | self.next as u16;
+        (window >> (8 - self.bit_in_byte)) as u8
+    }
+
+    /// Number of bits read so far.
+    pub fn bits_read(&self) -> u64 {
+        self.byte_idx as u64 * 8 + self.bit_in_byte as u64
+    }
+}
+
+impl Iterator for HuffmanBitReader<'_> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        self.read_bit()
+    }
+}
+
+/// Number of compressed bytes `HuffmanReader` reads from its source at a time.
+const READER_CHUNK: usize = 8 * 1024;
+
+/// Largest symbol header of 256 symbols with codes of up to `u16::MAX` bits.
+const MAX_HEADER_BYTES: u64 = 256 * (3 + 8192);
+
+/// Body of the stream read by `HuffmanReader`, known once the header is parsed.
+#[derive(Debug)]
+enum ReaderBody {
+    Stored,
+    Huffman(DecodeTrie),
+}
+
+/// Checksum of the payload read so far by `HuffmanReader` and the one from the header.
+#[derive(Debug, Clone)]
+struct PayloadChecksum {
+    expected: u32,
+    hasher: crc32fast::Hasher,
+}
+
+/// Decompresses a `.huff` stream written by `serialize_huffman` while it is read from `inner`,
+/// so the compressed stream never has to be held in memory. The header is parsed on the first
+/// `read`, after that the bits are read from `inner` in chunks and decoded on demand. The
+/// checksum covers the whole stream, so it is verified at its end: the `read` returning the last
+/// bytes reads `inner` to its end and fails instead if the checksum does not match.
+///
+/// Errors of the stream are returned as `io::ErrorKind::InvalidData` wrapping the `HuffErr`,
+/// e.g. `HuffErr::NotAHuffFile` or `HuffErr::ChecksumMismatch`.
+#[derive(Debug)]
+pub struct HuffmanReader<R: Read> {
+    inner: R,
+    body: Option<ReaderBody>,
+    /// `None` for version 1 streams and once verified
+    checksum: Option<PayloadChecksum>,
+    /// Compressed bytes read from `inner`, the first `bit_pos` bits are decoded
+    buffer: Vec<u8>,
+    bit_pos: usize,
+    total_bits: u64,
+    bits_left: u64,
+}
+
+impl<R: Read> HuffmanReader<R> {
+    /// Creates a reader decompressing the stream read from `inner`.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            body: None,
+            checksum: None,
+            buffer: Vec::new(),
+            bit_pos: 0,
+            total_bits: 0,
+            bits_left: 0,
+        }
+    }
+
+    /// The source, positioned after the bytes read so far.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Parses the magic bytes, version, checksum, format byte and, for Huffman encoded streams,
+    /// the sizes and the symbol header.
+    fn read_header(&mut self) -> Result<ReaderBody, HuffErr> {
+        let mut magic = [0u8; 4];
+        match self.inner.read_exact(&mut magic) {
+            Ok(()) if magic == HUFF_MAGIC => {}
+            Err(err) if err.kind() != io::ErrorKind::UnexpectedEof => return Err(err.into()),
+            _ => return Err(HuffErr::NotAHuffFile),
+        }
+        let [version] = self.read_array()?;
+        if version != HUFF_FORMAT_VERSION && version != HUFF_FORMAT_VERSION_LAST_BIT {
+            return Err(HuffErr::UnsupportedVersion(version));
+        }
+        if version == HUFF_FORMAT_VERSION {
+            self.checksum = Some(PayloadChecksum {
+                expected: u32::from_be_bytes(self.read_array()?),
+                hasher: crc32fast::Hasher::new(),
+            });
+        }
+        let [format] = self.read_array()?;
+        self.update_checksum(&[format]);
+
+        match format {
+            FORMAT_STORED => Ok(ReaderBody::Stored),
+            FORMAT_HUFFMAN => {
+                let sizes: [u8; 16] = self.read_array()?;
+                self.update_checksum(&sizes);
+                self.total_bits = u8_to_u64(&sizes[..8]);
+                self.bits_left = self.total_bits;
+                let header_num_bytes = u8_to_u64(&sizes[8..]);
+                if header_num_bytes > MAX_HEADER_BYTES {
+                    return Err(HuffErr::MalformedData(format!(
+                        "symbol header of {} bytes",
+                        header_num_bytes
+                    )));
+                }
+
+                let mut header = vec![0u8; header_num_bytes as usize];
+                self.inner.read_exact(&mut header).map_err(truncated)?;
+                self.update_checksum(&header);
+                let encoded_map = if version == HUFF_FORMAT_VERSION_LAST_BIT {
+                    parse_last_bit_header(&header)?
+                } else {
+                    parse_code_header(&header)?
+                };
+                Ok(ReaderBody::Huffman(DecodeTrie::new(&encoded_map)?))
+            }
+            format => Err(HuffErr::MalformedData(format!(
+                "unsupported format byte {:#04x}",
+                format
+            ))),
+        }
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], HuffErr> {
+        let mut bytes = [0u8; N];
+        self.inner.read_exact(&mut bytes).map_err(truncated)?;
+        Ok(bytes)
+    }
+
+    fn update_checksum(&mut self, bytes: &[u8]) {
+        if let Some(checksum) = self.checksum.as_mut() {
+            checksum.hasher.update(bytes);
+        }
+    }
+
+    /// Reads `inner` to its end and compares the checksum of the payload with the one of the
+    /// header, once.
+    fn verify_checksum(&mut self) -> Result<(), HuffErr> {
+        let Some(mut checksum) = self.checksum.take() else {
+            return Ok(());
+        };
+        let mut rest = [0u8; READER_CHUNK];
+        loop {
+            match self.inner.read(&mut rest) {
+                Ok(0) => break,
+                Ok(read) => checksum.hasher.update(&rest[..read]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let actual = checksum.hasher.finalize();
+        if actual != checksum.expected {
+            return Err(HuffErr::ChecksumMismatch {
+                expected: checksum.expected,
+                actual,
+            });
+        }
+        Ok(())
+    }
+
+    /// Decodes symbols into `out` until it is full or the stream is finished.
+    fn decode_into(&mut self, trie: &DecodeTrie, out: &mut [u8]) -> Result<usize, HuffErr> {
+        let mut written = 0;
+        while written < out.len() && self.bits_left > 0 {
+            let available = (self.buffer.len() * 8 - self.bit_pos).min(self.bits_left as usize);
+            let bits = &BitSlice::<u8, Msb0>::from_slice(&self.buffer)
+                [self.bit_pos..self.bit_pos + available];
+            match trie.decode(bits) {
+                Ok(Some((symbol, code_len))) => {
+                    out[written] = symbol;
+                    written += 1;
+                    self.bit_pos += code_len;
+                    self.bits_left -= code_len as u64;
+                }
+                Ok(None) => {
+                    if !self.refill()? {
+                        return Err(HuffErr::MalformedData(
+                            "bit buffer ends inside a code".to_string(),
+                        ));
+                    }
+                }
+                Err(_) => {
+                    return Err(HuffErr::CorruptedBitstream {
+                        bit_offset: self.total_bits - self.bits_left,
+                    })
+                }
+            }
+        }
+        Ok(written)
+    }
+
+    /// Drops the decoded bytes of the buffer and reads the next chunk from `inner`, false at the
+    /// end of `inner`.
+    fn refill(&mut self) -> io::Result<bool> {
+        self.buffer.drain(..self.bit_pos / 8);
+        self.bit_pos %= 8;
+
+        let len = self.buffer.len();
+        self.buffer.resize(len + READER_CHUNK, 0);
+        let read = loop {
+            match self.inner.read(&mut self.buffer[len..]) {
+                Ok(read) => break read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    self.buffer.truncate(len);
+                    return Err(err);
+                }
+            }
+        };
+        self.buffer.truncate(len + read);
+        if let Some(checksum) = self.checksum.as_mut() {
+            checksum.hasher.update(&self.buffer[len..]);
+        }
+        Ok(read > 0)
+    }
+}
+
+impl<R: Read> Read for HuffmanReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if out.is_empty() {
+            return Ok(0);
+        }
+        let body = match self.body.take() {
+            Some(body) => body,
+            None => self.read_header().map_err(into_io_error)?,
+        };
+
+        let result = match &body {
+            ReaderBody::Stored => self.inner.read(out).and_then(|read| {
+                self.update_checksum(&out[..read]);
+                if read == 0 {
+                    self.verify_checksum().map_err(into_io_error)?;
+                }
+                Ok(read)
+            }),
+            ReaderBody::Huffman(trie) => self
+                .decode_into(trie, out)
+                .and_then(|written| {
+                    if self.bits_left == 0 {
+                        self.verify_checksum()?;
+                    }
+                    Ok(written)
+                })
+                .map_err(into_io_error),
+        };
+        self.body = Some(body);
+        result
+    }
+}
+
+/// Maps the end of the source inside the header to `HuffErr::MalformedData`.
+fn truncated(err: io::Error) -> HuffErr {
+    if err.kind() == io::ErrorKind::UnexpectedEof {
+        HuffErr::MalformedData("truncated header".to_string())
+    } else {
+        err.into()
+    }
+}
+
+fn into_io_error(err: HuffErr) -> io::Error {
+    match err {
+        HuffErr::Io(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        build_huffman_array, encode_huffman_array, encoder::HuffmanEncoder, huff_encode_bitvec,
+        tally_frequency,
+    };
+
+    #[test]
+    fn decode_with_partial_input() {
+        let bytes = b"abracadabra";
+        let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(bytes)));
+        let (bit_buffer, total_bits) = huff_encode_bitvec(bytes, &encoded_map).unwrap();
+        let mut decoder = HuffmanDecoder::new(&encoded_map)
+            .unwrap()
+            .with_total_bits(total_bits);
+
+        let mut decoded = Vec::new();
+        for byte in &bit_buffer {
+            decoder.feed(&[*byte]);
+            while let Some(symbol) = decoder.try_decode_symbol().unwrap() {
+                decoded.push(symbol);
+            }
+        }
+
+        assert!(decoder.is_finished());
+        assert_eq!(decoded, bytes);
+    }
+
+    #[test]
+    fn decoded_bits_are_compacted() {
+        let bytes = b"abracadabra".repeat(20_000);
+        let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(&bytes)));
+        let (bit_buffer, total_bits) = huff_encode_bitvec(&bytes, &encoded_map).unwrap();
+
+        // All at once, decoding must not shift the remaining bits for every symbol
+        let mut decoder = HuffmanDecoder::new(&encoded_map)
+            .unwrap()
+            .with_total_bits(total_bits);
+        decoder.feed(&bit_buffer);
+        let mut decoded = Vec::with_capacity(bytes.len());
+        while let Some(symbol) = decoder.try_decode_symbol().unwrap() {
+            decoded.push(symbol);
+        }
+        assert_eq!(decoded, bytes);
+
+        // In chunks, the buffer stays bounded by the chunk size
+        let mut decoder = HuffmanDecoder::new(&encoded_map)
+            .unwrap()
+            .with_total_bits(total_bits);
+        decoded.clear();
+        for chunk in bit_buffer.chunks(1024) {
+            decoder.feed(chunk);
+            assert!(decoder.bits.len() <= 2 * 1024 * 8 + 8);
+            while let Some(symbol) = decoder.try_decode_symbol().unwrap() {
+                decoded.push(symbol);
+            }
+        }
+        assert!(decoder.is_finished());
+        assert_eq!(decoded, bytes);
+    }
+
+    #[test]
+    fn recover_from_corruption_at_block_boundary() {
+        // Codes 1, 01 and 00, with 00 removed so a corrupted stream can contain an invalid code
+        let mut encoded_map = encode_huffman_array(b"abc");
+        encoded_map.remove(&b'c');
+
+        let block_size = 4;
+        let bytes = b"abababababab";
+        let mut index_bytes = Vec::new();
+        let mut encoder = HuffmanEncoder::with_index_writer(
+            encode_huffman_array(b"abc"),
+            Vec::new(),
+            &mut index_bytes,
+            block_size,
+        );
+        encoder.encode(bytes).unwrap();
+        let (mut bit_buffer, total_bits) = encoder.finish().unwrap();
+        let offsets: Vec<u64> = index_bytes.chunks_exact(8).map(crate::u8_to_u64).collect();
+
+        // "1 01 1 01 ..." becomes "1 00 ..." at bit 1
+        bit_buffer[0] &= !0b0010_0000;
+
+        let mut decoder = HuffmanDecoder::new(&encoded_map)
+            .unwrap()
+            .with_total_bits(total_bits);
+        decoder.feed(&bit_buffer);
+
+        assert_eq!(decoder.try_decode_symbol().unwrap(), Some(b'a'));
+        assert!(matches!(
+            decoder.try_decode_symbol(),
+            Err(HuffErr::CorruptedBitstream { bit_offset: 1 })
+        ));
+
+        decoder.skip_to_bit(offsets[1]);
+        let mut decoded = Vec::new();
+        while let Some(symbol) = decoder.try_decode_symbol().unwrap() {
+            decoded.push(symbol);
+        }
+        assert_eq!(decoded, bytes[block_size..]);
+    }
+
+    #[test]
+    fn skip_past_bits_not_yet_fed() {
+        let bytes = b"abracadabra";
+        let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(bytes)));
+        let mut index_bytes = Vec::new();
+        let mut encoder = HuffmanEncoder::with_index_writer(
+            encode_huffman_array(&build_huffman_array(&tally_frequency(bytes))),
+            Vec::new(),
+            &mut index_bytes,
+            4,
+        );
+        encoder.encode(bytes).unwrap();
+        let (bit_buffer, total_bits) = encoder.finish().unwrap();
+        let offsets: Vec<u64> = index_bytes.chunks_exact(8).map(crate::u8_to_u64).collect();
+        assert!(offsets[2] > 8);
+
+        let mut decoder = HuffmanDecoder::new(&encoded_map)
+            .unwrap()
+            .with_total_bits(total_bits);
+        decoder.feed(&bit_buffer[..1]);
+        decoder.skip_to_bit(offsets[2]);
+        assert_eq!(decoder.bit_offset(), offsets[2]);
+        assert_eq!(decoder.try_decode_symbol().unwrap(), None);
+
+        bit_buffer[1..]
+            .iter()
+            .for_each(|byte| decoder.feed(&[*byte]));
+        let mut decoded = Vec::new();
+        while let Some(symbol) = decoder.try_decode_symbol().unwrap() {
+            decoded.push(symbol);
+        }
+        assert!(decoder.is_finished());
+        assert_eq!(decoded, bytes[8..]);
+    }
+
+    #[test]
+    fn state_machine_matches_trie_decoder() {
+        let bytes = b"she sells sea shells by the sea shore";
+        let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(bytes)));
+        let (bit_buffer, total_bits) = huff_encode_bitvec(bytes, &encoded_map).unwrap();
+
+        let mut decoder = HuffmanDecoder::new(&encoded_map)
+            .unwrap()
+            .with_total_bits(total_bits);
+        decoder.feed(&bit_buffer);
+        let mut trie_decoded = Vec::new();
+        while let Some(symbol) = decoder.try_decode_symbol().unwrap() {
+            trie_decoded.push(symbol);
+        }
+
+        let mut machine = HuffmanStateMachine::new(&encoded_map).unwrap();
+        assert_eq!(std::mem::size_of_val(machine.table()), 1024);
+        let bits = BitSlice::<u8, Msb0>::from_slice(&bit_buffer);
+        let machine_decoded: Vec<u8> = bits[..total_bits as usize]
+            .iter()
+            .by_vals()
+            .filter_map(|bit| machine.step(bit))
+            .collect();
+
+        assert_eq!(machine.state(), 0);
+        assert_eq!(machine_decoded, trie_decoded);
+        assert_eq!(machine_decoded, bytes);
+    }
+
+    #[test]
+    fn state_machine_rejects_incomplete_codes() {
+        let mut encoded_map = encode_huffman_array(b"abc");
+        encoded_map.remove(&b'c');
+        assert!(matches!(
+            HuffmanStateMachine::new(&encoded_map),
+            Err(HuffErr::MalformedData(_))
+        ));
+        assert!(matches!(
+            HuffmanStateMachine::new(&HashMap::new()),
+            Err(HuffErr::NullInput(_))
+        ));
+    }
+
+    #[test]
+    fn reject_codes_that_are_not_prefix_free() {
+        let mut encoded_map = encode_huffman_array(b"abc");
+        let mut bits = BitVec::new();
+        bits.push(false);
+        encoded_map.insert(
+            b'd',
+            Encoded {
+                bits,
+                num_bits_sequence: 1,
+                value: b'd',
+            },
+        );
+
+        assert!(HuffmanDecoder::new(&encoded_map).is_err());
+        assert!(matches!(
+            HuffmanDecoder::new(&HashMap::new()),
+            Err(HuffErr::NullInput("HuffmanDecoder::new"))
+        ));
+    }
+
+    #[test]
+    fn bit_reader_matches_bitvec() {
+        let bytes = [0b1010_0001, 0xFF, 0x00, 0b0110_1001];
+        let expected: BitVec<u8, Msb0> = BitVec::from_slice(&bytes);
+
+        let mut reader = HuffmanBitReader::new(&bytes);
+        assert_eq!(reader.peek_byte(), bytes[0]);
+        for _ in 0..3 {
+            reader.read_bit();
+        }
+        assert_eq!(reader.peek_byte(), 0b0000_1111);
+        assert_eq!(reader.bits_read(), 3);
+
+        let read: Vec<bool> = HuffmanBitReader::new(&bytes).collect();
+        assert_eq!(read, expected.iter().by_vals().collect::<Vec<_>>());
+        assert_eq!(HuffmanBitReader::new(&[]).next(), None);
+    }
+}
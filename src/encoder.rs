@@ -0,0 +1,824 @@
+//! Huffc Encoder - Incremental Huffman Encoding
+//!
+//! `HuffmanEncoder` encodes input as it arrives and writes complete bytes to a sink, so the
+//! whole encoded bit buffer never has to be held in memory. It can optionally produce a block
+//! index, the bit offset of every `block_size`-th input byte, which allows decompression to start
+//! in the middle of the stream. Long running encodes can be checkpointed with
+//! `HuffmanEncoder::checkpoint` and continued after a restart with `HuffmanEncoder::resume`. The
+//! encoder implements `io::Write`, so input can be streamed into it with `io::copy`.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use huffc::{build_huffman_array, encode_huffman_array, encoder::HuffmanEncoder, tally_frequency};
+//!
+//! let bytes = b"abracadabra";
+//! let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(bytes)));
+//! let mut encoder = HuffmanEncoder::new(encoded_map, Vec::new());
+//! encoder.encode(bytes).unwrap();
+//! let (bit_buffer, total_bits) = encoder.finish().unwrap();
+//! assert_eq!(bit_buffer.len() as u64, total_bits.div_ceil(8));
+//! ```
+//!
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use bitvec::{order::Msb0, slice::BitSlice, vec::BitVec};
+
+use crate::{u64_to_u8, u8_to_u64, Encoded, HuffErr};
+
+/// Encodes bytes with a fixed encoding, writing the bit stream to `W` and the optional block
+/// index to `I`.
+///
+/// The last bits that do not fill a whole byte are written, padded with zero bits, by `finish` or
+/// when the encoder is dropped. Dropping ignores write errors and writes nothing if the encoder
+/// was checkpointed to be resumed later, consumed by `into_stats` or returned an error, call
+/// `finish` to see the errors and get the total number of bits.
+#[must_use = "the encoder must be finished to get the total number of bits"]
+pub struct HuffmanEncoder<W: Write, I: Write = io::Sink> {
+    /// `None` once `finish` returned it
+    data_writer: Option<W>,
+    encoded_map: HashMap<u8, Encoded>,
+    /// Bits that do not yet fill a whole byte
+    pending: BitVec<u8, Msb0>,
+    total_bits: u64,
+    input_bytes: u64,
+    /// Occurrences of every byte encoded by this encoder, not restored by `resume`
+    symbol_counts: [u64; 256],
+    index: Option<(I, usize)>,
+    /// Set by `checkpoint`, `into_stats` and errors, after which dropping does not write the
+    /// pending bits
+    discardable: Cell<bool>,
+}
+
+/// Statistics of a `HuffmanEncoder`, returned by `HuffmanEncoder::stats`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EncoderStats {
+    pub input_bytes: u64,
+    pub output_bits: u64,
+    pub symbols_encoded: u64,
+    /// Output bits per encoded symbol, 0.0 if nothing was encoded.
+    pub avg_code_length: f64,
+    /// The lowest of the most frequent symbols, 0 if nothing was encoded.
+    pub most_frequent_symbol: u8,
+    pub most_frequent_count: u64,
+}
+
+impl<W: Write> HuffmanEncoder<W> {
+    /// Creates an encoder writing the encoded bits of every byte to `data_writer`.
+    pub fn new(encoded_map: HashMap<u8, Encoded>, data_writer: W) -> Self {
+        Self {
+            data_writer: Some(data_writer),
+            encoded_map,
+            pending: BitVec::new(),
+            total_bits: 0,
+            input_bytes: 0,
+            symbol_counts: [0; 256],
+            index: None,
+            discardable: Cell::new(false),
+        }
+    }
+
+    /// Rebuilds an encoder from a checkpoint written by `checkpoint`, without a block index.
+    ///
+    /// `data_writer` must continue where the checkpointed encoder's writer left off, e.g. the
+    /// output file opened in append mode, as the bytes written before the checkpoint are not part
+    /// of it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `HuffErr::MalformedData` if the checkpoint is truncated or inconsistent.
+    pub fn resume(checkpoint: &[u8], data_writer: W) -> Result<Self, HuffErr> {
+        let state = Checkpoint::from_bytes(checkpoint)?;
+        Ok(Self {
+            data_writer: Some(data_writer),
+            encoded_map: state.encoded_map,
+            pending: state.pending,
+            total_bits: state.total_bits,
+            input_bytes: state.input_bytes,
+            symbol_counts: [0; 256],
+            index: None,
+            discardable: Cell::new(false),
+        })
+    }
+}
+
+impl HuffmanEncoder<io::Sink> {
+    /// Estimates the size in bytes of `serialize_huffman`'s output for `bytes`: the encoded bits
+    /// rounded up to whole bytes, the header entry of every symbol of `map` (3 bytes plus its code
+    /// rounded up to whole bytes) and 26 bytes of header (magic bytes, version, checksum, format
+    /// byte and the two sizes). Bytes without a code are not counted.
+    pub fn estimated_output_size(bytes: &[u8], map: &HashMap<u8, Encoded>) -> u64 {
+        let mut lengths = [0u64; 256];
+        map.values()
+            .for_each(|encoded| lengths[encoded.value as usize] = encoded.bits.len() as u64);
+        let total_bits: u64 = bytes.iter().map(|byte| lengths[*byte as usize]).sum();
+        let header_bytes: u64 = lengths.iter().map(|len| len.div_ceil(8)).sum();
+        total_bits.div_ceil(8) + 3 * map.len() as u64 + header_bytes + 26
+    }
+}
+
+impl<W: Write, I: Write> HuffmanEncoder<W, I> {
+    /// Creates an encoder that additionally writes the bit offset of every `block_size`-th input
+    /// byte, starting with byte 0, to `index_writer` as a big endian `u64`.
+    ///
+    /// The index stream can be loaded with `HuffBlockIndex::from_bytes`. A `block_size` of 0 is
+    /// treated as 1.
+    pub fn with_index_writer(
+        encoded_map: HashMap<u8, Encoded>,
+        data_writer: W,
+        index_writer: I,
+        block_size: usize,
+    ) -> Self {
+        Self {
+            data_writer: Some(data_writer),
+            encoded_map,
+            pending: BitVec::new(),
+            total_bits: 0,
+            input_bytes: 0,
+            symbol_counts: [0; 256],
+            index: Some((index_writer, block_size.max(1))),
+            discardable: Cell::new(false),
+        }
+    }
+
+    /// Encodes `bytes`, writing every completed byte to the data writer.
+    ///
+    /// # Errors
+    ///
+    /// Returns `io::ErrorKind::InvalidInput` if a byte has no encoding, the bytes before it are
+    /// encoded.
+    pub fn encode(&mut self, bytes: &[u8]) -> io::Result<()> {
+        // Codes in pieces of at most 32 bits, shifted into an accumulator rather than appended
+        // to a `BitVec` bit by bit
+        let mut codes: [Option<Vec<(u64, usize)>>; 256] = std::array::from_fn(|_| None);
+        for encoded in self.encoded_map.values() {
+            let pieces = encoded
+                .bits
+                .chunks(32)
+                .map(|piece| (bits_to_u64(piece), piece.len()))
+                .collect();
+            codes[encoded.value as usize] = Some(pieces);
+        }
+
+        let mut acc = bits_to_u64(&self.pending);
+        let mut acc_len = self.pending.len();
+        let mut complete = Vec::with_capacity(bytes.len());
+        let mut result = Ok(());
+        for byte in bytes {
+            if let Some((index_writer, block_size)) = self.index.as_mut() {
+                if self.input_bytes.is_multiple_of(*block_size as u64) {
+                    if let Err(err) = index_writer.write_all(&u64_to_u8(self.total_bits)) {
+                        result = Err(err);
+                        break;
+                    }
+                }
+            }
+
+            let Some(pieces) = &codes[*byte as usize] else {
+                result = Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("no encoding for byte {:#04x}", byte),
+                ));
+                break;
+            };
+            for (piece, len) in pieces {
+                acc = acc << len | piece;
+                acc_len += len;
+                while acc_len >= 8 {
+                    acc_len -= 8;
+                    complete.push((acc >> acc_len) as u8);
+                }
+                self.total_bits += *len as u64;
+            }
+            self.input_bytes += 1;
+            self.symbol_counts[*byte as usize] += 1;
+        }
+
+        self.pending = (0..acc_len)
+            .rev()
+            .map(|shift| acc >> shift & 1 == 1)
+            .collect();
+        let result = self.data_writer().write_all(&complete).and(result);
+        if result.is_err() {
+            self.discardable.set(true);
+        }
+        result
+    }
+
+    /// Total number of bits encoded so far.
+    pub fn total_bits(&self) -> u64 {
+        self.total_bits
+    }
+
+    /// Number of input bytes encoded so far.
+    pub fn input_bytes(&self) -> u64 {
+        self.input_bytes
+    }
+
+    /// Statistics of the encoding so far. After `resume` the input bytes and output bits include
+    /// the checkpointed encoding, the symbol counts only the bytes encoded since.
+    pub fn stats(&self) -> EncoderStats {
+        let (most_frequent_symbol, most_frequent_count) = self
+            .symbol_counts
+            .iter()
+            .enumerate()
+            .fold((0, 0), |(best, best_count), (symbol, count)| {
+                if *count > best_count {
+                    (symbol as u8, *count)
+                } else {
+                    (best, best_count)
+                }
+            });
+        let avg_code_length = if self.input_bytes == 0 {
+            0.0
+        } else {
+            self.total_bits as f64 / self.input_bytes as f64
+        };
+
+        EncoderStats {
+            input_bytes: self.input_bytes,
+            output_bits: self.total_bits,
+            symbols_encoded: self.input_bytes,
+            avg_code_length,
+            most_frequent_symbol,
+            most_frequent_count,
+        }
+    }
+
+    /// Consumes the encoder and returns its `stats`. The final partial byte is not written, call
+    /// `stats` before `finish` to keep the output complete.
+    pub fn into_stats(self) -> EncoderStats {
+        self.discardable.set(true);
+        self.stats()
+    }
+
+    /// Point at which the two streams can be split, as `(input_offset, bit_offset)`: the next
+    /// input byte, at `input_offset`, is encoded starting at `bit_offset` of the data stream. At a
+    /// block boundary this is the entry written to the index stream.
+    pub fn split_point(&self) -> (u64, u64) {
+        (self.input_bytes, self.total_bits)
+    }
+
+    /// Serializes the state needed to continue encoding later: the encoding, the bits that do not
+    /// yet fill a whole byte, the input position and the block size of the index. The completed
+    /// bytes have already been written to the data writer and are not included.
+    ///
+    /// The layout is the size of the rest of the checkpoint (8 bytes), the number of input bytes
+    /// (8 bytes), the number of bits (8 bytes), the block size or 0 without an index (8 bytes),
+    /// the number of codes (2 bytes), every code as symbol, length in bits (2 bytes) and the code
+    /// bits padded to whole bytes, then the pending bits padded to a byte.
+    pub fn checkpoint(&self) -> Vec<u8> {
+        self.discardable.set(true);
+        let mut body = Vec::new();
+        body.extend_from_slice(&u64_to_u8(self.input_bytes));
+        body.extend_from_slice(&u64_to_u8(self.total_bits));
+        let block_size = self.index.as_ref().map_or(0, |(_, block_size)| *block_size);
+        body.extend_from_slice(&u64_to_u8(block_size as u64));
+
+        let mut codes: Vec<&Encoded> = self.encoded_map.values().collect();
+        codes.sort_by_key(|encoded| encoded.value);
+        body.extend_from_slice(&(codes.len() as u16).to_be_bytes());
+        for encoded in codes {
+            let mut bits = encoded.bits.clone();
+            bits.set_uninitialized(false);
+            body.push(encoded.value);
+            body.extend_from_slice(&encoded.num_bits_sequence.to_be_bytes());
+            body.extend_from_slice(bits.as_raw_slice());
+        }
+
+        let mut pending = self.pending.clone();
+        pending.set_uninitialized(false);
+        body.extend_from_slice(pending.as_raw_slice());
+
+        let mut checkpoint = u64_to_u8(body.len() as u64).to_vec();
+        checkpoint.extend(body);
+        checkpoint
+    }
+
+    /// Rebuilds an encoder from a checkpoint like `resume`, continuing its block index in
+    /// `index_writer`. `block_size` is taken from the checkpoint, or is 1 if the checkpointed
+    /// encoder had no index.
+    ///
+    /// # Errors
+    ///
+    /// Returns `HuffErr::MalformedData` if the checkpoint is truncated or inconsistent.
+    pub fn resume_with_index_writer(
+        checkpoint: &[u8],
+        data_writer: W,
+        index_writer: I,
+    ) -> Result<Self, HuffErr> {
+        let state = Checkpoint::from_bytes(checkpoint)?;
+        Ok(Self {
+            data_writer: Some(data_writer),
+            encoded_map: state.encoded_map,
+            pending: state.pending,
+            total_bits: state.total_bits,
+            input_bytes: state.input_bytes,
+            symbol_counts: [0; 256],
+            index: Some((index_writer, state.block_size.max(1))),
+            discardable: Cell::new(false),
+        })
+    }
+
+    /// Writes the final partial byte, padded with zero bits, and flushes both writers.
+    ///
+    /// # Returns
+    ///
+    /// * `(W, u64)` - The data writer and the total number of encoded bits.
+    pub fn finish(mut self) -> io::Result<(W, u64)> {
+        if !self.pending.is_empty() {
+            self.pending.set_uninitialized(false);
+            let pending = std::mem::take(&mut self.pending);
+            self.data_writer().write_all(pending.as_raw_slice())?;
+        }
+        self.flush()?;
+        let data_writer = self
+            .data_writer
+            .take()
+            .expect("finish consumes the encoder");
+        Ok((data_writer, self.total_bits))
+    }
+
+    fn data_writer(&mut self) -> &mut W {
+        self.data_writer
+            .as_mut()
+            .expect("the data writer is only taken by finish")
+    }
+}
+
+impl<W: Write, I: Write> Drop for HuffmanEncoder<W, I> {
+    fn drop(&mut self) {
+        if self.pending.is_empty() || self.discardable.get() {
+            return;
+        }
+        // `None` once `finish` wrote the pending bits itself
+        let Some(data_writer) = self.data_writer.as_mut() else {
+            return;
+        };
+        self.pending.set_uninitialized(false);
+        // Errors cannot be returned from drop, `finish` reports them
+        let _ = data_writer.write_all(self.pending.as_raw_slice());
+        let _ = data_writer.flush();
+    }
+}
+
+/// Streams input into the encoder, e.g. with `io::copy` from a file too large to load at once.
+/// `write` encodes the whole buffer like `encode`.
+///
+/// `flush` flushes the writers but keeps the bits that do not fill a whole byte, padding them
+/// would put zero bits in the middle of the stream. The final partial byte is written by
+/// `finish`, which also returns the total number of bits needed for the header, or on drop.
+impl<W: Write, I: Write> Write for HuffmanEncoder<W, I> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encode(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.data_writer().flush()?;
+        if let Some((index_writer, _)) = self.index.as_mut() {
+            index_writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// The bits of `bits`, at most 64, as the low bits of a `u64`, first bit most significant.
+fn bits_to_u64(bits: &BitSlice<u8, Msb0>) -> u64 {
+    bits.iter()
+        .by_vals()
+        .fold(0u64, |value, bit| value << 1 | bit as u64)
+}
+
+/// Encoder state parsed from `HuffmanEncoder::checkpoint`.
+struct Checkpoint {
+    input_bytes: u64,
+    total_bits: u64,
+    block_size: usize,
+    encoded_map: HashMap<u8, Encoded>,
+    pending: BitVec<u8, Msb0>,
+}
+
+impl Checkpoint {
+    fn from_bytes(checkpoint: &[u8]) -> Result<Self, HuffErr> {
+        let truncated = || HuffErr::MalformedData("truncated checkpoint".to_string());
+        let mut rest = checkpoint;
+        let mut take = |len: usize| -> Result<&[u8], HuffErr> {
+            if rest.len() < len {
+                return Err(truncated());
+            }
+            let (taken, remaining) = rest.split_at(len);
+            rest = remaining;
+            Ok(taken)
+        };
+
+        let body_len = u8_to_u64(take(8)?);
+        if body_len != (checkpoint.len() - 8) as u64 {
+            return Err(HuffErr::MalformedData(
+                "checkpoint size does not match its length prefix".to_string(),
+            ));
+        }
+        let input_bytes = u8_to_u64(take(8)?);
+        let total_bits = u8_to_u64(take(8)?);
+        let block_size = usize::try_from(u8_to_u64(take(8)?)).map_err(|_| {
+            HuffErr::MalformedData("checkpoint block size out of range".to_string())
+        })?;
+
+        let num_codes = take(2)?;
+        let num_codes = u16::from_be_bytes([num_codes[0], num_codes[1]]);
+        let mut encoded_map = HashMap::new();
+        for _ in 0..num_codes {
+            let code_header = take(3)?;
+            let (value, num_bits) = (
+                code_header[0],
+                u16::from_be_bytes([code_header[1], code_header[2]]),
+            );
+            let mut bits = BitVec::from_slice(take((num_bits as usize).div_ceil(8))?);
+            bits.truncate(num_bits as usize);
+            let encoded = Encoded {
+                bits,
+                num_bits_sequence: num_bits,
+                value,
+            };
+            if encoded_map.insert(value, encoded).is_some() {
+                return Err(HuffErr::MalformedData(format!(
+                    "duplicate code for byte {:#04x} in checkpoint",
+                    value
+                )));
+            }
+        }
+
+        let num_pending = (total_bits % 8) as usize;
+        let mut pending = BitVec::from_slice(take(num_pending.div_ceil(8))?);
+        pending.truncate(num_pending);
+        if !rest.is_empty() {
+            return Err(HuffErr::MalformedData(
+                "trailing bytes after checkpoint".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            input_bytes,
+            total_bits,
+            block_size,
+            encoded_map,
+            pending,
+        })
+    }
+}
+
+/// Block offsets written by `HuffmanEncoder::with_index_writer`, allowing decompression to start
+/// at any block boundary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HuffBlockIndex {
+    block_size: usize,
+    offsets: Vec<u64>,
+}
+
+impl HuffBlockIndex {
+    /// Parses an index stream, `block_size` must match the one used for encoding.
+    ///
+    /// # Errors
+    ///
+    /// Returns `HuffErr::MalformedData` if the stream is not a whole number of `u64` offsets.
+    pub fn from_bytes(index_bytes: &[u8], block_size: usize) -> Result<Self, HuffErr> {
+        if !index_bytes.len().is_multiple_of(8) {
+            return Err(HuffErr::MalformedData(
+                "block index is not a multiple of 8 bytes".to_string(),
+            ));
+        }
+        Ok(Self {
+            block_size: block_size.max(1),
+            offsets: index_bytes.chunks_exact(8).map(u8_to_u64).collect(),
+        })
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Number of indexed blocks.
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Bit offset of the first byte of `block`.
+    pub fn bit_offset(&self, block: usize) -> Option<u64> {
+        self.offsets.get(block).copied()
+    }
+
+    /// Decodes the bit buffer from the start of `block` to the end, the result starts at byte
+    /// `block * block_size` of the original input.
+    ///
+    /// # Errors
+    ///
+    /// Returns `HuffErr::MalformedData` if `block` is not indexed or the bits do not decode.
+    pub fn decode_from(
+        &self,
+        block: usize,
+        bit_buffer: &[u8],
+        total_bits: u64,
+        encoded_map: &HashMap<u8, Encoded>,
+    ) -> Result<Vec<u8>, HuffErr> {
+        let start = self
+            .bit_offset(block)
+            .ok_or_else(|| HuffErr::MalformedData(format!("block {} is not indexed", block)))?;
+        let bits = BitSlice::<u8, Msb0>::from_slice(bit_buffer);
+        if total_bits > bits.len() as u64 || start > total_bits {
+            return Err(HuffErr::MalformedData("truncated bit buffer".to_string()));
+        }
+
+        let codes: HashMap<&BitSlice<u8, Msb0>, u8> = encoded_map
+            .values()
+            .map(|encoded| (encoded.bits.as_bitslice(), encoded.value))
+            .collect();
+        let max_len = codes.keys().map(|code| code.len()).max().unwrap_or(0);
+
+        let mut decoded = Vec::new();
+        let mut code_start = start as usize;
+        for code_end in start as usize + 1..=total_bits as usize {
+            if let Some(value) = codes.get(&bits[code_start..code_end]) {
+                decoded.push(*value);
+                code_start = code_end;
+            } else if code_end - code_start >= max_len {
+                return Err(HuffErr::MalformedData("invalid code".to_string()));
+            }
+        }
+        if code_start != total_bits as usize {
+            return Err(HuffErr::MalformedData(
+                "bit buffer ends inside a code".to_string(),
+            ));
+        }
+        Ok(decoded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        build_huffman_array, encode_huffman_array, huff_encode_bitvec, tally_frequency,
+        FrequencyBuffer,
+    };
+
+    const FILE_PATH: &str = "./tests/resources/dna_seq_test.txt";
+
+    #[test]
+    fn estimated_output_size_bounds_serialized_size() {
+        let original = std::fs::read(FILE_PATH).unwrap();
+        let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(&original)));
+        let (bit_buffer, total_bits) = huff_encode_bitvec(&original, &encoded_map).unwrap();
+        let estimate = HuffmanEncoder::estimated_output_size(&original, &encoded_map);
+
+        // The codes of the 4 symbols are at most 8 bits long, so each takes 4 header bytes
+        assert_eq!(
+            estimate,
+            total_bits.div_ceil(8) + 4 * encoded_map.len() as u64 + 26
+        );
+        let serialized = crate::serialize_huffman(&encoded_map, bit_buffer, total_bits);
+        assert_eq!(serialized.len() as u64, estimate);
+        assert_eq!(HuffmanEncoder::estimated_output_size(b"", &encoded_map), 42);
+
+        // A code of 10 bits takes 2 bytes in the header
+        let long_codes = encode_huffman_array(b"abcdefghijk");
+        let (bit_buffer, total_bits) = huff_encode_bitvec(b"k", &long_codes).unwrap();
+        let serialized = crate::serialize_huffman(&long_codes, bit_buffer, total_bits);
+        assert_eq!(
+            serialized.len() as u64,
+            HuffmanEncoder::estimated_output_size(b"k", &long_codes)
+        );
+    }
+
+    #[test]
+    fn write_in_chunks_round_trips_10_mb() {
+        // 10 MB of skewed pseudo random text, so the unary codes are of many lengths
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let original: Vec<u8> = (0..10 * 1024 * 1024)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                b"eeeeeeeetttttaaaoinshrdlu"[(state % 25) as usize]
+            })
+            .collect();
+
+        // First pass builds the frequencies, the second streams the encoded bits
+        let mut freq_buffer = FrequencyBuffer([0; 256]);
+        for chunk in original.chunks(64 * 1024) {
+            freq_buffer.merge(&tally_frequency(chunk));
+        }
+        let huffman_array = build_huffman_array(&freq_buffer);
+        let mut encoder = HuffmanEncoder::new(encode_huffman_array(&huffman_array), Vec::new());
+        for chunk in original.chunks(64 * 1024) {
+            encoder.write_all(chunk).unwrap();
+        }
+        encoder.flush().unwrap();
+        let (bit_buffer, total_bits) = encoder.finish().unwrap();
+
+        let encoded_map = encode_huffman_array(&huffman_array);
+        let serialized = crate::serialize_huffman(&encoded_map, bit_buffer, total_bits);
+        assert_eq!(crate::deserialize_huffman(&serialized).unwrap(), original);
+    }
+
+    #[test]
+    fn encoder_matches_huff_encode_bitvec() {
+        let original = std::fs::read(FILE_PATH).unwrap();
+        let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(&original)));
+        let (expected_buffer, expected_bits) = huff_encode_bitvec(&original, &encoded_map).unwrap();
+
+        let mut encoder = HuffmanEncoder::new(encoded_map, Vec::new());
+        original
+            .chunks(333)
+            .for_each(|chunk| encoder.encode(chunk).unwrap());
+        let (bit_buffer, total_bits) = encoder.finish().unwrap();
+
+        assert_eq!(total_bits, expected_bits);
+        assert_eq!(bit_buffer, expected_buffer);
+    }
+
+    #[test]
+    fn stats_of_dna_file() {
+        let original = std::fs::read(FILE_PATH).unwrap();
+        let freq_buff = tally_frequency(&original);
+        let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(&original)));
+        let (_, expected_bits) = huff_encode_bitvec(&original, &encoded_map).unwrap();
+
+        let mut encoder = HuffmanEncoder::new(encoded_map, Vec::new());
+        assert_eq!(encoder.stats().avg_code_length, 0.0);
+        original
+            .chunks(1000)
+            .for_each(|chunk| encoder.encode(chunk).unwrap());
+        let stats = encoder.into_stats();
+
+        let most_frequent_count = *freq_buff.0.iter().max().unwrap();
+        let most_frequent_symbol = freq_buff
+            .0
+            .iter()
+            .position(|count| *count == most_frequent_count)
+            .unwrap() as u8;
+        assert_eq!(stats.input_bytes, original.len() as u64);
+        assert_eq!(stats.symbols_encoded, original.len() as u64);
+        assert_eq!(stats.output_bits, expected_bits);
+        assert_eq!(
+            stats.avg_code_length,
+            expected_bits as f64 / original.len() as f64
+        );
+        assert_eq!(stats.most_frequent_symbol, most_frequent_symbol);
+        assert_eq!(stats.most_frequent_count, most_frequent_count);
+    }
+
+    #[test]
+    fn seek_with_block_index() {
+        let original = std::fs::read(FILE_PATH).unwrap();
+        let block_size = 1000;
+        let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(&original)));
+
+        let mut index_bytes = Vec::new();
+        let mut encoder = HuffmanEncoder::with_index_writer(
+            encoded_map,
+            Vec::new(),
+            &mut index_bytes,
+            block_size,
+        );
+        encoder.encode(&original[..block_size * 2]).unwrap();
+        let (input_offset, split_offset) = encoder.split_point();
+        encoder.encode(&original[block_size * 2..]).unwrap();
+        let (bit_buffer, total_bits) = encoder.finish().unwrap();
+
+        let index = HuffBlockIndex::from_bytes(&index_bytes, block_size).unwrap();
+        assert_eq!(index.len(), original.len().div_ceil(block_size));
+        assert_eq!(index.bit_offset(0), Some(0));
+
+        let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(&original)));
+        let decoded = index
+            .decode_from(2, &bit_buffer, total_bits, &encoded_map)
+            .unwrap();
+        assert_eq!(decoded, original[block_size * 2..]);
+        assert_eq!(input_offset, (block_size * 2) as u64);
+        assert_eq!(index.bit_offset(2), Some(split_offset));
+    }
+
+    #[test]
+    fn decode_from_rejects_partial_code() {
+        let encoded_map = encode_huffman_array(b"abc");
+        let (bit_buffer, total_bits) = huff_encode_bitvec(b"cab", &encoded_map).unwrap();
+        let index = HuffBlockIndex::from_bytes(&[0; 8], 3).unwrap();
+
+        assert_eq!(
+            index
+                .decode_from(0, &bit_buffer, total_bits, &encoded_map)
+                .unwrap(),
+            b"cab"
+        );
+        // "00 1 01" cut after the first bit of the last code
+        assert!(matches!(
+            index.decode_from(0, &bit_buffer, total_bits - 1, &encoded_map),
+            Err(HuffErr::MalformedData(_))
+        ));
+    }
+
+    #[test]
+    fn resume_from_checkpoint() {
+        let original = std::fs::read(FILE_PATH).unwrap();
+        let block_size = 1000;
+        let encoded_map =
+            || encode_huffman_array(&build_huffman_array(&tally_frequency(&original)));
+        let (expected_buffer, expected_bits) =
+            huff_encode_bitvec(&original, &encoded_map()).unwrap();
+
+        let split = 2345;
+        let mut data = Vec::new();
+        let mut index_bytes = Vec::new();
+        let mut encoder = HuffmanEncoder::with_index_writer(
+            encoded_map(),
+            &mut data,
+            &mut index_bytes,
+            block_size,
+        );
+        encoder.encode(&original[..split]).unwrap();
+        let checkpoint = encoder.checkpoint();
+        drop(encoder);
+
+        let mut encoder =
+            HuffmanEncoder::resume_with_index_writer(&checkpoint, &mut data, &mut index_bytes)
+                .unwrap();
+        assert_eq!(encoder.input_bytes(), split as u64);
+        encoder.encode(&original[split..]).unwrap();
+        let (_, total_bits) = encoder.finish().unwrap();
+
+        assert_eq!(total_bits, expected_bits);
+        assert_eq!(data, expected_buffer);
+        let index = HuffBlockIndex::from_bytes(&index_bytes, block_size).unwrap();
+        assert_eq!(index.len(), original.len().div_ceil(block_size));
+
+        let mut tail = Vec::new();
+        let mut encoder = HuffmanEncoder::resume(&checkpoint, &mut tail).unwrap();
+        encoder.encode(&original[split..]).unwrap();
+        assert_eq!(encoder.finish().unwrap().1, expected_bits);
+    }
+
+    #[test]
+    fn resume_rejects_malformed_checkpoint() {
+        let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(b"abc")));
+        let mut encoder = HuffmanEncoder::new(encoded_map, Vec::new());
+        encoder.encode(b"abcab").unwrap();
+        let checkpoint = encoder.checkpoint();
+
+        assert!(HuffmanEncoder::resume(&checkpoint, Vec::new()).is_ok());
+        for len in 0..checkpoint.len() {
+            assert!(matches!(
+                HuffmanEncoder::resume(&checkpoint[..len], Vec::new()),
+                Err(HuffErr::MalformedData(_))
+            ));
+        }
+        let mut trailing = checkpoint.clone();
+        trailing.push(0);
+        assert!(HuffmanEncoder::resume(&trailing, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn encoder_rejects_unknown_byte() {
+        let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(b"ab")));
+        let mut encoder = HuffmanEncoder::new(encoded_map, Vec::new());
+
+        let err = encoder.encode(b"abc").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn dropping_an_unfinished_encoder_writes_the_last_byte() {
+        let original = b"abracadabra";
+        let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(original)));
+        let mut bit_buffer = Vec::new();
+        let mut encoder = HuffmanEncoder::new(encoded_map, &mut bit_buffer);
+        encoder.write_all(original).unwrap();
+        let total_bits = encoder.total_bits();
+        assert_ne!(total_bits % 8, 0);
+        drop(encoder);
+
+        assert_eq!(bit_buffer.len() as u64, total_bits.div_ceil(8));
+        let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(original)));
+        let serialized = crate::serialize_huffman(&encoded_map, bit_buffer, total_bits);
+        assert_eq!(crate::deserialize_huffman(&serialized).unwrap(), original);
+    }
+
+    #[test]
+    fn dropping_a_checkpointed_encoder_writes_nothing_more() {
+        let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(b"ab")));
+        let mut bit_buffer = Vec::new();
+        let mut encoder = HuffmanEncoder::new(encoded_map, &mut bit_buffer);
+        encoder.encode(b"a").unwrap();
+        let _checkpoint = encoder.checkpoint();
+        drop(encoder);
+
+        assert!(bit_buffer.is_empty());
+    }
+}
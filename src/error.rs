@@ -0,0 +1,446 @@
+//! Huffc Errors - Error Type Shared by the CLI and the Library
+//!
+//! `HuffErr` covers both invalid command-line arguments and failures of the library functions,
+//! such as malformed compressed data. It is re-exported at the crate root and from `cli`.
+//!
+//! Every variant has a stable negative integer code for C callers, `HuffErr::code` and
+//! `HuffErr::from_code` convert between the two. The `HUFFC_ERR_*` constants are plain `pub
+//! const` items, which `cbindgen` emits as `#define`s. Codes are never reused or renumbered, new
+//! variants get the next free code.
+//!
+use std::{error::Error, fmt::Display, io};
+
+pub const HUFFC_ERR_NO_ARGS: i32 = -1;
+pub const HUFFC_ERR_NO_FILE_PATH: i32 = -2;
+pub const HUFFC_ERR_FILE_DOES_NOT_EXIST: i32 = -3;
+pub const HUFFC_ERR_WRONG_FILE_EXTENSION: i32 = -4;
+pub const HUFFC_ERR_NO_OUTFILE_PROVIDED: i32 = -5;
+pub const HUFFC_ERR_NO_VALID_ARGS: i32 = -6;
+pub const HUFFC_ERR_COMPRESSION_FLAG: i32 = -7;
+pub const HUFFC_ERR_INVALID_PROBABILITIES: i32 = -8;
+pub const HUFFC_ERR_INVALID_FREQUENCY_TABLE: i32 = -9;
+pub const HUFFC_ERR_UNKNOWN_COMPRESSION_LEVEL: i32 = -10;
+pub const HUFFC_ERR_MALFORMED_DATA: i32 = -11;
+pub const HUFFC_ERR_SPLIT_SIZE_TOO_SMALL: i32 = -12;
+pub const HUFFC_ERR_INCOMPATIBLE_FLAGS: i32 = -13;
+pub const HUFFC_ERR_CORRUPTED_BITSTREAM: i32 = -14;
+pub const HUFFC_ERR_NULL_INPUT: i32 = -15;
+pub const HUFFC_ERR_DEPTH_CONSTRAINT_IMPOSSIBLE: i32 = -16;
+pub const HUFFC_ERR_UNKNOWN_CODEC: i32 = -17;
+pub const HUFFC_ERR_SYMBOL_NOT_IN_CODEBOOK: i32 = -18;
+pub const HUFFC_ERR_ARITHMETIC_OVERFLOW: i32 = -19;
+pub const HUFFC_ERR_CODEWORD_TOO_LONG: i32 = -20;
+pub const HUFFC_ERR_INVALID_HISTOGRAM: i32 = -21;
+pub const HUFFC_ERR_IO: i32 = -22;
+pub const HUFFC_ERR_INVALID_OUTPUT_EXTENSION: i32 = -23;
+pub const HUFFC_ERR_ROUND_TRIP_MISMATCH: i32 = -24;
+pub const HUFFC_ERR_NOT_A_HUFF_FILE: i32 = -25;
+pub const HUFFC_ERR_UNSUPPORTED_VERSION: i32 = -26;
+pub const HUFFC_ERR_OUTPUT_FILE_EXISTS: i32 = -27;
+pub const HUFFC_ERR_CHECKSUM_MISMATCH: i32 = -28;
+pub const HUFFC_ERR_FILE_NOT_IN_ARCHIVE: i32 = -29;
+pub const HUFFC_ERR_DUPLICATE_ARCHIVE_ENTRY: i32 = -30;
+
+/// Custom error type for handling argument validation and library errors.
+#[derive(Debug)]
+pub enum HuffErr {
+    /// No arguments were provided.
+    NoArgs,
+    /// No file path was provided.
+    NoFilePath,
+    /// The specified file does not exist, holds the path.
+    FileDoesNotExist(String),
+    /// The provided file has an incorrect extension, holds the path.
+    WrongFileExtension(String),
+    /// No output file was provided for stdin input.
+    NoOutfileProvided,
+    /// No valid arguments were provided.
+    NoValidArgs,
+    /// Both compression and decompression flags were set.
+    CompressionFlag,
+    /// The provided probabilities are invalid or do not sum to 1.0.
+    InvalidProbabilities,
+    /// The frequency table could not be parsed.
+    InvalidFrequencyTable(String),
+    /// The compressed data names a compression level that does not exist.
+    UnknownCompressionLevel(u8),
+    /// The compressed data is truncated or inconsistent.
+    MalformedData(String),
+    /// The split size cannot hold even a single compressed byte.
+    SplitSizeTooSmall(u64),
+    /// Two flags that cannot be used together were provided.
+    IncompatibleFlags(&'static str, &'static str),
+    /// The bit stream contains a code that matches no symbol.
+    CorruptedBitstream { bit_offset: u64 },
+    /// The named function received an empty input it cannot do anything meaningful with.
+    NullInput(&'static str),
+    /// The symbols do not fit in codes of at most `max_depth` bits.
+    DepthConstraintImpossible { max_depth: u8, num_symbols: usize },
+    /// No codebook is registered under the name.
+    UnknownCodec(String),
+    /// The data contains a byte the codebook has no code for.
+    SymbolNotInCodebook(u8),
+    /// An index computed from the compressed data overflowed.
+    ArithmeticOverflow,
+    /// The code of a symbol does not fit in the 64 bit codeword of the protobuf header.
+    CodewordTooLong { symbol: u8, code_length: u16 },
+    /// The normalized histogram has a negative or non-finite entry or does not sum to 1.0.
+    InvalidHistogram,
+    /// Reading or writing failed, the `io::Error` is the source.
+    Io(io::Error),
+    /// The `--output-extension` does not start with a `.` or has nothing after it.
+    InvalidOutputExtension(String),
+    /// Decompressing the compressed data did not give back the input, the bytes differ from
+    /// `first_diff_offset` on.
+    RoundTripMismatch { first_diff_offset: usize },
+    /// The buffer does not start with the `HUFF` magic bytes.
+    NotAHuffFile,
+    /// The format version after the magic bytes is not one this build can read.
+    UnsupportedVersion(u8),
+    /// The output file already exists and `--force` was not given, holds the path.
+    OutputFileExists(String),
+    /// The CRC-32 of the payload does not match the one in the header, the data is corrupted.
+    ChecksumMismatch { expected: u32, actual: u32 },
+    /// The archive has no file with the name.
+    FileNotInArchive(String),
+    /// Two files of an archive have the same name, holds the name.
+    DuplicateArchiveEntry(String),
+}
+
+impl HuffErr {
+    /// Stable integer code of the variant, one of the `HUFFC_ERR_*` constants.
+    pub fn code(&self) -> i32 {
+        match self {
+            HuffErr::NoArgs => HUFFC_ERR_NO_ARGS,
+            HuffErr::NoFilePath => HUFFC_ERR_NO_FILE_PATH,
+            HuffErr::FileDoesNotExist(_) => HUFFC_ERR_FILE_DOES_NOT_EXIST,
+            HuffErr::WrongFileExtension(_) => HUFFC_ERR_WRONG_FILE_EXTENSION,
+            HuffErr::NoOutfileProvided => HUFFC_ERR_NO_OUTFILE_PROVIDED,
+            HuffErr::NoValidArgs => HUFFC_ERR_NO_VALID_ARGS,
+            HuffErr::CompressionFlag => HUFFC_ERR_COMPRESSION_FLAG,
+            HuffErr::InvalidProbabilities => HUFFC_ERR_INVALID_PROBABILITIES,
+            HuffErr::InvalidFrequencyTable(_) => HUFFC_ERR_INVALID_FREQUENCY_TABLE,
+            HuffErr::UnknownCompressionLevel(_) => HUFFC_ERR_UNKNOWN_COMPRESSION_LEVEL,
+            HuffErr::MalformedData(_) => HUFFC_ERR_MALFORMED_DATA,
+            HuffErr::SplitSizeTooSmall(_) => HUFFC_ERR_SPLIT_SIZE_TOO_SMALL,
+            HuffErr::IncompatibleFlags(_, _) => HUFFC_ERR_INCOMPATIBLE_FLAGS,
+            HuffErr::CorruptedBitstream { .. } => HUFFC_ERR_CORRUPTED_BITSTREAM,
+            HuffErr::NullInput(_) => HUFFC_ERR_NULL_INPUT,
+            HuffErr::DepthConstraintImpossible { .. } => HUFFC_ERR_DEPTH_CONSTRAINT_IMPOSSIBLE,
+            HuffErr::UnknownCodec(_) => HUFFC_ERR_UNKNOWN_CODEC,
+            HuffErr::SymbolNotInCodebook(_) => HUFFC_ERR_SYMBOL_NOT_IN_CODEBOOK,
+            HuffErr::ArithmeticOverflow => HUFFC_ERR_ARITHMETIC_OVERFLOW,
+            HuffErr::CodewordTooLong { .. } => HUFFC_ERR_CODEWORD_TOO_LONG,
+            HuffErr::InvalidHistogram => HUFFC_ERR_INVALID_HISTOGRAM,
+            HuffErr::Io(_) => HUFFC_ERR_IO,
+            HuffErr::InvalidOutputExtension(_) => HUFFC_ERR_INVALID_OUTPUT_EXTENSION,
+            HuffErr::RoundTripMismatch { .. } => HUFFC_ERR_ROUND_TRIP_MISMATCH,
+            HuffErr::NotAHuffFile => HUFFC_ERR_NOT_A_HUFF_FILE,
+            HuffErr::UnsupportedVersion(_) => HUFFC_ERR_UNSUPPORTED_VERSION,
+            HuffErr::OutputFileExists(_) => HUFFC_ERR_OUTPUT_FILE_EXISTS,
+            HuffErr::ChecksumMismatch { .. } => HUFFC_ERR_CHECKSUM_MISMATCH,
+            HuffErr::FileNotInArchive(_) => HUFFC_ERR_FILE_NOT_IN_ARCHIVE,
+            HuffErr::DuplicateArchiveEntry(_) => HUFFC_ERR_DUPLICATE_ARCHIVE_ENTRY,
+        }
+    }
+
+    /// Variant with the integer `code`, `None` for unknown codes. The details carried by a
+    /// variant do not survive the conversion to a code, they are empty or zero.
+    pub fn from_code(code: i32) -> Option<HuffErr> {
+        let err = match code {
+            HUFFC_ERR_NO_ARGS => HuffErr::NoArgs,
+            HUFFC_ERR_NO_FILE_PATH => HuffErr::NoFilePath,
+            HUFFC_ERR_FILE_DOES_NOT_EXIST => HuffErr::FileDoesNotExist(String::new()),
+            HUFFC_ERR_WRONG_FILE_EXTENSION => HuffErr::WrongFileExtension(String::new()),
+            HUFFC_ERR_NO_OUTFILE_PROVIDED => HuffErr::NoOutfileProvided,
+            HUFFC_ERR_NO_VALID_ARGS => HuffErr::NoValidArgs,
+            HUFFC_ERR_COMPRESSION_FLAG => HuffErr::CompressionFlag,
+            HUFFC_ERR_INVALID_PROBABILITIES => HuffErr::InvalidProbabilities,
+            HUFFC_ERR_INVALID_FREQUENCY_TABLE => HuffErr::InvalidFrequencyTable(String::new()),
+            HUFFC_ERR_UNKNOWN_COMPRESSION_LEVEL => HuffErr::UnknownCompressionLevel(0),
+            HUFFC_ERR_MALFORMED_DATA => HuffErr::MalformedData(String::new()),
+            HUFFC_ERR_SPLIT_SIZE_TOO_SMALL => HuffErr::SplitSizeTooSmall(0),
+            HUFFC_ERR_INCOMPATIBLE_FLAGS => HuffErr::IncompatibleFlags("", ""),
+            HUFFC_ERR_CORRUPTED_BITSTREAM => HuffErr::CorruptedBitstream { bit_offset: 0 },
+            HUFFC_ERR_NULL_INPUT => HuffErr::NullInput(""),
+            HUFFC_ERR_DEPTH_CONSTRAINT_IMPOSSIBLE => HuffErr::DepthConstraintImpossible {
+                max_depth: 0,
+                num_symbols: 0,
+            },
+            HUFFC_ERR_UNKNOWN_CODEC => HuffErr::UnknownCodec(String::new()),
+            HUFFC_ERR_SYMBOL_NOT_IN_CODEBOOK => HuffErr::SymbolNotInCodebook(0),
+            HUFFC_ERR_ARITHMETIC_OVERFLOW => HuffErr::ArithmeticOverflow,
+            HUFFC_ERR_CODEWORD_TOO_LONG => HuffErr::CodewordTooLong {
+                symbol: 0,
+                code_length: 0,
+            },
+            HUFFC_ERR_INVALID_HISTOGRAM => HuffErr::InvalidHistogram,
+            HUFFC_ERR_IO => HuffErr::Io(io::ErrorKind::Other.into()),
+            HUFFC_ERR_INVALID_OUTPUT_EXTENSION => HuffErr::InvalidOutputExtension(String::new()),
+            HUFFC_ERR_ROUND_TRIP_MISMATCH => HuffErr::RoundTripMismatch {
+                first_diff_offset: 0,
+            },
+            HUFFC_ERR_NOT_A_HUFF_FILE => HuffErr::NotAHuffFile,
+            HUFFC_ERR_UNSUPPORTED_VERSION => HuffErr::UnsupportedVersion(0),
+            HUFFC_ERR_OUTPUT_FILE_EXISTS => HuffErr::OutputFileExists(String::new()),
+            HUFFC_ERR_CHECKSUM_MISMATCH => HuffErr::ChecksumMismatch {
+                expected: 0,
+                actual: 0,
+            },
+            HUFFC_ERR_FILE_NOT_IN_ARCHIVE => HuffErr::FileNotInArchive(String::new()),
+            HUFFC_ERR_DUPLICATE_ARCHIVE_ENTRY => HuffErr::DuplicateArchiveEntry(String::new()),
+            _ => return None,
+        };
+        Some(err)
+    }
+
+    /// Messages of the error and of every `Error::source` below it, separated by ` → `. A source
+    /// that repeats the message before it is skipped. `Io` displays the message of its source, so
+    /// its chain starts with `I/O error`, e.g. `"I/O error → No space left on device"`.
+    pub fn display_chain(&self) -> String {
+        let mut last = self.to_string();
+        let mut chain = match self {
+            HuffErr::Io(_) => format!("I/O error → {}", last),
+            _ => last.clone(),
+        };
+        let mut source = self.source();
+        while let Some(err) = source {
+            let message = err.to_string();
+            if message != last {
+                chain.push_str(" → ");
+                chain.push_str(&message);
+                last = message;
+            }
+            source = err.source();
+        }
+        chain
+    }
+}
+
+/// Implement the `Display` trait to provide user-friendly error messages.
+impl Display for HuffErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HuffErr::NoArgs => write!(f, "No arguments provided. Use --help for more information."),
+            HuffErr::FileDoesNotExist(path) => write!(
+                f,
+                "File does not exist in path provided: {}. Use --help for more information.",
+                path
+            ),
+            HuffErr::WrongFileExtension(path) => write!(
+                f,
+                "File {} does not have the file extension '.huff' or the one given with --output-extension. Use --help for more information.",
+                path
+            ),
+            HuffErr::CompressionFlag => write!(
+                f,
+                "Error: You must specify exactly one of --compress, --decompress, --info or --test.",
+            ),
+            HuffErr::NoFilePath => {
+                write!(f, "No file path provided. Use --help for more information.")
+            }
+            HuffErr::NoValidArgs => write!(
+                f,
+                "No valid arguments provided. Use --help for more information."
+            ),
+            HuffErr::NoOutfileProvided => write!(
+                f,
+                "No outfile path provided. Use --help for more information."
+            ),
+            HuffErr::InvalidProbabilities => write!(
+                f,
+                "Invalid probability distribution, probabilities must be non-negative and sum to 1.0."
+            ),
+            HuffErr::InvalidFrequencyTable(reason) => {
+                write!(f, "Invalid frequency table: {}", reason)
+            }
+            HuffErr::UnknownCompressionLevel(level) => {
+                write!(f, "Unknown compression level {} in compressed data.", level)
+            }
+            HuffErr::MalformedData(reason) => write!(f, "Malformed compressed data: {}", reason),
+            HuffErr::SplitSizeTooSmall(size) => write!(
+                f,
+                "Split size of {} bytes is too small to hold a compressed part.",
+                size
+            ),
+            HuffErr::CorruptedBitstream { bit_offset } => {
+                write!(f, "Corrupted bit stream at bit offset {}.", bit_offset)
+            }
+            HuffErr::NullInput(function) => write!(f, "{} received an empty input.", function),
+            HuffErr::DepthConstraintImpossible {
+                max_depth,
+                num_symbols,
+            } => write!(
+                f,
+                "{} symbols cannot be encoded with codes of at most {} bits.",
+                num_symbols, max_depth
+            ),
+            HuffErr::UnknownCodec(name) => write!(f, "No codebook registered as '{}'.", name),
+            HuffErr::SymbolNotInCodebook(symbol) => {
+                write!(f, "Byte {:#04x} has no code in the codebook.", symbol)
+            }
+            HuffErr::ArithmeticOverflow => {
+                write!(f, "Arithmetic overflow while decoding the compressed data.")
+            }
+            HuffErr::CodewordTooLong {
+                symbol,
+                code_length,
+            } => write!(
+                f,
+                "The {} bit code of byte {:#04x} does not fit in a 64 bit codeword.",
+                code_length, symbol
+            ),
+            HuffErr::InvalidHistogram => write!(
+                f,
+                "Invalid histogram, entries must be non-negative and sum to 1.0."
+            ),
+            HuffErr::Io(err) => err.fmt(f),
+            HuffErr::RoundTripMismatch { first_diff_offset } => write!(
+                f,
+                "Round trip mismatch, the decompressed data differs from the input at byte {}.",
+                first_diff_offset
+            ),
+            HuffErr::InvalidOutputExtension(extension) => write!(
+                f,
+                "Output extension '{}' must start with '.', e.g. '.huf'.",
+                extension
+            ),
+            HuffErr::NotAHuffFile => write!(f, "Not a huff file, the magic bytes are missing."),
+            HuffErr::UnsupportedVersion(version) => {
+                write!(f, "Unsupported huff format version {}.", version)
+            }
+            HuffErr::OutputFileExists(path) => write!(
+                f,
+                "Output file {} already exists, use -f to overwrite.",
+                path
+            ),
+            HuffErr::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "Checksum mismatch, expected {:#010x} but the data has {:#010x}, it is corrupted.",
+                expected, actual
+            ),
+            HuffErr::FileNotInArchive(name) => write!(f, "The archive has no file '{}'.", name),
+            HuffErr::DuplicateArchiveEntry(name) => {
+                write!(f, "The archive already has a file '{}'.", name)
+            }
+            HuffErr::IncompatibleFlags(a, b) => write!(
+                f,
+                "{} cannot be used together with {}. Use --help for more information.",
+                a, b
+            ),
+        }
+    }
+}
+
+/// Implement the `Error` trait for `HuffErr` to allow integration with Rust's error handling.
+impl Error for HuffErr {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            HuffErr::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for HuffErr {
+    fn from(err: io::Error) -> Self {
+        HuffErr::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_code_round_trips() {
+        let variants = [
+            HuffErr::NoArgs,
+            HuffErr::NoFilePath,
+            HuffErr::FileDoesNotExist("missing.txt".to_string()),
+            HuffErr::WrongFileExtension("file.txt".to_string()),
+            HuffErr::NoOutfileProvided,
+            HuffErr::NoValidArgs,
+            HuffErr::CompressionFlag,
+            HuffErr::InvalidProbabilities,
+            HuffErr::InvalidFrequencyTable("bad json".to_string()),
+            HuffErr::UnknownCompressionLevel(7),
+            HuffErr::MalformedData("truncated".to_string()),
+            HuffErr::SplitSizeTooSmall(3),
+            HuffErr::IncompatibleFlags("--a", "--b"),
+            HuffErr::CorruptedBitstream { bit_offset: 9 },
+            HuffErr::NullInput("f"),
+            HuffErr::DepthConstraintImpossible {
+                max_depth: 2,
+                num_symbols: 5,
+            },
+            HuffErr::UnknownCodec("x".to_string()),
+            HuffErr::SymbolNotInCodebook(1),
+            HuffErr::ArithmeticOverflow,
+            HuffErr::CodewordTooLong {
+                symbol: 1,
+                code_length: 70,
+            },
+            HuffErr::InvalidHistogram,
+            HuffErr::Io(io::ErrorKind::Other.into()),
+            HuffErr::InvalidOutputExtension(".".to_string()),
+            HuffErr::RoundTripMismatch {
+                first_diff_offset: 3,
+            },
+            HuffErr::NotAHuffFile,
+            HuffErr::UnsupportedVersion(2),
+            HuffErr::OutputFileExists("file.txt".to_string()),
+            HuffErr::ChecksumMismatch {
+                expected: 1,
+                actual: 2,
+            },
+            HuffErr::FileNotInArchive("a.txt".to_string()),
+            HuffErr::DuplicateArchiveEntry("a.txt".to_string()),
+        ];
+
+        for (idx, err) in variants.iter().enumerate() {
+            // Codes are dense, so a variant missing from this list leaves a gap
+            assert_eq!(err.code(), -(idx as i32) - 1);
+            let decoded = HuffErr::from_code(err.code()).unwrap();
+            assert_eq!(decoded.code(), err.code());
+            assert_eq!(
+                std::mem::discriminant(&decoded),
+                std::mem::discriminant(err)
+            );
+        }
+        assert!(HuffErr::from_code(0).is_none());
+        assert!(HuffErr::from_code(-(variants.len() as i32) - 1).is_none());
+    }
+
+    #[test]
+    fn io_errors_display_and_chain_the_wrapped_error() {
+        fn read_missing() -> Result<Vec<u8>, HuffErr> {
+            Ok(std::fs::read("./tests/resources/does_not_exist.txt")?)
+        }
+        // `?` converts the io::Error
+        let err = read_missing().unwrap_err();
+        assert!(matches!(&err, HuffErr::Io(inner) if inner.kind() == io::ErrorKind::NotFound));
+        assert_eq!(err.code(), HUFFC_ERR_IO);
+
+        let io_err = io::Error::other("No space left on device");
+        let err = HuffErr::from(io_err);
+        assert_eq!(err.to_string(), "No space left on device");
+        let source = err.source().unwrap().downcast_ref::<io::Error>().unwrap();
+        assert_eq!(source.kind(), io::ErrorKind::Other);
+    }
+
+    #[test]
+    fn display_chain_follows_sources() {
+        let err = HuffErr::from(io::Error::other("No space left on device"));
+        assert_eq!(err.display_chain(), "I/O error → No space left on device");
+
+        // Custom io errors display their payload and continue with its source
+        let nested = HuffErr::Io(io::Error::other(HuffErr::Io(io::Error::other("disk full"))));
+        assert_eq!(nested.display_chain(), "I/O error → disk full");
+
+        assert_eq!(
+            HuffErr::ArithmeticOverflow.display_chain(),
+            HuffErr::ArithmeticOverflow.to_string()
+        );
+    }
+}
@@ -0,0 +1,363 @@
+//! Huffc Frame - Self-Synchronizing Frames for Lossy Channels
+//!
+//! `HuffmanFrameEncoder` splits the encoded bit stream into frames of at most `max_frame_bytes`
+//! of compressed data, each starting at a symbol boundary so it can be decoded on its own. Every
+//! frame starts with the sync marker `FF 00 FF 53`. Inside a frame every `0xFF` byte is written
+//! as `FF 01`, so `FF 00`, and with it the marker, never appears outside a marker. After lost or
+//! corrupted bytes, `HuffmanFrameDecoder` drops the damaged frame and resumes at the next marker.
+//!
+//! A frame is the marker followed by the escaped frame body: the number of bits (4 bytes), the
+//! bit buffer and a CRC-32 of the two (4 bytes).
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use huffc::{
+//!     build_huffman_array, encode_huffman_array,
+//!     frame::{HuffmanFrameDecoder, HuffmanFrameEncoder},
+//!     tally_frequency,
+//! };
+//!
+//! let bytes = b"abracadabra abracadabra abracadabra";
+//! let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(bytes)));
+//!
+//! let mut encoder = HuffmanFrameEncoder::new(&encoded_map, Vec::new(), 64);
+//! encoder.encode(bytes).unwrap();
+//! let framed = encoder.finish().unwrap();
+//!
+//! let mut decoder = HuffmanFrameDecoder::new(&encoded_map, &framed[..]).unwrap();
+//! assert_eq!(decoder.decode_all().unwrap(), bytes);
+//! assert_eq!(decoder.frames_skipped(), 0);
+//! ```
+//!
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+};
+
+use bitvec::{order::Msb0, slice::BitSlice, vec::BitVec};
+
+use crate::{decoder::DecodeTrie, u8_to_u64, Encoded, HuffErr};
+
+/// Marker at the start of every frame.
+pub const SYNC_MARKER: [u8; 4] = [0xFF, 0x00, 0xFF, 0x53];
+/// Byte that follows an escaped `0xFF` inside a frame.
+const ESCAPED_FF: u8 = 0x01;
+/// Smallest frame size, fits the longest possible code of 255 bits.
+const MIN_FRAME_BYTES: usize = 32;
+/// Number of bytes read at a time while looking for the end of a frame.
+const READ_CHUNK: usize = 4096;
+
+const CRC_32: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+/// Encodes bytes into self-synchronizing frames written to `W`.
+pub struct HuffmanFrameEncoder<'a, W: Write> {
+    writer: W,
+    encoded_map: &'a HashMap<u8, Encoded>,
+    /// Bits of the current frame
+    bits: BitVec<u8, Msb0>,
+    max_frame_bits: usize,
+}
+
+impl<'a, W: Write> HuffmanFrameEncoder<'a, W> {
+    /// Creates an encoder whose frames hold at most `max_frame_bytes` of compressed data, at
+    /// least 32.
+    pub fn new(encoded_map: &'a HashMap<u8, Encoded>, writer: W, max_frame_bytes: usize) -> Self {
+        Self {
+            writer,
+            encoded_map,
+            bits: BitVec::new(),
+            max_frame_bits: max_frame_bytes.max(MIN_FRAME_BYTES) * 8,
+        }
+    }
+
+    /// Encodes `bytes`, writing every frame that fills up.
+    ///
+    /// # Errors
+    ///
+    /// Returns `io::ErrorKind::InvalidInput` if a byte has no encoding, the bytes before it are
+    /// encoded.
+    pub fn encode(&mut self, bytes: &[u8]) -> io::Result<()> {
+        for byte in bytes {
+            let encoded = self.encoded_map.get(byte).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("no encoding for byte {:#04x}", byte),
+                )
+            })?;
+            if self.bits.len() + encoded.bits.len() > self.max_frame_bits {
+                self.write_frame()?;
+            }
+            self.bits.extend_from_bitslice(&encoded.bits);
+        }
+        Ok(())
+    }
+
+    /// Writes the last frame and flushes the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.bits.is_empty() {
+            self.write_frame()?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_frame(&mut self) -> io::Result<()> {
+        let num_bits = self.bits.len() as u32;
+        let mut bits = std::mem::take(&mut self.bits);
+        bits.set_uninitialized(false);
+
+        let mut body = num_bits.to_be_bytes().to_vec();
+        body.extend_from_slice(bits.as_raw_slice());
+        body.extend_from_slice(&CRC_32.checksum(&body).to_be_bytes());
+
+        let mut frame = SYNC_MARKER.to_vec();
+        for byte in body {
+            frame.push(byte);
+            if byte == 0xFF {
+                frame.push(ESCAPED_FF);
+            }
+        }
+        self.writer.write_all(&frame)
+    }
+}
+
+/// Decodes the frames written by `HuffmanFrameEncoder` from `R`, skipping damaged frames.
+pub struct HuffmanFrameDecoder<R: Read> {
+    reader: R,
+    trie: DecodeTrie,
+    /// Bytes read but not yet decoded
+    buffer: Vec<u8>,
+    eof: bool,
+    frames_skipped: u64,
+}
+
+impl<R: Read> HuffmanFrameDecoder<R> {
+    /// Creates a decoder for the codes in `encoded_map`.
+    ///
+    /// # Errors
+    ///
+    /// * `HuffErr::NullInput` - `encoded_map` is empty.
+    /// * `HuffErr::MalformedData` - The codes are not prefix free.
+    pub fn new(encoded_map: &HashMap<u8, Encoded>, reader: R) -> Result<Self, HuffErr> {
+        if encoded_map.is_empty() {
+            return Err(HuffErr::NullInput("HuffmanFrameDecoder::new"));
+        }
+        Ok(Self {
+            reader,
+            trie: DecodeTrie::new(encoded_map)?,
+            buffer: Vec::new(),
+            eof: false,
+            frames_skipped: 0,
+        })
+    }
+
+    /// Number of damaged frames dropped so far.
+    pub fn frames_skipped(&self) -> u64 {
+        self.frames_skipped
+    }
+
+    /// Decodes the next frame, bytes before its marker are discarded.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(Ok(bytes)))` - The decoded frame.
+    /// * `Ok(Some(Err(HuffErr::MalformedData)))` - The frame is damaged and was dropped.
+    /// * `Ok(None)` - There are no more frames.
+    pub fn next_frame(&mut self) -> io::Result<Option<Result<Vec<u8>, HuffErr>>> {
+        let Some(start) = self.find_marker(0)? else {
+            self.buffer.clear();
+            return Ok(None);
+        };
+        self.buffer.drain(..start);
+
+        let end = self
+            .find_marker(SYNC_MARKER.len())?
+            .unwrap_or(self.buffer.len());
+        let frame: Vec<u8> = self.buffer.drain(..end).collect();
+
+        let decoded = self.decode_frame(&frame[SYNC_MARKER.len()..]);
+        if decoded.is_err() {
+            self.frames_skipped += 1;
+        }
+        Ok(Some(decoded))
+    }
+
+    /// Decodes every remaining frame, dropping damaged ones, see `frames_skipped`.
+    pub fn decode_all(&mut self) -> io::Result<Vec<u8>> {
+        let mut decoded = Vec::new();
+        while let Some(frame) = self.next_frame()? {
+            if let Ok(frame) = frame {
+                decoded.extend(frame);
+            }
+        }
+        Ok(decoded)
+    }
+
+    /// Position of the first marker at or after `from`, reading more input as needed.
+    fn find_marker(&mut self, from: usize) -> io::Result<Option<usize>> {
+        let mut searched = from;
+        loop {
+            if let Some(pos) = self.buffer.get(searched..).and_then(|rest| {
+                rest.windows(SYNC_MARKER.len())
+                    .position(|window| window == SYNC_MARKER)
+            }) {
+                return Ok(Some(searched + pos));
+            }
+            if self.eof {
+                return Ok(None);
+            }
+            // A marker may straddle the old end of the buffer
+            searched = self
+                .buffer
+                .len()
+                .saturating_sub(SYNC_MARKER.len() - 1)
+                .max(from);
+
+            let len = self.buffer.len();
+            self.buffer.resize(len + READ_CHUNK, 0);
+            let read = loop {
+                match self.reader.read(&mut self.buffer[len..]) {
+                    Ok(read) => break read,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                    Err(e) => {
+                        self.buffer.truncate(len);
+                        return Err(e);
+                    }
+                }
+            };
+            self.buffer.truncate(len + read);
+            self.eof = read == 0;
+        }
+    }
+
+    fn decode_frame(&self, escaped: &[u8]) -> Result<Vec<u8>, HuffErr> {
+        let damaged = |reason: &str| HuffErr::MalformedData(format!("damaged frame, {}", reason));
+
+        let mut body = Vec::with_capacity(escaped.len());
+        let mut bytes = escaped.iter();
+        while let Some(byte) = bytes.next() {
+            body.push(*byte);
+            if *byte == 0xFF && bytes.next() != Some(&ESCAPED_FF) {
+                return Err(damaged("invalid escape"));
+            }
+        }
+        if body.len() < 8 {
+            return Err(damaged("truncated"));
+        }
+
+        let (content, crc) = body.split_at(body.len() - 4);
+        if CRC_32.checksum(content).to_be_bytes() != crc {
+            return Err(damaged("checksum mismatch"));
+        }
+        let mut num_bits = [0; 8];
+        num_bits[4..].copy_from_slice(&content[..4]);
+        let num_bits = u8_to_u64(&num_bits) as usize;
+        let bits = BitSlice::<u8, Msb0>::from_slice(&content[4..]);
+        if num_bits > bits.len() {
+            return Err(damaged("truncated bit buffer"));
+        }
+
+        let mut decoded = Vec::new();
+        let mut read_bits = 0;
+        while read_bits < num_bits {
+            match self.trie.decode(&bits[read_bits..num_bits]) {
+                Ok(Some((symbol, code_len))) => {
+                    decoded.push(symbol);
+                    read_bits += code_len;
+                }
+                _ => return Err(damaged("invalid code")),
+            }
+        }
+        Ok(decoded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{build_huffman_array, encode_huffman_array, tally_frequency};
+
+    fn frame(original: &[u8], max_frame_bytes: usize) -> (HashMap<u8, Encoded>, Vec<u8>) {
+        let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(original)));
+        let mut encoder = HuffmanFrameEncoder::new(&encoded_map, Vec::new(), max_frame_bytes);
+        original
+            .chunks(100)
+            .for_each(|chunk| encoder.encode(chunk).unwrap());
+        let framed = encoder.finish().unwrap();
+        (encoded_map, framed)
+    }
+
+    fn marker_positions(framed: &[u8]) -> Vec<usize> {
+        framed
+            .windows(SYNC_MARKER.len())
+            .enumerate()
+            .filter(|(_, window)| *window == SYNC_MARKER)
+            .map(|(pos, _)| pos)
+            .collect()
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let original = std::fs::read("./tests/resources/dna_seq_test.txt").unwrap();
+        let (encoded_map, framed) = frame(&original, 64);
+
+        let markers = marker_positions(&framed);
+        assert!(markers.len() > 10);
+        // The marker only appears at frame starts, every frame holds at most 64 bytes of data
+        for (start, end) in markers.iter().zip(markers[1..].iter()) {
+            assert!(end - start <= SYNC_MARKER.len() + 2 * (64 + 8));
+        }
+
+        let mut decoder = HuffmanFrameDecoder::new(&encoded_map, &framed[..]).unwrap();
+        assert_eq!(decoder.decode_all().unwrap(), original);
+        assert_eq!(decoder.frames_skipped(), 0);
+    }
+
+    #[test]
+    fn recover_after_one_byte_erasure() {
+        let original = std::fs::read("./tests/resources/dna_seq_test.txt").unwrap();
+        let (encoded_map, mut framed) = frame(&original, 64);
+        let markers = marker_positions(&framed);
+        let num_frames = markers.len();
+
+        // Erase a byte in the middle of a frame in the middle of the stream
+        framed.remove(markers[num_frames / 2] + 10);
+
+        let mut decoder = HuffmanFrameDecoder::new(&encoded_map, &framed[..]).unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.next_frame().unwrap() {
+            frames.push(frame);
+        }
+        assert_eq!(frames.len(), num_frames);
+        assert_eq!(decoder.frames_skipped(), 1);
+        assert!(frames[num_frames / 2].is_err());
+
+        // Everything but the damaged frame is recovered
+        let decoded: Vec<u8> = frames.into_iter().flatten().flatten().collect();
+        let prefix = decoded
+            .iter()
+            .zip(&original)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let suffix = decoded
+            .iter()
+            .rev()
+            .zip(original.iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        assert!(prefix + suffix >= decoded.len());
+        assert!(original.len() - decoded.len() <= 64 * 8);
+    }
+
+    #[test]
+    fn garbage_before_the_first_marker_is_skipped() {
+        let original = b"mississippi mississippi";
+        let (encoded_map, framed) = frame(original, 32);
+        let mut noisy = vec![0x00, 0xFF, 0x00, 0xFF];
+        noisy.extend(framed);
+
+        let mut decoder = HuffmanFrameDecoder::new(&encoded_map, &noisy[..]).unwrap();
+        assert_eq!(decoder.decode_all().unwrap(), original);
+    }
+}
@@ -7,29 +7,48 @@
 //! - Uses `memmap2` for efficient file I/O operations.
 //! - Supports reading and writing large files efficiently.
 //! - Ensures safe memory mapping with flush operations.
+//! - On Linux, reads large files with `O_DIRECT` to keep them out of the page cache.
+//! - `ReadAheadBuffer` reads the next chunks on a background thread while the current one is
+//!   processed.
+//! - `decompress_file` decodes straight from the memory-mapped file, without copying it.
 //!
 //! ## Usage
 //!
 //! - Writing to a file:
 //!   ```rust
 //!   use huffc::fs::write_file;
-//!   write_file("output.huff", vec![1, 2, 3, 4, 5]);
+//!   write_file("output.huff", vec![1, 2, 3, 4, 5]).unwrap();
 //!   ```
 //!
 //! - Reading from a file:
 //!   ```rust
 //!   use huffc::fs::read_file;
-//!   let data = read_file("./tests/resources/input.txt");
+//!   let data = read_file("./tests/resources/input.txt").unwrap();
 //!   println!("File contents: {:?}", &data[..]);
 //!   ```
 //!
+//! - Reading from a file with progress reporting:
+//!   ```rust
+//!   use huffc::fs::read_file_with_progress;
+//!   let data = read_file_with_progress("./tests/resources/input.txt", |read, total| {
+//!       println!("{} / {} bytes", read, total);
+//!   })
+//!   .unwrap();
+//!   ```
+//!
 use std::{
     fs::{File, OpenOptions},
+    io::{self, BufReader, Read},
+    ops::Deref,
     path::Path,
+    sync::mpsc::{self, Receiver},
+    thread::{self, JoinHandle},
 };
 
 use memmap2::{Mmap, MmapMut};
 
+use crate::level::decompress_level;
+
 /// Writes a buffer to a file using memory-mapped I/O.
 ///
 /// # Arguments
@@ -40,23 +59,50 @@ use memmap2::{Mmap, MmapMut};
 /// This function creates or truncates the file, maps it into memory,
 /// and writes the data efficiently before flushing changes to disk.
 ///
-pub fn write_file<P: AsRef<Path>>(path: P, buffer: Vec<u8>) {
+/// # Errors
+///
+/// Returns the error of creating, resizing, mapping or flushing the file, e.g. if its directory
+/// does not exist or the disk is full.
+pub fn write_file<P: AsRef<Path>>(path: P, buffer: Vec<u8>) -> io::Result<()> {
     let file = OpenOptions::new()
         .read(true)
         .write(true)
         .create(true)
         .truncate(true)
-        .open(path)
-        .unwrap();
+        .open(path)?;
 
-    file.set_len(buffer.len() as u64).unwrap();
+    file.set_len(buffer.len() as u64)?;
 
-    let mut mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
+    let mut mmap = unsafe { MmapMut::map_mut(&file)? };
     mmap[..].copy_from_slice(&buffer);
-    mmap.flush().unwrap();
+    mmap.flush()
+}
+
+/// Files of at least this many bytes are read with `O_DIRECT` on Linux.
+pub const DIRECT_IO_THRESHOLD: u64 = 256 * 1024 * 1024;
+
+/// Contents of a file read by `read_file`.
+pub enum FileBuffer {
+    /// The file mapped into memory.
+    Mapped(Mmap),
+    /// The file read with `O_DIRECT`, bypassing the page cache.
+    #[cfg(target_os = "linux")]
+    Direct(DirectBuffer),
+}
+
+impl Deref for FileBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            FileBuffer::Mapped(mmap) => mmap,
+            #[cfg(target_os = "linux")]
+            FileBuffer::Direct(buffer) => buffer,
+        }
+    }
 }
 
-/// Reads a file into a memory-mapped buffer.
+/// Reads a file into memory.
 ///
 /// # Arguments
 ///
@@ -64,13 +110,370 @@ pub fn write_file<P: AsRef<Path>>(path: P, buffer: Vec<u8>) {
 ///
 /// # Returns
 ///
-/// * `Mmap` - Memory-mapped buffer containing the file contents.
+/// * `FileBuffer` - Buffer containing the file contents.
 ///
-/// This function opens a file and maps its contents into memory,
-/// allowing efficient access without excessive system calls.
+/// Files smaller than `DIRECT_IO_THRESHOLD` are mapped into memory, allowing efficient access
+/// without excessive system calls. On Linux larger files are read with `O_DIRECT` so a single
+/// sequential pass does not evict the page cache of other processes, falling back to a memory
+/// map if the filesystem does not support it.
 ///
-pub fn read_file<P: AsRef<Path>>(path: P) -> Mmap {
-    let file = File::open(path).unwrap();
+/// # Errors
+///
+/// Returns the error of opening or mapping the file.
+pub fn read_file<P: AsRef<Path>>(path: P) -> io::Result<FileBuffer> {
+    #[cfg(target_os = "linux")]
+    {
+        let len = std::fs::metadata(path.as_ref()).map(|metadata| metadata.len());
+        if matches!(len, Ok(len) if len >= DIRECT_IO_THRESHOLD) {
+            if let Ok(buffer) = read_file_direct(path.as_ref()) {
+                return Ok(FileBuffer::Direct(buffer));
+            }
+        }
+    }
+
+    let file = File::open(path)?;
+
+    Ok(FileBuffer::Mapped(unsafe { Mmap::map(&file) }?))
+}
+
+/// Decompresses a file written by `huffc`, decoding from the memory-mapped file. The compressed
+/// bytes are read in place, only the decompressed output is allocated.
+///
+/// # Errors
+///
+/// Returns the error of opening or mapping the file, or an `io::ErrorKind::InvalidData` error
+/// wrapping the `HuffErr` of `decompress_level` if the contents are not a valid buffer.
+pub fn decompress_file<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
+    let file = File::open(path)?;
+    let mmap = unsafe { Mmap::map(&file) }?;
+    decompress_level(&mmap).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Alignment of the buffer, offsets and lengths of `O_DIRECT` reads.
+#[cfg(target_os = "linux")]
+const DIRECT_IO_ALIGNMENT: usize = 4096;
+
+/// Size of a single `O_DIRECT` read.
+#[cfg(target_os = "linux")]
+const DIRECT_IO_CHUNK: usize = 8 * 1024 * 1024;
+
+/// File contents read with `O_DIRECT`, an aligned window into an over-allocated buffer.
+#[cfg(target_os = "linux")]
+pub struct DirectBuffer {
+    storage: Vec<u8>,
+    offset: usize,
+    len: usize,
+}
+
+#[cfg(target_os = "linux")]
+impl Deref for DirectBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.storage[self.offset..self.offset + self.len]
+    }
+}
+
+/// Reads a file with `O_DIRECT`, bypassing the page cache.
+///
+/// # Errors
+///
+/// Returns the error of opening or reading the file, `O_DIRECT` is not supported by every
+/// filesystem (e.g. `tmpfs`).
+#[cfg(target_os = "linux")]
+pub fn read_file_direct<P: AsRef<Path>>(path: P) -> Result<DirectBuffer, io::Error> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_DIRECT)
+        .open(path)?;
+    let len = file.metadata()?.len() as usize;
+
+    // Every read must start at an aligned address and cover whole blocks, the last one returns
+    // the remainder of the file
+    let aligned_len = len.next_multiple_of(DIRECT_IO_ALIGNMENT);
+    let mut storage = vec![0u8; aligned_len + DIRECT_IO_ALIGNMENT];
+    let offset = storage.as_ptr().align_offset(DIRECT_IO_ALIGNMENT);
+    let buffer = &mut storage[offset..offset + aligned_len];
+
+    let mut read = 0;
+    while read < aligned_len {
+        let end = (read + DIRECT_IO_CHUNK).min(aligned_len);
+        match file.read(&mut buffer[read..end]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(DirectBuffer {
+        storage,
+        offset,
+        len: read.min(len),
+    })
+}
+
+/// Number of bytes between two progress notifications of `ReadProgress`.
+pub const PROGRESS_INTERVAL: u64 = 1024 * 1024;
+
+/// Reader wrapper that reports progress while reading.
+///
+/// The callback receives `(bytes_read, total_bytes)` every `PROGRESS_INTERVAL` bytes and once
+/// more when the inner reader reaches the end.
+pub struct ReadProgress<R: Read> {
+    inner: R,
+    total: u64,
+    read: u64,
+    last_reported: u64,
+    callback: Box<dyn FnMut(u64, u64)>,
+}
+
+impl<R: Read> ReadProgress<R> {
+    /// Wraps `inner`, `total` is the expected number of bytes passed to the callback.
+    pub fn new<F: FnMut(u64, u64) + 'static>(inner: R, total: u64, callback: F) -> Self {
+        Self {
+            inner,
+            total,
+            read: 0,
+            last_reported: 0,
+            callback: Box::new(callback),
+        }
+    }
+
+    /// Number of bytes read so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.read
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for ReadProgress<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+
+        let reached_end = n == 0 && !buf.is_empty();
+        let crossed_interval =
+            self.read / PROGRESS_INTERVAL > self.last_reported / PROGRESS_INTERVAL;
+        if crossed_interval || (reached_end && self.last_reported != self.read) {
+            self.last_reported = self.read;
+            (self.callback)(self.read, self.total);
+        }
+        Ok(n)
+    }
+}
+
+/// Reads a file into memory through a buffered reader, reporting progress.
+///
+/// # Arguments
+///
+/// * `path` - Path to the file to be read.
+/// * `cb` - Called with `(bytes_read, total_bytes)` every `PROGRESS_INTERVAL` bytes and at the end.
+///
+/// # Returns
+///
+/// * `Result<Vec<u8>, io::Error>` - The file contents, or the error that stopped the read.
+///
+/// Unlike `read_file` this does not memory map the file, which suits network backed filesystems
+/// where progress is worth reporting.
+///
+pub fn read_file_with_progress<P, F>(path: P, cb: F) -> Result<Vec<u8>, io::Error>
+where
+    P: AsRef<Path>,
+    F: FnMut(u64, u64) + 'static,
+{
+    let file = File::open(path)?;
+    let total = file.metadata()?.len();
+
+    let mut reader = ReadProgress::new(BufReader::new(file), total, cb);
+    let mut buffer = Vec::with_capacity(total as usize);
+    reader.read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Iterator over the chunks of a reader that are read ahead on a background thread, so reading
+/// the next chunks overlaps with processing the current one.
+///
+/// Every chunk is `chunk_size` bytes except the last, which holds the rest. At most
+/// `queue_depth` chunks are read ahead. Iteration ends after the last chunk or the first error.
+pub struct ReadAheadBuffer {
+    chunks: Receiver<io::Result<Vec<u8>>>,
+    reader_thread: Option<JoinHandle<()>>,
+}
+
+impl ReadAheadBuffer {
+    /// Starts reading `reader` on a background thread. A `chunk_size` or `queue_depth` of 0 is
+    /// treated as 1.
+    pub fn new<R: Read + Send + 'static>(
+        mut reader: R,
+        chunk_size: usize,
+        queue_depth: usize,
+    ) -> Self {
+        let chunk_size = chunk_size.max(1);
+        let (sender, chunks) = mpsc::sync_channel(queue_depth.max(1));
+        let reader_thread = thread::spawn(move || loop {
+            let chunk = read_chunk(&mut reader, chunk_size);
+            let last = !matches!(&chunk, Ok(chunk) if chunk.len() == chunk_size);
+            if matches!(&chunk, Ok(chunk) if chunk.is_empty()) {
+                return;
+            }
+            // The receiver was dropped, nobody wants the rest
+            if sender.send(chunk).is_err() || last {
+                return;
+            }
+        });
+        Self {
+            chunks,
+            reader_thread: Some(reader_thread),
+        }
+    }
+}
+
+/// Reads until `chunk_size` bytes or the end of `reader`.
+fn read_chunk<R: Read>(reader: &mut R, chunk_size: usize) -> io::Result<Vec<u8>> {
+    let mut chunk = Vec::with_capacity(chunk_size);
+    reader.take(chunk_size as u64).read_to_end(&mut chunk)?;
+    Ok(chunk)
+}
+
+impl Iterator for ReadAheadBuffer {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.chunks.recv() {
+            Ok(chunk) => Some(chunk),
+            Err(_) => {
+                if let Some(reader_thread) = self.reader_thread.take() {
+                    if let Err(panic) = reader_thread.join() {
+                        std::panic::resume_unwind(panic);
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn write_file_and_read_file_return_errors() {
+        let dir = std::env::temp_dir().join(format!("huffc-missing-{}", std::process::id()));
+        assert!(write_file(dir.join("output.huff"), vec![1, 2, 3]).is_err());
+        assert_eq!(
+            read_file(dir.join("input.txt")).err().map(|e| e.kind()),
+            Some(io::ErrorKind::NotFound)
+        );
+
+        // A directory cannot be opened for writing, even by root, unlike a read-only file
+        assert!(write_file(std::env::temp_dir(), vec![1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn decompress_file_decodes_from_the_mapping() {
+        let original = std::fs::read("./tests/resources/dna_seq_test.txt").unwrap();
+        let path = std::env::temp_dir().join(format!("huffc-mmap-{}.huff", std::process::id()));
+        write_file(&path, crate::compress(&original).unwrap()).unwrap();
+
+        let decompressed = decompress_file(&path);
+        write_file(&path, vec![0xEE, 1, 2]).unwrap();
+        let invalid = decompress_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(decompressed.unwrap(), original);
+        assert_eq!(invalid.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(decompress_file(&path).is_err());
+    }
+
+    #[test]
+    fn read_progress_reports_every_interval() {
+        let data = vec![7u8; (PROGRESS_INTERVAL * 2 + 10) as usize];
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        let sink = Rc::clone(&reports);
+
+        let mut reader = ReadProgress::new(&data[..], data.len() as u64, move |read, total| {
+            sink.borrow_mut().push((read, total))
+        });
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+
+        assert_eq!(out, data);
+        let reports = reports.borrow();
+        assert_eq!(reports.len(), 3);
+        assert!(reports[0].0 >= PROGRESS_INTERVAL);
+        assert_eq!(
+            *reports.last().unwrap(),
+            (data.len() as u64, data.len() as u64)
+        );
+    }
+
+    #[test]
+    fn read_ahead_yields_every_chunk_in_order() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let chunks: Vec<Vec<u8>> = ReadAheadBuffer::new(io::Cursor::new(data.clone()), 1024, 2)
+            .collect::<io::Result<_>>()
+            .unwrap();
+
+        assert_eq!(chunks.len(), 10);
+        assert!(chunks[..9].iter().all(|chunk| chunk.len() == 1024));
+        assert_eq!(chunks.concat(), data);
+
+        let exact: Vec<_> = ReadAheadBuffer::new(&[1u8; 8][..], 4, 1).collect();
+        assert_eq!(exact.len(), 2);
+        assert_eq!(ReadAheadBuffer::new(io::empty(), 4, 1).count(), 0);
+    }
+
+    #[test]
+    fn read_ahead_stops_at_the_first_error() {
+        struct FailAfter(usize);
+        impl Read for FailAfter {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.0 == 0 {
+                    return Err(io::Error::other("disk on fire"));
+                }
+                let n = self.0.min(buf.len());
+                self.0 -= n;
+                Ok(n)
+            }
+        }
+
+        let mut chunks = ReadAheadBuffer::new(FailAfter(6), 4, 4);
+        assert_eq!(chunks.next().unwrap().unwrap().len(), 4);
+        assert!(chunks.next().unwrap().is_err());
+        assert!(chunks.next().is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn read_file_direct_matches_contents() {
+        let path = "./tests/resources/dna_seq_test.txt";
+        let expected = std::fs::read(path).unwrap();
+
+        // Not every filesystem supports O_DIRECT, read_file falls back to a memory map there
+        match read_file_direct(path) {
+            Ok(buffer) => {
+                assert_eq!(&buffer[..], &expected[..]);
+                assert_eq!(buffer.as_ptr().align_offset(DIRECT_IO_ALIGNMENT), 0);
+            }
+            Err(e) => assert_eq!(e.raw_os_error(), Some(libc::EINVAL)),
+        }
+        assert_eq!(&read_file(path).unwrap()[..], &expected[..]);
+    }
+
+    #[test]
+    fn read_file_with_progress_reads_whole_file() {
+        let expected = std::fs::read("./tests/resources/input.txt").unwrap();
+        let data = read_file_with_progress("./tests/resources/input.txt", |_, _| ()).unwrap();
 
-    unsafe { Mmap::map(&file) }.unwrap()
+        assert_eq!(data, expected);
+        assert!(read_file_with_progress("./tests/resources/missing.txt", |_, _| ()).is_err());
+    }
 }
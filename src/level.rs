@@ -0,0 +1,497 @@
+//! Huffc Compression Levels - Selectable Compression Strategies
+//!
+//! Each level trades speed for compression ratio. The level is stored in the first byte of the
+//! compressed buffer, so `decompress_level` can decode any of them.
+//!
+//! | Level | Strategy |
+//! |-------|----------|
+//! | 0 | Store only, no compression |
+//! | 1 | Byte frequency Huffman |
+//! | 3 | Bigram Huffman |
+//! | 5 | Run-length encoding + Huffman |
+//! | 9 | Burrows-Wheeler + move-to-front + Huffman |
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use huffc::level::{compress_level, decompress_level, CompressionLevel};
+//!
+//! let compressed = compress_level(b"abracadabra abracadabra", CompressionLevel::Bwt);
+//! let decompressed = decompress_level(&compressed).unwrap();
+//! assert_eq!(decompressed, b"abracadabra abracadabra");
+//! ```
+//!
+use std::collections::HashMap;
+
+use bitvec::{order::Msb0, vec::BitVec};
+
+use crate::{
+    archive::FORMAT_ARCHIVE,
+    canonical_huffman_codes, decode_huffman_frame, deserialize_huffman, huff_buffer_len,
+    huff_encode_bitvec, huffman_frame_len,
+    ngram::tally_ngram_frequency,
+    parallel::{blocks_frame_len, decompress_blocks, FORMAT_BLOCKS},
+    serialize_huffman_frame, tally_frequency,
+    transform::{bwt_decode, bwt_encode, mtf_decode, mtf_encode, rle_decode, rle_encode},
+    u64_to_u8, u8_to_u64, wrap_huff_payload, HuffErr, HUFF_MAGIC,
+};
+
+/// Compression strategy, the discriminant is the format byte of the compressed buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionLevel {
+    /// Store the input as is, avoids the Huffman header dominating tiny inputs.
+    Store = 0,
+    /// Huffman code over single bytes.
+    Huffman = 1,
+    /// Huffman code over pairs of bytes.
+    Bigram = 3,
+    /// Run-length encoding followed by a byte Huffman code.
+    RunLength = 5,
+    /// Burrows-Wheeler and move-to-front transforms followed by a byte Huffman code.
+    Bwt = 9,
+}
+
+impl CompressionLevel {
+    /// Maps a numeric level to the strongest strategy not above it, levels above 9 are clamped.
+    pub fn from_u8(level: u8) -> Self {
+        match level {
+            0 => CompressionLevel::Store,
+            1..=2 => CompressionLevel::Huffman,
+            3..=4 => CompressionLevel::Bigram,
+            5..=8 => CompressionLevel::RunLength,
+            _ => CompressionLevel::Bwt,
+        }
+    }
+
+    fn from_format_byte(format: u8) -> Option<Self> {
+        match format {
+            0 => Some(CompressionLevel::Store),
+            1 => Some(CompressionLevel::Huffman),
+            3 => Some(CompressionLevel::Bigram),
+            5 => Some(CompressionLevel::RunLength),
+            9 => Some(CompressionLevel::Bwt),
+            _ => None,
+        }
+    }
+}
+
+/// Compresses `bytes` with the strategy selected by `level`. The buffer starts with `HUFF_MAGIC`,
+/// the format version and a checksum like those of `serialize_huffman`, then the level's format
+/// byte.
+pub fn compress_level(bytes: &[u8], level: CompressionLevel) -> Vec<u8> {
+    wrap_huff_payload(&compress_level_frame(bytes, level))
+}
+
+/// Compresses `bytes` like `compress_level` without the magic bytes, version and checksum,
+/// starting with the format byte. Used for the blocks of a block buffer.
+pub(crate) fn compress_level_frame(bytes: &[u8], level: CompressionLevel) -> Vec<u8> {
+    match level {
+        CompressionLevel::Store => {
+            let mut stored = Vec::with_capacity(bytes.len() + 1);
+            stored.push(CompressionLevel::Store as u8);
+            stored.extend_from_slice(bytes);
+            stored
+        }
+        // A Huffman buffer without bits does not validate, there is nothing to encode anyway
+        CompressionLevel::Huffman if bytes.is_empty() => {
+            compress_level_frame(bytes, CompressionLevel::Store)
+        }
+        CompressionLevel::Huffman => huffman(bytes),
+        CompressionLevel::Bigram => bigram_huffman(bytes),
+        CompressionLevel::RunLength => {
+            let mut compressed = vec![CompressionLevel::RunLength as u8];
+            compressed.extend(huffman(&rle_encode(bytes)));
+            compressed
+        }
+        CompressionLevel::Bwt => {
+            let (last_column, primary) = bwt_encode(bytes);
+            let mut compressed = vec![CompressionLevel::Bwt as u8];
+            compressed.extend_from_slice(&u64_to_u8(primary as u64));
+            compressed.extend(huffman(&mtf_encode(&last_column)));
+            compressed
+        }
+    }
+}
+
+/// Decompresses a buffer produced by `compress_level`, `compress_parallel` or `serialize_huffman`,
+/// or the parts of `compress_split` written back to back, the level is read from the buffer.
+///
+/// # Errors
+///
+/// * `HuffErr::NullInput` - `bytes` is empty.
+/// * `HuffErr::NotAHuffFile` - The buffer does not start with `HUFF_MAGIC`.
+/// * `HuffErr::UnknownCompressionLevel` - The format byte does not name a known level.
+/// * `HuffErr::ChecksumMismatch` - A payload does not match its checksum.
+/// * `HuffErr::MalformedData` - The buffer is truncated or inconsistent.
+pub fn decompress_level(bytes: &[u8]) -> Result<Vec<u8>, HuffErr> {
+    if bytes.is_empty() {
+        return Err(HuffErr::NullInput("decompress_level"));
+    }
+    if !bytes.starts_with(&HUFF_MAGIC) {
+        return Err(HuffErr::NotAHuffFile);
+    }
+    let mut decompressed = Vec::new();
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        let (buffer, next) = rest.split_at(huff_buffer_len(rest));
+        decompressed.extend(deserialize_huffman(buffer)?);
+        rest = next;
+    }
+    Ok(decompressed)
+}
+
+/// Whether `format` is the format byte of a compression level, of a block buffer, of an archive
+/// or, with the `proto` feature, of a protobuf header buffer.
+pub(crate) fn is_level_format(format: u8) -> bool {
+    if format == FORMAT_ARCHIVE {
+        return true;
+    }
+    #[cfg(feature = "proto")]
+    if format == crate::proto::FORMAT_HUFFMAN_PROTO {
+        return true;
+    }
+    format == FORMAT_BLOCKS || CompressionLevel::from_format_byte(format).is_some()
+}
+
+/// Decodes the payload of a `.huff` buffer holding a compression level or block buffer, starting
+/// with its format byte.
+pub(crate) fn decompress_payload(bytes: &[u8]) -> Result<Vec<u8>, HuffErr> {
+    decompress(bytes, true)
+}
+
+/// Decompresses a block of a block buffer, like `decompress_level` but rejects block buffers so
+/// a crafted buffer cannot nest them without bound.
+pub(crate) fn decompress_in_block(bytes: &[u8]) -> Result<Vec<u8>, HuffErr> {
+    decompress(bytes, false)
+}
+
+fn decompress(bytes: &[u8], allow_blocks: bool) -> Result<Vec<u8>, HuffErr> {
+    let Some(format) = bytes.first() else {
+        return Err(HuffErr::MalformedData("empty buffer".to_string()));
+    };
+    if *format == FORMAT_BLOCKS {
+        return decompress_frames(bytes, allow_blocks);
+    }
+    if *format == FORMAT_ARCHIVE {
+        return Err(HuffErr::MalformedData(
+            "an archive holds named files, read them with HuffArchive".to_string(),
+        ));
+    }
+    #[cfg(feature = "proto")]
+    if *format == crate::proto::FORMAT_HUFFMAN_PROTO {
+        return crate::proto::decode_proto_frame(bytes);
+    }
+    let level = CompressionLevel::from_format_byte(*format)
+        .ok_or(HuffErr::UnknownCompressionLevel(*format))?;
+
+    match level {
+        CompressionLevel::Store => Ok(bytes[1..].to_vec()),
+        CompressionLevel::Huffman => decompress_frames(bytes, allow_blocks),
+        CompressionLevel::Bigram => bigram_unhuffman(&bytes[1..]),
+        CompressionLevel::RunLength => {
+            let inner = decompress_inner(&bytes[1..])?;
+            rle_decode(&inner).ok_or(HuffErr::MalformedData("truncated run".to_string()))
+        }
+        CompressionLevel::Bwt => {
+            if bytes.len() < 9 {
+                return Err(HuffErr::MalformedData("missing BWT index".to_string()));
+            }
+            let primary = u8_to_u64(&bytes[1..9]) as usize;
+            let inner = decompress_inner(&bytes[9..])?;
+            bwt_decode(&mtf_decode(&inner), primary)
+                .ok_or(HuffErr::MalformedData("BWT index out of range".to_string()))
+        }
+    }
+}
+
+/// Decodes Huffman and block buffers written back to back, e.g. the parts of a split file.
+fn decompress_frames(bytes: &[u8], allow_blocks: bool) -> Result<Vec<u8>, HuffErr> {
+    let mut decoded = Vec::new();
+    let mut rest = bytes;
+    while let Some(format) = rest.first() {
+        let frame_len = match *format {
+            FORMAT_BLOCKS if !allow_blocks => {
+                return Err(HuffErr::MalformedData("nested block buffer".to_string()))
+            }
+            FORMAT_BLOCKS => {
+                let frame_len = blocks_frame_len(rest)
+                    .ok_or(HuffErr::MalformedData("truncated block buffer".to_string()))?;
+                decoded.extend(decompress_blocks(&rest[..frame_len])?);
+                frame_len
+            }
+            format if format == CompressionLevel::Huffman as u8 => {
+                let frame_len = huffman_frame_len(rest).ok_or(HuffErr::MalformedData(
+                    "truncated Huffman buffer".to_string(),
+                ))?;
+                decoded.extend(decode_huffman_frame(&rest[..frame_len])?);
+                frame_len
+            }
+            format => {
+                return Err(HuffErr::MalformedData(format!(
+                    "unexpected format byte {:#04x} after a buffer",
+                    format
+                )))
+            }
+        };
+        rest = &rest[frame_len..];
+    }
+    Ok(decoded)
+}
+
+pub(crate) fn huffman(bytes: &[u8]) -> Vec<u8> {
+    let encoded_map = canonical_huffman_codes(&tally_frequency(bytes))
+        .expect("the code lengths of a Huffman tree satisfy the Kraft inequality");
+    // An inner frame may be empty, e.g. the run-length encoding of empty input
+    let (bit_buffer, total_bits) = if bytes.is_empty() {
+        (Vec::new(), 0)
+    } else {
+        huff_encode_bitvec(bytes, &encoded_map).expect("the encoding is built from the same bytes")
+    };
+    serialize_huffman_frame(&encoded_map, bit_buffer, total_bits)
+}
+
+fn decompress_inner(bytes: &[u8]) -> Result<Vec<u8>, HuffErr> {
+    match huffman_frame_len(bytes) {
+        Some(frame_len) if frame_len == bytes.len() => decode_huffman_frame(bytes),
+        _ => Err(HuffErr::MalformedData(
+            "missing or truncated inner Huffman buffer".to_string(),
+        )),
+    }
+}
+
+/// Layout: format byte, total bits (8 bytes), number of symbols (4 bytes), per symbol the two
+/// bytes, code length (2 bytes) and the code padded to whole bytes, a flag byte telling whether
+/// an odd trailing byte follows, the trailing byte if any, then the bit buffer.
+fn bigram_huffman(bytes: &[u8]) -> Vec<u8> {
+    let codes = tally_ngram_frequency::<2>(bytes).build_codes();
+
+    let mut bits: BitVec<u8, Msb0> = BitVec::with_capacity(bytes.len() * 4);
+    bytes
+        .chunks_exact(2)
+        .for_each(|pair| bits.extend_from_bitslice(&codes[&[pair[0], pair[1]]]));
+
+    let mut symbols: Vec<_> = codes.iter().collect();
+    symbols.sort_unstable_by_key(|(symbol, _)| **symbol);
+
+    let mut compressed = vec![CompressionLevel::Bigram as u8];
+    compressed.extend_from_slice(&u64_to_u8(bits.len() as u64));
+    compressed.extend_from_slice(&(symbols.len() as u32).to_be_bytes());
+    for (symbol, code) in symbols {
+        compressed.extend_from_slice(symbol);
+        compressed.extend_from_slice(&(code.len() as u16).to_be_bytes());
+        let mut code = code.clone();
+        code.set_uninitialized(false);
+        compressed.extend(code.into_vec());
+    }
+    match bytes.len() % 2 {
+        1 => compressed.extend_from_slice(&[1, bytes[bytes.len() - 1]]),
+        _ => compressed.push(0),
+    }
+    bits.set_uninitialized(false);
+    compressed.extend(bits.into_vec());
+    compressed
+}
+
+fn bigram_unhuffman(bytes: &[u8]) -> Result<Vec<u8>, HuffErr> {
+    let truncated = || HuffErr::MalformedData("truncated bigram header".to_string());
+    let take = |idx: &mut usize, len: usize| -> Result<&[u8], HuffErr> {
+        let slice = bytes.get(*idx..*idx + len).ok_or_else(truncated)?;
+        *idx += len;
+        Ok(slice)
+    };
+
+    let mut idx = 0;
+    let total_bits = u8_to_u64(take(&mut idx, 8)?);
+    let num_symbols = u32::from_be_bytes(take(&mut idx, 4)?.try_into().unwrap());
+
+    let mut codes: HashMap<BitVec<u8, Msb0>, [u8; 2]> = HashMap::new();
+    for _ in 0..num_symbols {
+        let symbol: [u8; 2] = take(&mut idx, 2)?.try_into().unwrap();
+        let len = u16::from_be_bytes(take(&mut idx, 2)?.try_into().unwrap()) as usize;
+        let mut code: BitVec<u8, Msb0> = BitVec::from_slice(take(&mut idx, len.div_ceil(8))?);
+        code.truncate(len);
+        codes.insert(code, symbol);
+    }
+    let trailing = match take(&mut idx, 1)?[0] {
+        0 => None,
+        _ => Some(take(&mut idx, 1)?[0]),
+    };
+
+    let payload: &bitvec::slice::BitSlice<u8, Msb0> =
+        bitvec::slice::BitSlice::from_slice(&bytes[idx..]);
+    if (payload.len() as u64) < total_bits {
+        return Err(HuffErr::MalformedData("truncated bit buffer".to_string()));
+    }
+
+    let max_len = codes.keys().map(|code| code.len()).max().unwrap_or(0);
+    let mut decoded = Vec::with_capacity(total_bits as usize / 4);
+    let mut current: BitVec<u8, Msb0> = BitVec::new();
+    for bit in payload[..total_bits as usize].iter().by_vals() {
+        current.push(bit);
+        if let Some(symbol) = codes.get(&current) {
+            decoded.extend_from_slice(symbol);
+            current.clear();
+        } else if current.len() >= max_len {
+            return Err(HuffErr::MalformedData("invalid bigram code".to_string()));
+        }
+    }
+    if !current.is_empty() {
+        return Err(HuffErr::MalformedData("incomplete bigram code".to_string()));
+    }
+
+    decoded.extend(trailing);
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE_PATH: &str = "./tests/resources/dna_seq_test.txt";
+
+    #[test]
+    fn every_level_round_trips() {
+        let original = std::fs::read(FILE_PATH).unwrap();
+        for level in 0..=9 {
+            let compressed = compress_level(&original, CompressionLevel::from_u8(level));
+            assert_eq!(
+                compressed[crate::HUFF_HEADER_LEN],
+                CompressionLevel::from_u8(level) as u8
+            );
+            assert_eq!(decompress_level(&compressed).unwrap(), original);
+        }
+    }
+
+    #[test]
+    fn empty_input_round_trips_at_every_level() {
+        for level in [0, 1, 3, 5, 9] {
+            let compressed = compress_level(b"", CompressionLevel::from_u8(level));
+            assert!(decompress_level(&compressed).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn odd_length_bigram_round_trip() {
+        let original = b"abcabcabcab";
+        let compressed = compress_level(original, CompressionLevel::Bigram);
+
+        assert_eq!(decompress_level(&compressed).unwrap(), original);
+    }
+
+    #[test]
+    fn store_level_for_tiny_input() {
+        let compressed = compress_level(b"hi", CompressionLevel::Store);
+
+        assert_eq!(compressed[crate::HUFF_HEADER_LEN..], [0, b'h', b'i']);
+        assert_eq!(compressed, crate::compress(b"hi").unwrap());
+        assert_eq!(decompress_level(&compressed).unwrap(), b"hi");
+    }
+
+    #[test]
+    fn checksum_detects_a_flipped_bit() {
+        let original = std::fs::read(FILE_PATH).unwrap();
+        for level in [0, 1, 3, 5, 9] {
+            let mut compressed = compress_level(&original, CompressionLevel::from_u8(level));
+            let last = compressed.len() - 1;
+            compressed[last] ^= 0x01;
+            assert!(matches!(
+                decompress_level(&compressed),
+                Err(HuffErr::ChecksumMismatch { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn buffers_without_magic_are_rejected() {
+        let original = std::fs::read(FILE_PATH).unwrap();
+        for level in [0, 1, 3, 5, 9] {
+            let frame = compress_level_frame(&original, CompressionLevel::from_u8(level));
+            assert!(matches!(
+                decompress_level(&frame),
+                Err(HuffErr::NotAHuffFile)
+            ));
+        }
+        // A huffc 0.1.1 file starts with total_bits, its first byte is the stored format byte
+        let legacy_bytes = [
+            0, 0, 0, 0, 0, 0, 0, 6, 0, 0, 0, 0, 0, 0, 0, 9, 1, 1, 1, 2, 2, 0, 3, 2, 1, 176,
+        ];
+        assert!(matches!(
+            decompress_level(&legacy_bytes),
+            Err(HuffErr::NotAHuffFile)
+        ));
+        assert!(matches!(
+            decompress_level(b"\x00hello"),
+            Err(HuffErr::NotAHuffFile)
+        ));
+    }
+
+    #[test]
+    fn concatenated_huffman_buffers() {
+        let first = compress_level(b"abracadabra", CompressionLevel::Huffman);
+        let second = compress_level(b"mississippi", CompressionLevel::Huffman);
+        let concatenated = [first.clone(), second].concat();
+
+        assert_eq!(
+            decompress_level(&concatenated).unwrap(),
+            b"abracadabramississippi"
+        );
+        assert!(decompress_level(&[&first[..], &first[..5]].concat()).is_err());
+    }
+
+    #[test]
+    fn empty_input_is_rejected() {
+        assert!(matches!(
+            decompress_level(&[]),
+            Err(HuffErr::NullInput("decompress_level"))
+        ));
+    }
+
+    #[test]
+    fn unknown_level_is_rejected() {
+        assert!(matches!(
+            decompress_in_block(&[7, 1, 2, 3]),
+            Err(HuffErr::UnknownCompressionLevel(7))
+        ));
+        assert!(matches!(
+            decompress_level(&wrap_huff_payload(&[7, 1, 2, 3])),
+            Err(HuffErr::MalformedData(_))
+        ));
+    }
+
+    #[test]
+    fn stored_payload_resembling_a_version_0_buffer() {
+        // Total bits, a one symbol header and the bit buffer, as a version 0 file would start
+        let payload = [
+            0, 0, 0, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 3, b'a', 1, 1, 0xAA,
+        ];
+
+        let stored = compress_level(&payload, CompressionLevel::Store);
+        assert_eq!(decompress_level(&stored).unwrap(), payload);
+
+        let blocks = crate::parallel::compress_parallel(&payload, 1);
+        assert_eq!(decompress_level(&blocks).unwrap(), payload);
+
+        let blocks = crate::CompressOptions::new()
+            .with_block_size(64)
+            .encode(&payload)
+            .unwrap();
+        assert_eq!(decompress_level(&blocks).unwrap(), payload);
+    }
+
+    #[test]
+    fn truncated_inner_buffer_is_rejected() {
+        assert!(decompress_level(&wrap_huff_payload(&[5, 1])).is_err());
+        assert!(
+            decompress_level(&wrap_huff_payload(&[9, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0])).is_err()
+        );
+
+        let compressed = compress_level(b"aaaabbbbcccc", CompressionLevel::RunLength);
+        assert!(decompress_level(&compressed[..compressed.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn from_u8_clamps() {
+        assert_eq!(CompressionLevel::from_u8(4), CompressionLevel::Bigram);
+        assert_eq!(CompressionLevel::from_u8(200), CompressionLevel::Bwt);
+    }
+}
@@ -8,21 +8,619 @@
 //! - Builds a Huffman tree to encode data optimally
 //! - Encodes and decodes data using bitwise representations
 //! - Supports serialization and deserialization of Huffman-encoded data
+//!
+//! `compress` and `decompress` run the whole pipeline and are the place to start, the functions
+//! for each step are public for finer control.
+pub mod archive;
+pub mod batch;
+pub mod builder;
+pub mod cache;
 pub mod cli;
+pub mod codec;
+pub mod decoder;
+pub mod encoder;
+mod error;
+pub mod frame;
 pub mod fs;
+pub mod level;
+pub mod ngram;
+pub mod parallel;
+pub mod pipeline;
+#[cfg(feature = "proto")]
+pub mod proto;
+pub mod simd;
+pub mod timing;
+mod transform;
+pub mod tree;
 
-use std::collections::{HashMap, VecDeque};
+pub use builder::CompressOptions;
+pub use error::*;
+pub use tree::{
+    build_huffman_arena, build_huffman_tree, build_huffman_tree_two_queue, encode_huffman_tree,
+    HuffNode, HuffNodeArena,
+};
 
-use bitvec::{order::Msb0, vec::BitVec};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, VecDeque},
+    fmt,
+};
 
-#[derive(Debug)]
+use bitvec::{order::Msb0, slice::BitSlice, vec::BitVec};
+use decoder::{DecodeTrie, HuffmanBitReader};
+
+/// Compresses `input` to the `.huff` format of `serialize_huffman`, with a Huffman code built
+/// from its own byte frequencies. Input that encoding would not make smaller, e.g. empty input,
+/// is stored as is.
+///
+/// # Errors
+///
+/// Reports the errors of the encoding stages, which a code built from `input` itself does not
+/// produce:
+///
+/// * `HuffErr::MalformedData` - `canonicalize_encoding` rejected the code lengths of the tree.
+/// * `HuffErr::SymbolNotInCodebook` - `input` contains a byte without a code.
+///
+/// # Examples
+///
+/// ```rust
+/// let compressed = huffc::compress(b"abracadabra abracadabra").unwrap();
+/// assert_eq!(huffc::decompress(&compressed).unwrap(), b"abracadabra abracadabra");
+///
+/// let empty = huffc::compress(b"").unwrap();
+/// assert!(huffc::decompress(&empty).unwrap().is_empty());
+/// ```
+pub fn compress(input: &[u8]) -> Result<Vec<u8>, HuffErr> {
+    compress_with_frequencies(input, tally_frequency(input))
+}
+
+/// Compresses `input` like `compress`, with a Huffman code built from `freq_buffer` instead of
+/// the frequencies of `input`, e.g. a table loaded with `FrequencyBuffer::from_json`.
+///
+/// # Errors
+///
+/// Returns `HuffErr::SymbolNotInCodebook` if `input` contains a byte with a count of zero in
+/// `freq_buffer`.
+pub fn compress_with_frequencies(
+    input: &[u8],
+    freq_buffer: FrequencyBuffer,
+) -> Result<Vec<u8>, HuffErr> {
+    if input.is_empty() {
+        return Ok(serialize_stored(input));
+    }
+    let encoded_map = canonical_huffman_codes(&freq_buffer)?;
+    let (bit_buffer, total_bits) = huff_encode_bitvec(input, &encoded_map)?;
+    Ok(serialize_huffman_or_passthrough(
+        &encoded_map,
+        bit_buffer,
+        total_bits,
+        input,
+    ))
+}
+
+/// Decompresses a buffer produced by `compress`, `level::compress_level` or
+/// `parallel::compress_parallel`.
+///
+/// # Errors
+///
+/// See `level::decompress_level`.
+///
+/// # Examples
+///
+/// ```rust
+/// let compressed = huffc::compress(b"GATTACA").unwrap();
+/// assert_eq!(huffc::decompress(&compressed).unwrap(), b"GATTACA");
+/// // An empty buffer is not a compressed one, empty input compresses to a few header bytes
+/// assert!(huffc::decompress(&[]).is_err());
+/// ```
+pub fn decompress(input: &[u8]) -> Result<Vec<u8>, HuffErr> {
+    level::decompress_level(input)
+}
+
+/// `compressed_len` over `original_len`, below 1.0 when the data shrank. Empty input gives 1.0.
+///
+/// # Examples
+///
+/// ```rust
+/// assert_eq!(huffc::compress_ratio(200, 50), 0.25);
+/// assert_eq!(huffc::compress_ratio(0, 10), 1.0);
+/// ```
+pub fn compress_ratio(original_len: usize, compressed_len: usize) -> f64 {
+    if original_len == 0 {
+        return 1.0;
+    }
+    compressed_len as f64 / original_len as f64
+}
+
+/// Percentage of `original_len` saved by compressing it to `compressed_len`, negative when the
+/// data grew.
+pub fn space_saved_percent(original_len: usize, compressed_len: usize) -> f64 {
+    (1.0 - compress_ratio(original_len, compressed_len)) * 100.0
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FrequencyBuffer(pub [u64; 256]);
 
+/// All counts zero.
+impl Default for FrequencyBuffer {
+    fn default() -> Self {
+        FrequencyBuffer([0; 256])
+    }
+}
+
+impl FrequencyBuffer {
+    /// Shannon entropy of the distribution in bits per symbol, the sum of `-p * log2(p)` over the
+    /// symbols with a non-zero count. A lower bound for `expected_bits_per_symbol`, 0.0 if every
+    /// count is zero.
+    pub fn entropy(&self) -> f64 {
+        let total = self.total_symbols();
+        self.0
+            .iter()
+            .filter(|count| **count != 0)
+            .map(|count| {
+                let p = *count as f64 / total as f64;
+                -p * p.log2()
+            })
+            .sum()
+    }
+
+    /// Average code length in bits per symbol of the codes `canonical_huffman_codes` assigns,
+    /// weighted by the counts. 0.0 if every count is zero.
+    pub fn expected_bits_per_symbol(&self) -> f64 {
+        let total = self.total_symbols();
+        if total == 0 {
+            return 0.0;
+        }
+        let Some(tree) = build_huffman_tree(self) else {
+            return 0.0;
+        };
+        // Canonical codes keep the lengths of the tree codes
+        let encoded_map = encode_huffman_tree(&tree);
+        let total_bits: u64 = encoded_map
+            .values()
+            .map(|encoded| self[encoded.value] * encoded.bits.len() as u64)
+            .sum();
+        total_bits as f64 / total as f64
+    }
+
+    /// Number of distinct symbols, the symbols with a non-zero count.
+    #[inline]
+    pub fn non_zero_count(&self) -> usize {
+        self.0.iter().filter(|count| **count != 0).count()
+    }
+
+    /// Sum of all counts, the number of bytes tallied, saturating at `u64::MAX`.
+    #[inline]
+    pub fn total_symbols(&self) -> u64 {
+        self.0
+            .iter()
+            .fold(0u64, |total, count| total.saturating_add(*count))
+    }
+
+    /// Count of `symbol`.
+    pub fn count_of(&self, symbol: u8) -> u64 {
+        self[symbol]
+    }
+
+    /// Sets the count of `symbol`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `HuffErr::InvalidFrequencyTable` if `count` is `u64::MAX`, which is reserved as a
+    /// sentinel.
+    pub fn set_count(&mut self, symbol: u8, count: u64) -> Result<(), HuffErr> {
+        if count == u64::MAX {
+            return Err(HuffErr::InvalidFrequencyTable(format!(
+                "count of byte {:#04x} cannot be u64::MAX",
+                symbol
+            )));
+        }
+        self[symbol] = count;
+        Ok(())
+    }
+
+    /// Returns a copy with every count below `min_count` set to zero, so rare symbols can be
+    /// left out of the code and escaped separately.
+    pub fn filter_by_min_count(&self, min_count: u64) -> FrequencyBuffer {
+        FrequencyBuffer(
+            self.0
+                .map(|count| if count < min_count { 0 } else { count }),
+        )
+    }
+
+    /// Returns a CRC64 fingerprint of the frequency counts, buffers with equal counts always have
+    /// equal fingerprints.
+    pub fn fingerprint(&self) -> u64 {
+        let crc = crc::Crc::<u64>::new(&crc::CRC_64_ECMA_182);
+        let mut digest = crc.digest();
+        self.0
+            .iter()
+            .for_each(|count| digest.update(&u64_to_u8(*count)));
+        digest.finalize()
+    }
+
+    /// Builds a frequency buffer from a theoretical probability distribution rather than from
+    /// observed data.
+    ///
+    /// Each probability is scaled by `u64::MAX / 2` to obtain a proportional integer count,
+    /// symbols not present in `probs` get a count of zero.
+    ///
+    /// # Errors
+    ///
+    /// * `HuffErr::NullInput` - `probs` is empty.
+    /// * `HuffErr::InvalidProbabilities` - A probability is negative or not finite, a symbol
+    ///   appears more than once, or the probabilities do not sum to 1.0 (within `1e-6`).
+    pub fn from_probability_distribution(probs: &[(u8, f64)]) -> Result<FrequencyBuffer, HuffErr> {
+        if probs.is_empty() {
+            return Err(HuffErr::NullInput(
+                "FrequencyBuffer::from_probability_distribution",
+            ));
+        }
+        let mut seen = [false; 256];
+        let mut sum = 0.0;
+        for (symbol, p) in probs {
+            if !p.is_finite() || *p < 0.0 || seen[*symbol as usize] {
+                return Err(HuffErr::InvalidProbabilities);
+            }
+            seen[*symbol as usize] = true;
+            sum += p;
+        }
+
+        if (sum - 1.0).abs() > 1e-6 {
+            return Err(HuffErr::InvalidProbabilities);
+        }
+
+        let scale = (u64::MAX / 2) as f64;
+        let mut fb = FrequencyBuffer([0; 256]);
+        for (symbol, p) in probs {
+            fb[*symbol] = (p / sum * scale) as u64;
+        }
+        Ok(fb)
+    }
+
+    /// Builds a frequency buffer from a histogram of all 256 byte values normalized to
+    /// probabilities, the dense counterpart of `from_probability_distribution`. Each entry is
+    /// scaled by `u64::MAX / 2` and rounded.
+    ///
+    /// # Errors
+    ///
+    /// Returns `HuffErr::InvalidHistogram` if an entry is negative or not finite, or the entries
+    /// do not sum to 1.0 (within `1e-6`).
+    pub fn from_normalized_histogram(hist: &[f64; 256]) -> Result<FrequencyBuffer, HuffErr> {
+        if hist.iter().any(|p| !p.is_finite() || *p < 0.0) {
+            return Err(HuffErr::InvalidHistogram);
+        }
+        let sum: f64 = hist.iter().sum();
+        if (sum - 1.0).abs() > 1e-6 {
+            return Err(HuffErr::InvalidHistogram);
+        }
+
+        let scale = (u64::MAX / 2) as f64;
+        let mut fb = FrequencyBuffer([0; 256]);
+        for (count, p) in fb.0.iter_mut().zip(hist) {
+            *count = (p / sum * scale).round() as u64;
+        }
+        Ok(fb)
+    }
+
+    /// Adds `alpha * total_count / 256` (at least 1) to every entry, including zero ones, so
+    /// every symbol is assigned a (long) code and a codebook built from a small corpus can encode
+    /// symbols it never saw. The smoothing is proportional to the corpus size. A non-positive or
+    /// non-finite `alpha` leaves the buffer unchanged.
+    pub fn apply_laplace_smoothing(&mut self, alpha: f64) {
+        if !alpha.is_finite() || alpha <= 0.0 {
+            return;
+        }
+
+        let total_count: f64 = self.0.iter().map(|count| *count as f64).sum();
+        let increment = ((alpha * total_count / 256.0) as u64).max(1);
+        self.0
+            .iter_mut()
+            .for_each(|count| *count = count.saturating_add(increment));
+    }
+
+    /// Formats all 256 entries as CSV with a `symbol_dec,count` header row, for loading into a
+    /// spreadsheet or pandas.
+    pub fn to_csv(&self) -> String {
+        self.to_delimited(',')
+    }
+
+    /// Formats all 256 entries like `to_csv`, separated by tabs.
+    pub fn to_tsv(&self) -> String {
+        self.to_delimited('\t')
+    }
+
+    /// Formats one row per non-zero symbol as a bar chart for the terminal, e.g.
+    /// `'A' (0x41) [████████░░░░] 1234 (12.3%)`. The bar is `width` characters wide for the most
+    /// frequent symbol and scaled down for the others.
+    pub fn to_ascii_histogram(&self, width: usize) -> String {
+        let max = self.0.iter().copied().max().unwrap_or(0);
+        let total: u128 = self.0.iter().map(|count| *count as u128).sum();
+        let mut histogram = String::new();
+        for (symbol, count) in self.0.iter().enumerate().filter(|(_, count)| **count != 0) {
+            let filled = (*count as u128 * width as u128 / max as u128) as usize;
+            histogram.push_str(&format!(
+                "'{}' ({:#04x}) [{}{}] {} ({:.1}%)\n",
+                std::ascii::escape_default(symbol as u8),
+                symbol,
+                "█".repeat(filled),
+                "░".repeat(width - filled),
+                count,
+                *count as f64 * 100.0 / total as f64
+            ));
+        }
+        histogram
+    }
+
+    /// Adds the counts of `other`, saturating at `u64::MAX`.
+    pub fn merge(&mut self, other: &FrequencyBuffer) {
+        for (count, other) in self.0.iter_mut().zip(other.0.iter()) {
+            *count = count.saturating_add(*other);
+        }
+    }
+
+    /// Subtracts the counts of `other`, saturating at zero.
+    pub fn saturating_sub_assign(&mut self, other: &FrequencyBuffer) {
+        for (count, other) in self.0.iter_mut().zip(other.0.iter()) {
+            *count = count.saturating_sub(*other);
+        }
+    }
+
+    /// Per-symbol `self - other`, differences beyond the range of `i64` saturate. Also available
+    /// as `self - other`.
+    pub fn difference(self, other: FrequencyBuffer) -> SignedFrequencyBuffer {
+        SignedFrequencyBuffer(std::array::from_fn(|symbol| {
+            (self.0[symbol] as i128 - other.0[symbol] as i128)
+                .clamp(i64::MIN as i128, i64::MAX as i128) as i64
+        }))
+    }
+
+    fn to_delimited(&self, delimiter: char) -> String {
+        let mut table = format!("symbol_dec{}count\n", delimiter);
+        self.0.iter().enumerate().for_each(|(symbol, count)| {
+            table.push_str(&format!("{}{}{}\n", symbol, delimiter, count))
+        });
+        table
+    }
+
+    /// Serializes the non-zero entries of the buffer to a JSON object of the form
+    /// `{"0": 0, "1": 12345, ...}`, suitable for hand editing.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        let counts: HashMap<u8, u64> = self
+            .0
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count != 0)
+            .map(|(symbol, count)| (symbol as u8, *count))
+            .collect();
+        serde_json::to_string(&counts).unwrap()
+    }
+
+    /// Parses a frequency buffer from the JSON representation produced by `to_json`, symbols
+    /// missing from the object get a count of zero.
+    ///
+    /// # Errors
+    ///
+    /// Returns `HuffErr::InvalidFrequencyTable` if the JSON is malformed or a key is not a valid
+    /// byte value.
+    #[cfg(feature = "serde")]
+    pub fn from_json(s: &str) -> Result<FrequencyBuffer, HuffErr> {
+        let counts: HashMap<u8, u64> =
+            serde_json::from_str(s).map_err(|e| HuffErr::InvalidFrequencyTable(e.to_string()))?;
+        let mut fb = FrequencyBuffer([0; 256]);
+        for (symbol, count) in counts {
+            fb[symbol] = count;
+        }
+        Ok(fb)
+    }
+}
+
+impl FrequencyBuffer {
+    fn sorted_non_zero(&self) -> Vec<(u8, u64)> {
+        let mut entries: Vec<(u8, u64)> = self
+            .0
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count != 0)
+            .map(|(symbol, count)| (symbol as u8, *count))
+            .collect();
+        entries.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        entries
+    }
+}
+
+/// Iterates over the non-zero `(symbol, count)` pairs, most frequent first, ties ordered by
+/// ascending symbol.
+impl IntoIterator for FrequencyBuffer {
+    type Item = (u8, u64);
+    type IntoIter = std::vec::IntoIter<(u8, u64)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.sorted_non_zero().into_iter()
+    }
+}
+
+/// Iterates over the non-zero `(symbol, count)` pairs, most frequent first, ties ordered by
+/// ascending symbol.
+impl IntoIterator for &FrequencyBuffer {
+    type Item = (u8, u64);
+    type IntoIter = std::vec::IntoIter<(u8, u64)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.sorted_non_zero().into_iter()
+    }
+}
+
+/// Difference between two snapshots of a frequency table, e.g. before and after an adaptive
+/// encoder updated it.
+#[derive(Debug)]
+pub struct DifferentialFrequencyBuffer {
+    pub before: FrequencyBuffer,
+    pub after: FrequencyBuffer,
+}
+
+impl DifferentialFrequencyBuffer {
+    pub fn new(before: FrequencyBuffer, after: FrequencyBuffer) -> Self {
+        Self { before, after }
+    }
+
+    /// Returns `(symbol, after - before)` for every symbol whose count changed, largest absolute
+    /// change first, ties ordered by ascending symbol. Deltas beyond the range of `i64` saturate.
+    pub fn changed_symbols(&self) -> Vec<(u8, i64)> {
+        let mut changed: Vec<(u8, i64)> = self
+            .before
+            .0
+            .iter()
+            .zip(self.after.0.iter())
+            .enumerate()
+            .filter(|(_, (before, after))| before != after)
+            .map(|(symbol, (before, after))| {
+                let delta =
+                    (*after as i128 - *before as i128).clamp(i64::MIN as i128, i64::MAX as i128);
+                (symbol as u8, delta as i64)
+            })
+            .collect();
+        changed.sort_unstable_by(|a, b| {
+            b.1.unsigned_abs()
+                .cmp(&a.1.unsigned_abs())
+                .then(a.0.cmp(&b.0))
+        });
+        changed
+    }
+
+    /// Applies the same deltas to `fb`, counts saturate at zero and `u64::MAX`.
+    pub fn apply_to(&self, fb: &mut FrequencyBuffer) {
+        for (symbol, delta) in self.changed_symbols() {
+            let count = &mut fb[symbol];
+            *count = count.saturating_add_signed(delta);
+        }
+    }
+}
+
+/// Count of a symbol, `fb[b'a']` is the same as `fb.count_of(b'a')`.
+///
+/// ```rust
+/// let mut fb = huffc::tally_frequency(b"GATTACA");
+/// assert_eq!(fb[b'A'], 3);
+/// fb[b'A'] += 1;
+/// assert_eq!(fb.count_of(b'A'), 4);
+/// ```
+impl std::ops::Index<u8> for FrequencyBuffer {
+    type Output = u64;
+
+    fn index(&self, symbol: u8) -> &u64 {
+        &self.0[symbol as usize]
+    }
+}
+
+/// Unlike `set_count` this allows any count, including `u64::MAX`.
+impl std::ops::IndexMut<u8> for FrequencyBuffer {
+    fn index_mut(&mut self, symbol: u8) -> &mut u64 {
+        &mut self.0[symbol as usize]
+    }
+}
+
+/// Per-symbol sum of two frequency tables, saturating at `u64::MAX` like `merge`.
+impl std::ops::Add<FrequencyBuffer> for FrequencyBuffer {
+    type Output = FrequencyBuffer;
+
+    fn add(mut self, other: FrequencyBuffer) -> FrequencyBuffer {
+        self.merge(&other);
+        self
+    }
+}
+
+/// Adds the counts of `other` like `merge`.
+impl std::ops::AddAssign<FrequencyBuffer> for FrequencyBuffer {
+    fn add_assign(&mut self, other: FrequencyBuffer) {
+        self.merge(&other);
+    }
+}
+
+/// Per-symbol difference like `difference`, `saturating_sub_assign` subtracts in place instead.
+impl std::ops::Sub<FrequencyBuffer> for FrequencyBuffer {
+    type Output = SignedFrequencyBuffer;
+
+    fn sub(self, other: FrequencyBuffer) -> SignedFrequencyBuffer {
+        self.difference(other)
+    }
+}
+
+/// Per-symbol differences of two frequency tables, created by `FrequencyBuffer::difference`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SignedFrequencyBuffer(pub [i64; 256]);
+
+impl SignedFrequencyBuffer {
+    /// The increases, symbols that decreased or did not change have a count of 0.
+    pub fn positive_entries(&self) -> FrequencyBuffer {
+        FrequencyBuffer(self.0.map(|delta| delta.max(0) as u64))
+    }
+
+    /// The decreases as positive counts, symbols that increased or did not change have a count
+    /// of 0.
+    pub fn negative_entries(&self) -> FrequencyBuffer {
+        FrequencyBuffer(self.0.map(|delta| delta.min(0).unsigned_abs()))
+    }
+
+    /// The absolute value of every difference.
+    pub fn magnitude(&self) -> FrequencyBuffer {
+        FrequencyBuffer(self.0.map(i64::unsigned_abs))
+    }
+}
+
+/// Frequencies of the most recent chunks of a stream, for rebuilding an adaptive codebook
+/// from recent data only.
+#[derive(Debug)]
+pub struct FrequencyWindow {
+    /// Sum of the chunks in `history`
+    buffer: FrequencyBuffer,
+    history: VecDeque<FrequencyBuffer>,
+}
+
+impl Default for FrequencyWindow {
+    fn default() -> Self {
+        Self {
+            buffer: FrequencyBuffer([0; 256]),
+            history: VecDeque::new(),
+        }
+    }
+}
+
+impl FrequencyWindow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tallies `chunk` into the window and ages out the oldest chunks so the window covers at
+    /// most the last `window_count` chunks. Returns the frequencies of the window.
+    pub fn update(&mut self, chunk: &[u8], window_count: usize) -> &FrequencyBuffer {
+        let tally = tally_frequency(chunk);
+        self.buffer.merge(&tally);
+        self.history.push_back(tally);
+        while self.history.len() > window_count {
+            let oldest = self.history.pop_front().unwrap();
+            self.buffer.saturating_sub_assign(&oldest);
+        }
+        &self.buffer
+    }
+
+    /// Frequencies of the chunks currently in the window.
+    pub fn frequencies(&self) -> &FrequencyBuffer {
+        &self.buffer
+    }
+}
+
+/// Counts how often each byte occurs in `bytes`. Empty input gives all zero counts, which
+/// `huff_encode_bitvec` rejects as `HuffErr::NullInput` further down the pipeline.
+#[must_use = "the frequencies must be passed to build_huffman_array"]
 pub fn tally_frequency(bytes: &[u8]) -> FrequencyBuffer {
     let mut fb = FrequencyBuffer([0; 256]);
-    bytes.iter().for_each(|byte| unsafe {
-        *fb.0.as_mut_ptr().add(*byte as usize) += 1;
-    });
+    for byte in bytes {
+        // A `u8` index is always below the array's 256 entries, so the compiler drops the bounds
+        // check and this is as fast as unchecked pointer arithmetic, no `unsafe` needed
+        fb.0[*byte as usize] += 1;
+    }
     fb
 }
 
@@ -30,6 +628,10 @@ pub fn tally_frequency(bytes: &[u8]) -> FrequencyBuffer {
 // to pop
 type Idx = u8;
 type Freq = u64;
+/// The smallest value and, if any, the second smallest
+type MinTwo = ((Idx, Freq), Option<(Idx, Freq)>);
+/// Pops the smallest non-zero value with a linear scan. `build_huffman_array` uses a binary heap
+/// instead, this is kept for callers working on a raw buffer.
 pub fn find_and_pop_min(freq_buf: &mut [u64]) -> Option<(Idx, Freq)> {
     let mut min_value_idx = None;
     let mut min_value = None;
@@ -52,8 +654,7 @@ pub fn find_and_pop_min(freq_buf: &mut [u64]) -> Option<(Idx, Freq)> {
     });
 
     if let Some(idx) = min_value_idx {
-        let byte = unsafe { freq_buf.get_unchecked_mut(idx as usize) };
-        *byte = 0;
+        freq_buf[idx as usize] = 0;
     }
 
     match (min_value_idx, min_value) {
@@ -62,25 +663,130 @@ pub fn find_and_pop_min(freq_buf: &mut [u64]) -> Option<(Idx, Freq)> {
     }
 }
 
-pub fn huff_encode_bitvec(bytes: &[u8], encoded_map: &HashMap<u8, Encoded>) -> (Vec<u8>, u64) {
-    let mut final_bits: BitVec<u8, Msb0> = BitVec::with_capacity(bytes.len() / 2);
+/// Pops the two smallest non-zero values in a single pass, ties go to the lower index first. The
+/// second value is `None` when only one non-zero value is left, the result is `None` when all
+/// values are zero.
+pub fn find_and_pop_min_two(freq_buf: &mut [u64]) -> Option<MinTwo> {
+    let mut min: Option<(Idx, Freq)> = None;
+    let mut second: Option<(Idx, Freq)> = None;
+    freq_buf.iter().enumerate().for_each(|(idx, byte)| {
+        if *byte == 0 {
+            return;
+        }
+
+        match min {
+            Some((_, v)) if *byte >= v => match second {
+                Some((_, v)) if *byte >= v => (),
+                _ => second = Some((idx as u8, *byte)),
+            },
+            _ => {
+                second = min;
+                min = Some((idx as u8, *byte));
+            }
+        }
+    });
+
+    for (idx, _) in min.iter().chain(second.iter()) {
+        freq_buf[*idx as usize] = 0;
+    }
+
+    min.map(|min| (min, second))
+}
+
+/// Encodes `bytes` to a bit buffer and its length in bits. Uses `simd::huff_encode_packed` when
+/// every code is at most 8 bits.
+///
+/// # Errors
+///
+/// * `HuffErr::NullInput` - `bytes` is empty, `compress` stores empty input instead.
+/// * `HuffErr::SymbolNotInCodebook` - The first byte of `bytes` that has no code in
+///   `encoded_map`, e.g. when the map was built from other data.
+#[must_use = "the encoded bit buffer must be passed to serialize_huffman"]
+pub fn huff_encode_bitvec(
+    bytes: &[u8],
+    encoded_map: &HashMap<u8, Encoded>,
+) -> Result<(Vec<u8>, u64), HuffErr> {
+    if bytes.is_empty() {
+        return Err(HuffErr::NullInput("huff_encode_bitvec"));
+    }
+    if let Some((table, lengths)) = simd::short_code_tables(encoded_map) {
+        if let Some(byte) = bytes.iter().find(|byte| lengths[**byte as usize] == 0) {
+            return Err(HuffErr::SymbolNotInCodebook(*byte));
+        }
+        return Ok(simd::huff_encode_packed(bytes, &table, &lengths));
+    }
+
+    let estimated = encoder::HuffmanEncoder::estimated_output_size(bytes, encoded_map);
+    let mut final_bits: BitVec<u8, Msb0> = BitVec::with_capacity(estimated as usize * 8);
     for byte in bytes {
-        let encoded = encoded_map.get(byte).unwrap();
-        final_bits.extend(encoded.bits.iter());
+        let encoded = encoded_map
+            .get(byte)
+            .ok_or(HuffErr::SymbolNotInCodebook(*byte))?;
+        final_bits.extend_from_bitslice(&encoded.bits);
     }
 
     let total_bits = final_bits.len();
-    (final_bits.into(), total_bits as u64)
+    Ok((final_bits.into(), total_bits as u64))
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct Encoded {
     bits: BitVec<u8, Msb0>,
     /// Number of bits in the sequence
-    num_bits_sequence: u8,
+    num_bits_sequence: u16,
     value: u8,
 }
 
+impl Encoded {
+    /// Builds the code of `symbol` from its canonical codeword, the lowest `length` bits of
+    /// `canonical_code`, most significant bit first. Bits above `length` are ignored, lengths
+    /// over 32 are padded with leading zero bits.
+    pub fn from_canonical(symbol: u8, length: u8, canonical_code: u32) -> Self {
+        let bits = (0..length)
+            .rev()
+            .map(|shift| shift < 32 && canonical_code >> shift & 1 == 1)
+            .collect();
+        Self {
+            bits,
+            num_bits_sequence: length.into(),
+            value: symbol,
+        }
+    }
+}
+
+/// Shows the symbol, its code most significant bit first and the code length, e.g.
+/// `'A' (0x41) -> 101 (3 bits)`. Bytes that are not printable ASCII are escaped.
+impl fmt::Display for Encoded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "'{}' (0x{:02x}) -> ",
+            std::ascii::escape_default(self.value),
+            self.value
+        )?;
+        for bit in self.bits.iter().by_vals() {
+            f.write_str(if bit { "1" } else { "0" })?;
+        }
+        let len = self.bits.len();
+        write!(f, " ({} bit{})", len, if len == 1 { "" } else { "s" })
+    }
+}
+
+/// A code map that displays one `Encoded` per line, sorted by code length, then by symbol.
+#[derive(Debug, PartialEq, Eq)]
+pub struct EncodingTable(pub HashMap<u8, Encoded>);
+
+impl fmt::Display for EncodingTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut entries: Vec<&Encoded> = self.0.values().collect();
+        entries.sort_unstable_by_key(|encoded| (encoded.bits.len(), encoded.value));
+        for encoded in entries {
+            writeln!(f, "{}", encoded)?;
+        }
+        Ok(())
+    }
+}
+
 fn u64_to_u8(value: u64) -> [u8; 8] {
     [
         (value >> 56) as u8,
@@ -94,19 +800,125 @@ fn u64_to_u8(value: u64) -> [u8; 8] {
     ]
 }
 
+/// Format byte of a buffer holding the original bytes uncompressed.
+const FORMAT_STORED: u8 = 0x00;
+/// Format byte of a Huffman encoded buffer.
+const FORMAT_HUFFMAN: u8 = 0x01;
+
+/// First bytes of every buffer written by `serialize_huffman`, "HUFF".
+pub const HUFF_MAGIC: [u8; 4] = *b"HUFF";
+/// Version of the `.huff` format written by `serialize_huffman`, following `HUFF_MAGIC`.
+///
+/// * Version 2 - The version is followed by a CRC-32 of the rest of the buffer. A header entry is
+///   the symbol, the code length in bits (2 bytes) and the code, padded with zeros to whole
+///   bytes.
+/// * Version 1 - A header entry is the symbol, the code length in bits and the last bit of the
+///   code, which only describes the unary codes of `encode_huffman_array`. Still read by
+///   `deserialize_huffman`.
+/// * Version 0 - The format of huffc 0.1.1 and earlier, with no magic bytes, version or format
+///   byte. It starts with the total number of bits and the size of the header in bytes (8 bytes
+///   each, big endian), followed by the version 1 header entries and the bit buffer. Prepending
+///   `HUFF_MAGIC`, the version byte `0x01` and the Huffman format byte `0x01` to a version 0
+///   buffer migrates it.
+pub const HUFF_FORMAT_VERSION: u8 = 2;
+
+/// Format version whose header holds only the last bit of each code, see `HUFF_FORMAT_VERSION`.
+const HUFF_FORMAT_VERSION_LAST_BIT: u8 = 1;
+
+/// Length of `HUFF_MAGIC`, the version and the checksum in front of the payload.
+const HUFF_HEADER_LEN: usize = 9;
+
+/// Offset of the payload, starting with the format byte, in a buffer of format `version`. Only
+/// `HUFF_FORMAT_VERSION` has a checksum.
+pub(crate) fn huff_payload_offset(version: u8) -> usize {
+    if version == HUFF_FORMAT_VERSION {
+        HUFF_HEADER_LEN
+    } else {
+        HUFF_MAGIC.len() + 1
+    }
+}
+
+/// `HUFF_MAGIC`, `HUFF_FORMAT_VERSION` and room for the checksum, which `seal_huff_buffer` fills
+/// in once the payload is appended.
+fn start_huff_buffer(payload_len: usize) -> Vec<u8> {
+    let mut buffer = Vec::with_capacity(HUFF_HEADER_LEN + payload_len);
+    buffer.extend_from_slice(&HUFF_MAGIC);
+    buffer.push(HUFF_FORMAT_VERSION);
+    buffer.extend_from_slice(&[0; 4]);
+    buffer
+}
+
+/// Writes the checksum of the payload of a buffer started with `start_huff_buffer`.
+fn seal_huff_buffer(mut buffer: Vec<u8>) -> Vec<u8> {
+    let checksum = crc32fast::hash(&buffer[HUFF_HEADER_LEN..]);
+    buffer[HUFF_MAGIC.len() + 1..HUFF_HEADER_LEN].copy_from_slice(&checksum.to_be_bytes());
+    buffer
+}
+
+/// Puts `payload`, which starts with its format byte, behind `HUFF_MAGIC`, `HUFF_FORMAT_VERSION`
+/// and its checksum. Used for the compression level and block buffers.
+pub(crate) fn wrap_huff_payload(payload: &[u8]) -> Vec<u8> {
+    let mut buffer = start_huff_buffer(payload.len());
+    buffer.extend_from_slice(payload);
+    seal_huff_buffer(buffer)
+}
+
+/// Length of the `.huff` buffer at the start of `bytes`, which more buffers may follow, e.g. the
+/// parts of a split file. Huffman and block payloads are self delimiting, any other buffer runs
+/// to the end of `bytes`.
+pub(crate) fn huff_buffer_len(bytes: &[u8]) -> usize {
+    let payload_len = match bytes.get(HUFF_MAGIC.len()) {
+        Some(&HUFF_FORMAT_VERSION) => match bytes.get(HUFF_HEADER_LEN..) {
+            Some(payload) if payload.first() == Some(&FORMAT_HUFFMAN) => huffman_frame_len(payload),
+            Some(payload) if payload.first() == Some(&parallel::FORMAT_BLOCKS) => {
+                parallel::blocks_frame_len(payload)
+            }
+            _ => None,
+        },
+        _ => None,
+    };
+    payload_len.map_or(bytes.len(), |payload_len| HUFF_HEADER_LEN + payload_len)
+}
+
+/// Serializes the encoded data, the layout is: `HUFF_MAGIC`, `HUFF_FORMAT_VERSION`, the CRC-32 of
+/// everything after it (4 bytes), format byte (`0x01`), total number of bits (8 bytes), size of
+/// the symbol header in bytes (8 bytes), the symbol header, then the bit buffer. A header entry is
+/// the symbol, the code length in bits (2 bytes), then the code padded with zeros to whole bytes,
+/// so any prefix free code can be stored. The header entries are written in ascending symbol
+/// order, so the same input always gives the same bytes.
+#[must_use = "the serialized buffer must be written out"]
 pub fn serialize_huffman(
     encoded_map: &HashMap<u8, Encoded>,
     bit_buffer: Vec<u8>,
     total_bits: u64,
 ) -> Vec<u8> {
-    let mut serialized_buffer = u64_to_u8(total_bits).to_vec();
+    let frame = serialize_huffman_frame(encoded_map, bit_buffer, total_bits);
+    let mut serialized_buffer = start_huff_buffer(frame.len());
+    serialized_buffer.extend(frame);
+    seal_huff_buffer(serialized_buffer)
+}
+
+/// Serializes the encoded data without the magic bytes and version, starting with the format
+/// byte. Used for the Huffman buffers nested in other formats, e.g. compression levels and
+/// blocks.
+pub(crate) fn serialize_huffman_frame(
+    encoded_map: &HashMap<u8, Encoded>,
+    bit_buffer: Vec<u8>,
+    total_bits: u64,
+) -> Vec<u8> {
+    let mut serialized_buffer = vec![FORMAT_HUFFMAN];
+    serialized_buffer.extend_from_slice(&u64_to_u8(total_bits));
 
     let mut tmp_buffer = Vec::new();
 
-    for encoded in encoded_map.values() {
+    let mut codes: Vec<&Encoded> = encoded_map.values().collect();
+    codes.sort_unstable_by_key(|encoded| encoded.value);
+    for encoded in codes {
         tmp_buffer.push(encoded.value);
-        tmp_buffer.push(encoded.num_bits_sequence);
-        tmp_buffer.push(*encoded.bits.last().unwrap() as u8);
+        tmp_buffer.extend_from_slice(&encoded.num_bits_sequence.to_be_bytes());
+        let mut code = encoded.bits.clone();
+        code.set_uninitialized(false);
+        tmp_buffer.extend(code.into_vec());
     }
 
     let size_of_header_bytes = tmp_buffer.len() as u64;
@@ -118,72 +930,459 @@ pub fn serialize_huffman(
     serialized_buffer
 }
 
-#[derive(Debug, Clone, Copy)]
-struct ValueBitMap {
+/// Serializes the encoded data like `serialize_huffman`, unless that would be larger than the
+/// original input, in which case the original bytes are stored as is behind the magic bytes,
+/// version, checksum and the format byte `0x00`. `deserialize_huffman` handles both layouts.
+pub fn serialize_huffman_or_passthrough(
+    encoded_map: &HashMap<u8, Encoded>,
+    bit_buffer: Vec<u8>,
+    total_bits: u64,
+    original: &[u8],
+) -> Vec<u8> {
+    let serialized_buffer = serialize_huffman(encoded_map, bit_buffer, total_bits);
+    if serialized_buffer.len() <= original.len() + HUFF_HEADER_LEN {
+        return serialized_buffer;
+    }
+
+    serialize_stored(original)
+}
+
+/// The original bytes uncompressed behind the magic bytes, version, checksum and the format byte
+/// `0x00`.
+fn serialize_stored(original: &[u8]) -> Vec<u8> {
+    let mut stored_buffer = start_huff_buffer(original.len() + 1);
+    stored_buffer.push(FORMAT_STORED);
+    stored_buffer.extend_from_slice(original);
+    seal_huff_buffer(stored_buffer)
+}
+
+/// Length in bytes of the Huffman encoded buffer at the start of `huff_bytes`, `None` if it is
+/// not a Huffman encoded buffer or is truncated. Allows buffers written back to back to be split.
+pub(crate) fn huffman_frame_len(huff_bytes: &[u8]) -> Option<usize> {
+    if huff_bytes.len() < 17 || huff_bytes[0] != FORMAT_HUFFMAN {
+        return None;
+    }
+
+    let total_bits = u8_to_u64(&huff_bytes[1..9]);
+    let header_num_bytes = u8_to_u64(&huff_bytes[9..17]);
+    let frame_len = 17u64
+        .checked_add(header_num_bytes)?
+        .checked_add(total_bits.div_ceil(8))?;
+    usize::try_from(frame_len)
+        .ok()
+        .filter(|frame_len| *frame_len <= huff_bytes.len())
+}
+
+/// Returns true if the serialized buffer holds the original bytes uncompressed.
+pub fn is_stored_passthrough(huff_bytes: &[u8]) -> bool {
+    let body = match huff_bytes.strip_prefix(&HUFF_MAGIC) {
+        Some(rest) => rest
+            .first()
+            .and_then(|version| huff_bytes.get(huff_payload_offset(*version)..))
+            .unwrap_or_default(),
+        None => huff_bytes,
+    };
+    body.first() == Some(&FORMAT_STORED)
+}
+
+/// Entry of the decode table, maps a symbol to the last bit of its code. Together with the code
+/// length this identifies the symbol during decoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueBitMap {
     value: u8,
     ends_in_1: bool,
 }
 
-pub fn deserialize_huffman(huff_bytes: &[u8]) -> Vec<u8> {
-    let total_bits = u8_to_u64(&huff_bytes[0..8]);
-    let header_end_byte = 16;
-    let header_num_bytes = u8_to_u64(&huff_bytes[8..header_end_byte]);
+impl ValueBitMap {
+    pub fn new(value: u8, ends_in_1: bool) -> Self {
+        Self { value, ends_in_1 }
+    }
 
-    let mut map_values = HashMap::new();
-    let mut idx = header_end_byte;
-    let mut max_bits = 0;
-    while idx - header_end_byte < header_num_bytes as usize {
-        let value = huff_bytes[idx];
-        let encoding_number_of_bits = huff_bytes[idx + 1];
-        let ends_in_1 = huff_bytes[idx + 2] != 0;
+    /// The decoded symbol.
+    pub fn value(&self) -> u8 {
+        self.value
+    }
 
-        max_bits = max_bits.max(encoding_number_of_bits);
-        let value_bit_map = ValueBitMap { value, ends_in_1 };
+    /// Whether the code of the symbol ends in a 1 bit.
+    pub fn ends_in_1(&self) -> bool {
+        self.ends_in_1
+    }
+}
 
-        map_values
-            .entry(encoding_number_of_bits)
-            .and_modify(|v: &mut Vec<ValueBitMap>| v.push(value_bit_map))
-            .or_insert(vec![value_bit_map]);
+/// Summary of a `.huff` buffer, returned by `validate_huff_bytes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HuffHeader {
+    pub format_version: u8,
+    /// Format byte of the payload, `0x00` stored, `0x01` Huffman, otherwise the format byte of a
+    /// compression level or block buffer, see `level`.
+    pub format: u8,
+    /// Number of encoded bits, for a stored buffer the number of stored bits.
+    pub total_bits: u64,
+    /// Number of entries in the code header, 0 for a stored buffer.
+    pub symbol_count: usize,
+    /// Bytes following the headers, the bit buffer or the stored bytes.
+    pub compressed_payload_bytes: usize,
+}
 
-        idx += 3
+/// Checks that `bytes` is a structurally valid `.huff` buffer without decoding it: the magic
+/// bytes, a supported format version, the checksum of the payload, a code header that fits in
+/// the buffer and a non-zero number of encoded bits that fits in the bit buffer. A buffer that
+/// passes can still fail to decode if its bits match no code. The payloads of compression level
+/// and block buffers are only checked against the checksum, they report every payload bit and
+/// no symbols.
+///
+/// # Errors
+///
+/// * `HuffErr::NullInput` - The buffer is empty.
+/// * `HuffErr::NotAHuffFile` - The buffer does not start with `HUFF_MAGIC`.
+/// * `HuffErr::UnsupportedVersion` - The format version is neither `HUFF_FORMAT_VERSION` nor 1.
+/// * `HuffErr::ChecksumMismatch` - The payload does not match its checksum.
+/// * `HuffErr::MalformedData` - The buffer is truncated, inconsistent or of another format.
+/// * `HuffErr::ArithmeticOverflow` - A size in the header overflows.
+///
+/// # Examples
+///
+/// ```rust
+/// let compressed = huffc::compress(&b"abracadabra".repeat(10)).unwrap();
+/// let header = huffc::validate_huff_bytes(&compressed).unwrap();
+/// assert_eq!(header.format_version, huffc::HUFF_FORMAT_VERSION);
+/// assert_eq!(header.symbol_count, 5);
+/// ```
+pub fn validate_huff_bytes(bytes: &[u8]) -> Result<HuffHeader, HuffErr> {
+    if bytes.is_empty() {
+        return Err(HuffErr::NullInput("validate_huff_bytes"));
     }
+    let Some(rest) = bytes.strip_prefix(&HUFF_MAGIC) else {
+        return Err(HuffErr::NotAHuffFile);
+    };
+    let Some((&format_version, body)) = rest.split_first() else {
+        return Err(HuffErr::MalformedData("missing format version".to_string()));
+    };
+    if format_version != HUFF_FORMAT_VERSION && format_version != HUFF_FORMAT_VERSION_LAST_BIT {
+        return Err(HuffErr::UnsupportedVersion(format_version));
+    }
+    let body = if format_version == HUFF_FORMAT_VERSION {
+        let Some((checksum, payload)) = body.split_first_chunk::<4>() else {
+            return Err(HuffErr::MalformedData("missing checksum".to_string()));
+        };
+        let expected = u32::from_be_bytes(*checksum);
+        let actual = crc32fast::hash(payload);
+        if actual != expected {
+            return Err(HuffErr::ChecksumMismatch { expected, actual });
+        }
+        payload
+    } else {
+        body
+    };
 
-    let mut decoded_buffer: Vec<u8> = Vec::new();
-    let bit_vec: BitVec<u8, Msb0> = BitVec::from_slice(&huff_bytes[idx..]);
-    let mut bit_vec_iter = bit_vec.iter();
-
-    let mut bits_to_target = 0;
+    let overflow = || HuffErr::ArithmeticOverflow;
+    match body.first() {
+        Some(&FORMAT_STORED) => {
+            let compressed_payload_bytes = body.len() - 1;
+            Ok(HuffHeader {
+                format_version,
+                format: FORMAT_STORED,
+                total_bits: (compressed_payload_bytes as u64)
+                    .checked_mul(8)
+                    .ok_or_else(overflow)?,
+                symbol_count: 0,
+                compressed_payload_bytes,
+            })
+        }
+        Some(&FORMAT_HUFFMAN) => {
+            let header_end_byte = 17;
+            if body.len() < header_end_byte {
+                return Err(HuffErr::MalformedData(
+                    "truncated Huffman buffer".to_string(),
+                ));
+            }
+            let total_bits = u8_to_u64(&body[1..9]);
+            let header_num_bytes =
+                usize::try_from(u8_to_u64(&body[9..header_end_byte])).map_err(|_| overflow())?;
+            let bits_start = header_end_byte
+                .checked_add(header_num_bytes)
+                .ok_or_else(overflow)?;
+            let Some(header) = body.get(header_end_byte..bits_start) else {
+                return Err(HuffErr::MalformedData("truncated header".to_string()));
+            };
+            let symbol_count = if format_version == HUFF_FORMAT_VERSION_LAST_BIT {
+                parse_last_bit_header(header)?.len()
+            } else {
+                parse_code_header(header)?.len()
+            };
 
-    let mut read_bits = 0;
-    while read_bits < total_bits {
-        let bit = bit_vec_iter.next().unwrap();
-        if *bit {
-            bits_to_target += 1;
-            // Here I need to do logic to find the number of bits
-            let value_bit_map = map_values.get(&bits_to_target).unwrap();
-            decoded_buffer.push(value_bit_map.iter().find(|v| v.ends_in_1).unwrap().value);
-            read_bits += bits_to_target as u64;
-            bits_to_target = 0;
-        } else {
-            bits_to_target += 1;
-            if bits_to_target >= max_bits {
-                // We hit the least occuring character, now we need to find it
-                let value_bit_map = map_values.get(&bits_to_target).unwrap();
-                decoded_buffer.push(value_bit_map.iter().find(|v| !v.ends_in_1).unwrap().value);
-                read_bits += bits_to_target as u64;
-                bits_to_target = 0
+            if total_bits == 0 {
+                return Err(HuffErr::MalformedData(
+                    "total number of bits is zero".to_string(),
+                ));
+            }
+            let compressed_payload_bytes = body.len() - bits_start;
+            if total_bits.div_ceil(8) > compressed_payload_bytes as u64 {
+                return Err(HuffErr::MalformedData(
+                    "bit buffer is shorter than the total number of bits".to_string(),
+                ));
             }
+            Ok(HuffHeader {
+                format_version,
+                format: FORMAT_HUFFMAN,
+                total_bits,
+                symbol_count,
+                compressed_payload_bytes,
+            })
         }
+        Some(&format)
+            if format_version == HUFF_FORMAT_VERSION && level::is_level_format(format) =>
+        {
+            let compressed_payload_bytes = body.len() - 1;
+            Ok(HuffHeader {
+                format_version,
+                format,
+                total_bits: (compressed_payload_bytes as u64)
+                    .checked_mul(8)
+                    .ok_or_else(overflow)?,
+                symbol_count: 0,
+                compressed_payload_bytes,
+            })
+        }
+        Some(format) => Err(HuffErr::MalformedData(format!(
+            "unsupported format byte {:#04x}",
+            format
+        ))),
+        None => Err(HuffErr::MalformedData("missing format byte".to_string())),
+    }
+}
+
+/// Decodes a buffer written by `serialize_huffman`, `serialize_huffman_or_passthrough` or, with
+/// a single part, by any function of `level` and `parallel`. The bits are read in place with
+/// `HuffmanBitReader`, so a memory-mapped file is decoded without copying it, only the output is
+/// allocated. The buffer is checked with `validate_huff_bytes` first.
+///
+/// # Errors
+///
+/// * `HuffErr::NullInput` - The buffer is empty.
+/// * `HuffErr::NotAHuffFile` - The buffer does not start with `HUFF_MAGIC`, e.g. a version 0
+///   buffer.
+/// * `HuffErr::UnsupportedVersion` - The format version is neither `HUFF_FORMAT_VERSION` nor 1.
+/// * `HuffErr::ChecksumMismatch` - The payload does not match its checksum.
+/// * `HuffErr::MalformedData` - The buffer is truncated, inconsistent or of another format.
+/// * `HuffErr::CorruptedBitstream` - The bit buffer contains a sequence that matches no code.
+/// * `HuffErr::ArithmeticOverflow` - A size in the header overflows.
+#[must_use = "the decoded bytes are the decompressed data"]
+pub fn deserialize_huffman(huff_bytes: &[u8]) -> Result<Vec<u8>, HuffErr> {
+    if huff_bytes.is_empty() {
+        return Err(HuffErr::NullInput("deserialize_huffman"));
     }
+    let header = validate_huff_bytes(huff_bytes)?;
+    let body = &huff_bytes[huff_payload_offset(header.format_version)..];
 
-    decoded_buffer
+    match header.format {
+        FORMAT_STORED => Ok(body[1..].to_vec()),
+        _ if header.format_version == HUFF_FORMAT_VERSION_LAST_BIT => decode_last_bit_frame(body),
+        FORMAT_HUFFMAN => decode_huffman_frame(body),
+        _ => level::decompress_payload(body),
+    }
 }
 
-fn u8_to_u64(bytes: &[u8]) -> u64 {
-    assert!(bytes.len() >= 8);
-    (bytes[0] as u64) << 56
-        | (bytes[1] as u64) << 48
-        | (bytes[2] as u64) << 40
+/// Decodes a Huffman encoded buffer (format byte `0x01`) whose header holds the full codes. All
+/// index arithmetic is checked, so malformed input is reported as an error instead of a panic.
+pub(crate) fn decode_huffman_frame(huff_bytes: &[u8]) -> Result<Vec<u8>, HuffErr> {
+    let overflow = || HuffErr::ArithmeticOverflow;
+    let header_end_byte = 17;
+    if huff_bytes.len() < header_end_byte {
+        return Err(HuffErr::MalformedData(
+            "truncated Huffman buffer".to_string(),
+        ));
+    }
+
+    let total_bits = u8_to_u64(&huff_bytes[1..9]);
+    let header_num_bytes =
+        usize::try_from(u8_to_u64(&huff_bytes[9..header_end_byte])).map_err(|_| overflow())?;
+    let bits_start = header_end_byte
+        .checked_add(header_num_bytes)
+        .ok_or_else(overflow)?;
+    let Some(header) = huff_bytes.get(header_end_byte..bits_start) else {
+        return Err(HuffErr::MalformedData("truncated header".to_string()));
+    };
+
+    let encoded_map = parse_code_header(header)?;
+    let trie = DecodeTrie::new(&encoded_map)?;
+
+    let bits = BitSlice::<u8, Msb0>::from_slice(&huff_bytes[bits_start..]);
+    let Some(bits) = usize::try_from(total_bits)
+        .ok()
+        .and_then(|total_bits| bits.get(..total_bits))
+    else {
+        return Err(HuffErr::MalformedData(
+            "bit buffer is shorter than the total number of bits".to_string(),
+        ));
+    };
+
+    let mut decoded_buffer: Vec<u8> = Vec::new();
+    let mut read_bits = 0;
+    while read_bits < bits.len() {
+        match trie.decode(&bits[read_bits..]) {
+            Ok(Some((symbol, code_len))) => {
+                decoded_buffer.push(symbol);
+                read_bits += code_len;
+            }
+            Ok(None) => {
+                return Err(HuffErr::MalformedData(
+                    "bit buffer ends inside a code".to_string(),
+                ))
+            }
+            Err(_) => {
+                return Err(HuffErr::CorruptedBitstream {
+                    bit_offset: read_bits as u64,
+                })
+            }
+        }
+    }
+
+    Ok(decoded_buffer)
+}
+
+/// Parses the symbol header of a Huffman encoded buffer, every entry is the symbol, the code
+/// length in bits (2 bytes) and the code padded to whole bytes.
+pub(crate) fn parse_code_header(header: &[u8]) -> Result<HashMap<u8, Encoded>, HuffErr> {
+    let mut encoded_map = HashMap::new();
+    let mut idx = 0;
+    while idx < header.len() {
+        let Some(&[value, len_high, len_low]) = header.get(idx..idx + 3) else {
+            return Err(HuffErr::MalformedData("truncated header".to_string()));
+        };
+        let num_bits_sequence = u16::from_be_bytes([len_high, len_low]);
+        let code_end = idx + 3 + (num_bits_sequence as usize).div_ceil(8);
+        let Some(code) = header.get(idx + 3..code_end) else {
+            return Err(HuffErr::MalformedData("truncated header".to_string()));
+        };
+
+        let bits = BitSlice::<u8, Msb0>::from_slice(code)[..num_bits_sequence as usize].to_bitvec();
+        let encoded = Encoded {
+            bits,
+            num_bits_sequence,
+            value,
+        };
+        if encoded_map.insert(value, encoded).is_some() {
+            return Err(HuffErr::MalformedData(format!(
+                "duplicate header entry for byte {:#04x}",
+                value
+            )));
+        }
+        idx = code_end;
+    }
+    Ok(encoded_map)
+}
+
+/// Parses the symbol header of a format version 1 buffer, every entry is the symbol, the code
+/// length and the last bit of its unary code, which is zeros up to that bit.
+pub(crate) fn parse_last_bit_header(header: &[u8]) -> Result<HashMap<u8, Encoded>, HuffErr> {
+    if !header.len().is_multiple_of(3) {
+        return Err(HuffErr::MalformedData("truncated header".to_string()));
+    }
+
+    let mut encoded_map = HashMap::new();
+    for entry in header.chunks_exact(3) {
+        let (value, num_bits, ends_in_1) = (entry[0], entry[1] as usize, entry[2] != 0);
+        let mut bits: BitVec<u8, Msb0> = BitVec::repeat(false, num_bits.saturating_sub(1));
+        if num_bits > 0 {
+            bits.push(ends_in_1);
+        }
+        let encoded = Encoded {
+            bits,
+            num_bits_sequence: num_bits as u16,
+            value,
+        };
+        if encoded_map.insert(value, encoded).is_some() {
+            return Err(HuffErr::MalformedData(format!(
+                "duplicate header entry for byte {:#04x}",
+                value
+            )));
+        }
+    }
+    Ok(encoded_map)
+}
+
+/// Decodes a Huffman encoded buffer of format version 1, whose header holds only the last bit
+/// of each code. All index arithmetic is checked, so malformed input is reported as an error
+/// instead of a panic.
+fn decode_last_bit_frame(huff_bytes: &[u8]) -> Result<Vec<u8>, HuffErr> {
+    let overflow = || HuffErr::ArithmeticOverflow;
+    let header_end_byte = 17;
+    if huff_bytes.len() < header_end_byte {
+        return Err(HuffErr::MalformedData(
+            "truncated Huffman buffer".to_string(),
+        ));
+    }
+
+    let total_bits = u8_to_u64(&huff_bytes[1..9]);
+    let header_num_bytes =
+        usize::try_from(u8_to_u64(&huff_bytes[9..header_end_byte])).map_err(|_| overflow())?;
+
+    let mut map_values = HashMap::new();
+    let mut idx = header_end_byte;
+    let mut max_bits = 0;
+    while idx.checked_sub(header_end_byte).ok_or_else(overflow)? < header_num_bytes {
+        let entry_end = idx.checked_add(3).ok_or_else(overflow)?;
+        let Some(&[value, encoding_number_of_bits, ends_in_1]) = huff_bytes.get(idx..entry_end)
+        else {
+            return Err(HuffErr::MalformedData("truncated header".to_string()));
+        };
+
+        max_bits = max_bits.max(encoding_number_of_bits);
+        let value_bit_map = ValueBitMap::new(value, ends_in_1 != 0);
+
+        map_values
+            .entry(encoding_number_of_bits)
+            .and_modify(|v: &mut Vec<ValueBitMap>| v.push(value_bit_map))
+            .or_insert(vec![value_bit_map]);
+
+        idx = entry_end;
+    }
+
+    let find_value = |num_bits: u8, ends_in_1: bool| {
+        map_values
+            .get(&num_bits)
+            .and_then(|v| v.iter().find(|v| v.ends_in_1() == ends_in_1))
+            .map(ValueBitMap::value)
+            .ok_or(HuffErr::MalformedData(format!(
+                "no symbol for a code of {} bits",
+                num_bits
+            )))
+    };
+
+    let mut decoded_buffer: Vec<u8> = Vec::new();
+    let mut bit_reader = HuffmanBitReader::new(&huff_bytes[idx..]);
+
+    let mut bits_to_target: u8 = 0;
+
+    let mut read_bits: u64 = 0;
+    while read_bits < total_bits {
+        let bit = bit_reader.read_bit().ok_or(HuffErr::MalformedData(
+            "bit buffer ends inside a code".to_string(),
+        ))?;
+        bits_to_target = bits_to_target.checked_add(1).ok_or_else(overflow)?;
+        if bit {
+            decoded_buffer.push(find_value(bits_to_target, true)?);
+        } else if bits_to_target >= max_bits {
+            // We hit the least occuring character, now we need to find it
+            decoded_buffer.push(find_value(bits_to_target, false)?);
+        } else {
+            continue;
+        }
+        read_bits = read_bits
+            .checked_add(bits_to_target as u64)
+            .ok_or_else(overflow)?;
+        bits_to_target = 0;
+    }
+
+    Ok(decoded_buffer)
+}
+
+fn u8_to_u64(bytes: &[u8]) -> u64 {
+    assert!(bytes.len() >= 8);
+    (bytes[0] as u64) << 56
+        | (bytes[1] as u64) << 48
+        | (bytes[2] as u64) << 40
         | (bytes[3] as u64) << 32
         | (bytes[4] as u64) << 24
         | (bytes[5] as u64) << 16
@@ -195,23 +1394,94 @@ fn u8_to_u64(bytes: &[u8]) -> u64 {
 /// is 01, the next 001 and so on... until the last one which is encoded 0 (repeated n) where n is
 /// the length of the vector, the least frequent is at the back the most frequent is at the front,
 /// the actual frequency does not matter, only their relative frequency, which is represented by
-/// their position in the buffer
-pub fn build_huffman_array(mut freq_buffer: FrequencyBuffer) -> Vec<u8> {
-    let mut buffer = VecDeque::new();
-    while let Some((idx, _)) = find_and_pop_min(&mut freq_buffer.0) {
-        buffer.push_front(idx);
+/// their position in the buffer. Symbols with equal frequencies are ordered by ascending symbol
+/// value, so the array does not depend on anything but the frequencies. The frequencies are only
+/// borrowed and can be reused afterwards, for example for `FrequencyBuffer::entropy`.
+///
+/// This is the legacy path, the unary codes are only optimal when every symbol is at least twice
+/// as frequent as the next one. `build_huffman_tree` with `encode_huffman_tree` gives real
+/// Huffman codes.
+#[must_use = "the huffman array must be passed to encode_huffman_array"]
+pub fn build_huffman_array(freq_buffer: &FrequencyBuffer) -> Vec<u8> {
+    // Pops the least frequent first and ties by the higher symbol first, so the reversed order
+    // is that of `build_huffman_array_from_counts`
+    let mut heap: BinaryHeap<(Reverse<u64>, u8)> = freq_buffer
+        .0
+        .iter()
+        .enumerate()
+        .filter(|(_, count)| **count != 0)
+        .map(|(symbol, count)| (Reverse(*count), symbol as u8))
+        .collect();
+    let mut huffman_array = Vec::with_capacity(freq_buffer.non_zero_count());
+    while let Some((_, symbol)) = heap.pop() {
+        huffman_array.push(symbol);
     }
-    buffer.into()
+    huffman_array.reverse();
+    huffman_array
+}
+
+/// Builds the huffman array from a tree built with the two-queue algorithm, see
+/// `build_huffman_tree_two_queue`. The symbols are ordered by their depth in the tree, then most
+/// frequent first and ties by the lower symbol first, which is the order of
+/// `build_huffman_array` unless symbols with equal counts end up at different depths.
+pub fn build_huffman_array_two_queue(freq_buffer: FrequencyBuffer) -> Vec<u8> {
+    let Some(tree) = build_huffman_tree_two_queue(&freq_buffer) else {
+        return Vec::new();
+    };
+
+    let mut depths = tree.leaf_depths();
+    depths.sort_unstable_by(|(a_symbol, a_depth), (b_symbol, b_depth)| {
+        a_depth
+            .cmp(b_depth)
+            .then(freq_buffer[*b_symbol].cmp(&freq_buffer[*a_symbol]))
+            .then(a_symbol.cmp(b_symbol))
+    });
+    depths.into_iter().map(|(symbol, _)| symbol).collect()
+}
+
+/// Builds the huffman array from unsorted `(symbol, count)` pairs, the same ordering as
+/// `build_huffman_array`: most frequent first, ties broken by the lower symbol first. Symbols
+/// with a count of zero are left out.
+///
+/// # Errors
+///
+/// Returns `HuffErr::InvalidFrequencyTable` if a symbol appears more than once.
+pub fn build_huffman_array_from_counts(counts: &[(u8, u64)]) -> Result<Vec<u8>, HuffErr> {
+    let mut seen = [false; 256];
+    for (symbol, _) in counts {
+        if seen[*symbol as usize] {
+            return Err(HuffErr::InvalidFrequencyTable(format!(
+                "duplicate symbol {:#04x}",
+                symbol
+            )));
+        }
+        seen[*symbol as usize] = true;
+    }
+
+    let mut sorted: Vec<(u8, u64)> = counts
+        .iter()
+        .filter(|(_, count)| *count != 0)
+        .copied()
+        .collect();
+    sorted.sort_unstable_by(|(a_symbol, a_count), (b_symbol, b_count)| {
+        b_count.cmp(a_count).then(a_symbol.cmp(b_symbol))
+    });
+    Ok(sorted.into_iter().map(|(symbol, _)| symbol).collect())
 }
 
+/// Assigns the unary codes of a huffman array from `build_huffman_array`, see there. Legacy path,
+/// `encode_huffman_tree` assigns real Huffman codes.
+#[must_use = "the encoded map must be passed to huff_encode_bitvec"]
 pub fn encode_huffman_array(huffman_array: &[u8]) -> HashMap<u8, Encoded> {
     huffman_array
         .iter()
         .enumerate()
         .map(|(idx, value)| {
             if idx == huffman_array.len() - 1 {
-                let bv: BitVec<u8, Msb0> = (0..idx as u8).map(|_| false).collect();
-                let num_bits_sequence = bv.len() as u8;
+                // A single symbol gets the code `0` rather than an empty code, which could not
+                // be decoded
+                let bv: BitVec<u8, Msb0> = (0..idx.max(1)).map(|_| false).collect();
+                let num_bits_sequence = bv.len() as u16;
                 return (
                     *value,
                     Encoded {
@@ -230,7 +1500,7 @@ pub fn encode_huffman_array(huffman_array: &[u8]) -> HashMap<u8, Encoded> {
                     false
                 })
                 .collect();
-            let num_bits_sequence = bv.len() as u8;
+            let num_bits_sequence = bv.len() as u16;
             (
                 *value,
                 Encoded {
@@ -243,6 +1513,188 @@ pub fn encode_huffman_array(huffman_array: &[u8]) -> HashMap<u8, Encoded> {
         .collect()
 }
 
+/// Replaces every code of `encoded_map` by its canonical Huffman code, keeping the code lengths.
+/// The symbols are sorted by code length, then by symbol, and take consecutive codes in that
+/// order, each shifted left when the length grows. The codes are then fully determined by the
+/// lengths, e.g. lengths 1, 2, 3, 3 give `0`, `10`, `110`, `111`.
+///
+/// # Errors
+///
+/// Returns `HuffErr::MalformedData` if the code lengths violate the Kraft inequality, which those
+/// of any prefix code satisfy, `encoded_map` is left unchanged then.
+pub fn canonicalize_encoding(encoded_map: &mut HashMap<u8, Encoded>) -> Result<(), HuffErr> {
+    let mut entries: Vec<&mut Encoded> = encoded_map.values_mut().collect();
+    entries.sort_unstable_by_key(|encoded| (encoded.bits.len(), encoded.value));
+
+    let mut codes: Vec<BitVec<u8, Msb0>> = Vec::with_capacity(entries.len());
+    let mut code: BitVec<u8, Msb0> = BitVec::new();
+    for (idx, encoded) in entries.iter().enumerate() {
+        if idx > 0 {
+            // Increment the previous code, the lengths of a prefix code never run out of codes.
+            // `BitSlice::last_zero` overflows on some all ones slices, so search by value.
+            let Some(last_zero) = code.iter().by_vals().rposition(|bit| !bit) else {
+                return Err(HuffErr::MalformedData(
+                    "code lengths violate the Kraft inequality".to_string(),
+                ));
+            };
+            code.set(last_zero, true);
+            code[last_zero + 1..].fill(false);
+        }
+        code.resize(encoded.bits.len(), false);
+        codes.push(code.clone());
+    }
+
+    for (encoded, code) in entries.into_iter().zip(codes) {
+        encoded.bits = code;
+    }
+    Ok(())
+}
+
+/// The canonical Huffman codes of the non-zero entries of `freq_buffer`, the codes `compress`
+/// uses: `build_huffman_tree` and `encode_huffman_tree` give the code lengths,
+/// `canonicalize_encoding` the codes. Empty if every count is zero.
+///
+/// # Errors
+///
+/// Returns `HuffErr::MalformedData` if `canonicalize_encoding` rejects the code lengths of the
+/// tree, which does not happen for a well formed tree.
+pub fn canonical_huffman_codes(
+    freq_buffer: &FrequencyBuffer,
+) -> Result<HashMap<u8, Encoded>, HuffErr> {
+    let Some(tree) = build_huffman_tree(freq_buffer) else {
+        return Ok(HashMap::new());
+    };
+    let mut encoded_map = encode_huffman_tree(&tree);
+    canonicalize_encoding(&mut encoded_map)?;
+    Ok(encoded_map)
+}
+
+/// Like `encode_huffman_array`, but no code is longer than `max_depth` bits.
+///
+/// The code lengths are chosen with the package-merge algorithm, weighting the symbols as
+/// `encode_huffman_array` implicitly does: every symbol is twice as frequent as the next one in
+/// the array. When the bound does not bind the codes are identical to those of
+/// `encode_huffman_array`, a single symbol gets a one bit code.
+///
+/// # Errors
+///
+/// * `HuffErr::NullInput` - `huffman_array` is empty.
+/// * `HuffErr::DepthConstraintImpossible` - `max_depth` bits cannot give every symbol a distinct
+///   code, i.e. `2^max_depth` is smaller than the number of symbols.
+pub fn encode_huffman_array_bounded(
+    huffman_array: &[u8],
+    max_depth: u8,
+) -> Result<HashMap<u8, Encoded>, HuffErr> {
+    let num_symbols = huffman_array.len();
+    if num_symbols == 0 {
+        return Err(HuffErr::NullInput("encode_huffman_array_bounded"));
+    }
+    if max_depth == 0 || (max_depth < 64 && 1u64 << max_depth < num_symbols as u64) {
+        return Err(HuffErr::DepthConstraintImpossible {
+            max_depth,
+            num_symbols,
+        });
+    }
+
+    let lengths = package_merge_lengths(num_symbols, max_depth);
+
+    // Canonical codes in array order, complemented so the most frequent symbol gets the ones
+    // like in encode_huffman_array
+    let mut encoded_map = HashMap::with_capacity(num_symbols);
+    let mut code: BitVec<u8, Msb0> = BitVec::new();
+    for (idx, (value, len)) in huffman_array.iter().zip(lengths).enumerate() {
+        if idx > 0 {
+            increment_code(&mut code);
+        }
+        code.resize(len as usize, false);
+        encoded_map.insert(
+            *value,
+            Encoded {
+                bits: !code.clone(),
+                num_bits_sequence: len.into(),
+                value: *value,
+            },
+        );
+    }
+    Ok(encoded_map)
+}
+
+/// Code lengths, in array order, minimising the weighted length with weights `2^-(i + 1)` (the
+/// last symbol sharing the weight of the one before it) subject to `max_depth`.
+fn package_merge_lengths(num_symbols: usize, max_depth: u8) -> Vec<u8> {
+    if num_symbols == 1 {
+        return vec![1];
+    }
+
+    #[derive(Clone, Copy)]
+    enum Item {
+        Leaf(usize),
+        /// Items `2k` and `2k + 1` of the previous list
+        Package(usize),
+    }
+
+    // Codes never need to be longer than num_symbols - 1 bits
+    let depth = (max_depth as usize).min(num_symbols - 1);
+    let weight = |idx: usize| 0.5f64.powi(idx.min(num_symbols - 2) as i32 + 1);
+    // Least frequent first, ties keep leaves ahead of packages
+    let leaves: Vec<(f64, Item)> = (0..num_symbols)
+        .rev()
+        .map(|idx| (weight(idx), Item::Leaf(idx)))
+        .collect();
+
+    let mut lists = vec![leaves.clone()];
+    for _ in 1..depth {
+        let packages = lists
+            .last()
+            .unwrap()
+            .chunks_exact(2)
+            .enumerate()
+            .map(|(k, pair)| (pair[0].0 + pair[1].0, Item::Package(k)));
+
+        let mut merged = Vec::with_capacity(num_symbols * 2);
+        let mut leaves = leaves.iter().copied().peekable();
+        for package in packages {
+            while let Some(leaf) = leaves.next_if(|leaf| leaf.0 <= package.0) {
+                merged.push(leaf);
+            }
+            merged.push(package);
+        }
+        merged.extend(leaves);
+        lists.push(merged);
+    }
+
+    // The 2n - 2 cheapest items of the last list, every occurrence of a leaf adds a bit to its code
+    let mut lengths = vec![0u8; num_symbols];
+    let mut take = 2 * num_symbols - 2;
+    for list in lists.iter().rev() {
+        let mut take_previous = 0;
+        for (_, item) in &list[..take] {
+            match item {
+                Item::Leaf(idx) => lengths[*idx] += 1,
+                Item::Package(k) => take_previous = take_previous.max(2 * k + 2),
+            }
+        }
+        take = take_previous;
+    }
+    lengths
+}
+
+/// Adds one to `code` read as a big endian binary number of fixed width.
+fn increment_code(code: &mut BitVec<u8, Msb0>) {
+    for mut bit in code.iter_mut().rev() {
+        if !*bit {
+            *bit = true;
+            return;
+        }
+        *bit = false;
+    }
+}
+
+/// Converts an encoded map into a table indexed by symbol, symbols without an encoding are `None`.
+pub fn build_encoded_array(mut encoded_map: HashMap<u8, Encoded>) -> [Option<Encoded>; 256] {
+    std::array::from_fn(|symbol| encoded_map.remove(&(symbol as u8)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -271,15 +1723,325 @@ mod tests {
         assert!(result.is_none());
     }
 
+    #[test]
+    fn find_and_pop_min_two_pops_both() {
+        let mut bytes = [4, 0, 1, 8, 1];
+        assert_eq!(
+            find_and_pop_min_two(&mut bytes),
+            Some(((2, 1), Some((4, 1))))
+        );
+        assert_eq!(bytes, [4, 0, 0, 8, 0]);
+        assert_eq!(
+            find_and_pop_min_two(&mut bytes),
+            Some(((0, 4), Some((3, 8))))
+        );
+
+        let mut bytes = [0, 3, 0];
+        assert_eq!(find_and_pop_min_two(&mut bytes), Some(((1, 3), None)));
+        assert_eq!(find_and_pop_min_two(&mut bytes), None);
+    }
+
+    #[test]
+    fn build_huffman_array_matches_from_counts() {
+        let original = std::fs::read("./tests/resources/dna_seq_test.txt").unwrap();
+        for bytes in [&original[..], b"", b"a", b"abracadabra", b"zyxwvzyxwz"] {
+            let counts: Vec<(u8, u64)> = tally_frequency(bytes).into_iter().collect();
+            assert_eq!(
+                build_huffman_array(&tally_frequency(bytes)),
+                build_huffman_array_from_counts(&counts).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_huffman_array_tie_breaking() {
+        let freq_buff = tally_frequency(b"zzzaaammmmbbb");
+        assert_eq!(build_huffman_array(&freq_buff), b"mabz");
+
+        let freq_buff = tally_frequency(b"cab");
+        assert_eq!(build_huffman_array(&freq_buff), b"abc");
+    }
+
     #[test]
     fn build_small_huffman_array() {
         let bytes = [1, 2, 1, 1, 1, 1, 1, 1, 1, 3, 1];
         let freq_buff = tally_frequency(&bytes);
-        let actual = build_huffman_array(freq_buff);
-        let expected = vec![1, 3, 2];
+        let actual = build_huffman_array(&freq_buff);
+        let expected = vec![1, 2, 3];
         assert_eq!(actual, expected)
     }
 
+    #[test]
+    fn frequency_buffer_ascii_histogram() {
+        let fb = tally_frequency(b"AAAAAAAB\n\n");
+        assert_eq!(
+            fb.to_ascii_histogram(7),
+            "'\\n' (0x0a) [██░░░░░] 2 (20.0%)\n\
+             'A' (0x41) [███████] 7 (70.0%)\n\
+             'B' (0x42) [█░░░░░░] 1 (10.0%)\n"
+        );
+        assert_eq!(FrequencyBuffer([0; 256]).to_ascii_histogram(10), "");
+    }
+
+    #[test]
+    fn frequency_buffer_from_normalized_histogram() {
+        let mut hist = [0.0; 256];
+        hist[b'a' as usize] = 0.5;
+        hist[b'b' as usize] = 0.25;
+        hist[b'c' as usize] = 0.25;
+        let fb = FrequencyBuffer::from_normalized_histogram(&hist).unwrap();
+        assert_eq!(fb[b'a'], 2 * fb[b'b']);
+        assert_eq!(fb[b'b'], fb[b'c']);
+        assert_eq!(fb.non_zero_count(), 3);
+        assert_eq!(build_huffman_array(&fb), b"abc");
+
+        hist[b'c' as usize] = 0.3;
+        assert!(matches!(
+            FrequencyBuffer::from_normalized_histogram(&hist),
+            Err(HuffErr::InvalidHistogram)
+        ));
+        hist[b'd' as usize] = -0.05;
+        assert!(FrequencyBuffer::from_normalized_histogram(&hist).is_err());
+        hist[b'd' as usize] = f64::NAN;
+        assert!(FrequencyBuffer::from_normalized_histogram(&hist).is_err());
+    }
+
+    #[test]
+    fn frequency_window_ages_out_old_chunks() {
+        let mut window = FrequencyWindow::new();
+        assert_eq!(window.update(b"aaaa", 2)[b'a'], 4);
+
+        let fb = window.update(b"bb", 2);
+        assert_eq!(fb[b'a'], 4);
+        assert_eq!(fb[b'b'], 2);
+
+        // The chunk of a's leaves the window
+        let fb = window.update(b"bc", 2);
+        assert_eq!(fb[b'a'], 0);
+        assert_eq!(fb[b'b'], 3);
+        assert_eq!(fb[b'c'], 1);
+
+        // Shrinking the window drops every chunk but the newest
+        let fb = window.update(b"d", 1);
+        assert_eq!(fb.0, tally_frequency(b"d").0);
+        assert_eq!(window.frequencies().0, tally_frequency(b"d").0);
+    }
+
+    #[test]
+    fn frequency_buffer_merge_and_sub_saturate() {
+        let mut fb = tally_frequency(b"aab");
+        fb.merge(&tally_frequency(b"abc"));
+        assert_eq!(fb[b'a'], 3);
+        assert_eq!(fb[b'c'], 1);
+
+        fb.saturating_sub_assign(&tally_frequency(b"bbbbc"));
+        assert_eq!(fb[b'b'], 0);
+        assert_eq!(fb[b'c'], 0);
+
+        let mut full = FrequencyBuffer([u64::MAX; 256]);
+        full.merge(&tally_frequency(b"a"));
+        assert_eq!(full[b'a'], u64::MAX);
+    }
+
+    #[test]
+    fn frequency_buffer_difference() {
+        let diff = tally_frequency(b"aaab") - tally_frequency(b"abbbc");
+        assert_eq!(diff.0[b'a' as usize], 2);
+        assert_eq!(diff.0[b'b' as usize], -2);
+        assert_eq!(diff.0[b'c' as usize], -1);
+        assert_eq!(
+            diff,
+            tally_frequency(b"aaab").difference(tally_frequency(b"abbbc"))
+        );
+
+        assert_eq!(diff.positive_entries().0, tally_frequency(b"aa").0);
+        assert_eq!(diff.negative_entries().0, tally_frequency(b"bbc").0);
+        assert_eq!(diff.magnitude().0, tally_frequency(b"aabbc").0);
+
+        let extreme = FrequencyBuffer([u64::MAX; 256]) - FrequencyBuffer([0; 256]);
+        assert_eq!(extreme.0[0], i64::MAX);
+        assert_eq!(extreme.magnitude()[0], i64::MAX as u64);
+
+        let sum = tally_frequency(b"aaab") + tally_frequency(b"abbbc");
+        assert_eq!(sum.0, tally_frequency(b"aaaabbbbc").0);
+    }
+
+    #[test]
+    fn entropy_and_expected_bits_per_symbol() {
+        let uniform = tally_frequency(b"abcd");
+        assert_eq!(uniform.entropy(), 2.0);
+        // Four 2 bit codes, the unary codes would average 2.25 bits
+        assert_eq!(uniform.expected_bits_per_symbol(), 2.0);
+
+        let skewed = tally_frequency(b"aaaabbc");
+        let (pa, pb, pc) = (4.0 / 7.0, 2.0 / 7.0, 1.0 / 7.0);
+        let expected = -(pa * f64::log2(pa) + pb * f64::log2(pb) + pc * f64::log2(pc));
+        assert!((skewed.entropy() - expected).abs() < 1e-12);
+        assert_eq!(skewed.expected_bits_per_symbol(), 10.0 / 7.0);
+        assert!(skewed.entropy() <= skewed.expected_bits_per_symbol());
+
+        assert_eq!(tally_frequency(b"zzzz").entropy(), 0.0);
+        assert_eq!(FrequencyBuffer::default().entropy(), 0.0);
+        assert_eq!(FrequencyBuffer::default().expected_bits_per_symbol(), 0.0);
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn tallies_of_two_halves_add_up_to_the_whole(
+            bytes in proptest::collection::vec(proptest::num::u8::ANY, 0..2048),
+            split in proptest::arbitrary::any::<proptest::sample::Index>(),
+        ) {
+            let (left, right) = bytes.split_at(split.index(bytes.len() + 1));
+            let whole = tally_frequency(&bytes);
+            proptest::prop_assert_eq!(tally_frequency(left) + tally_frequency(right), whole.clone());
+
+            let mut sum = FrequencyBuffer::default();
+            sum += tally_frequency(left);
+            sum += tally_frequency(right);
+            proptest::prop_assert_eq!(sum, whole);
+        }
+    }
+
+    #[test]
+    fn frequency_buffer_csv_round_trip() {
+        let fb = tally_frequency(b"hello, csv");
+        for (table, delimiter) in [(fb.to_csv(), ','), (fb.to_tsv(), '\t')] {
+            let mut lines = table.lines();
+            assert_eq!(
+                lines.next(),
+                Some(format!("symbol_dec{}count", delimiter).as_str())
+            );
+
+            let mut parsed = FrequencyBuffer([0; 256]);
+            let mut rows = 0;
+            for line in lines {
+                let (symbol, count) = line.split_once(delimiter).unwrap();
+                parsed[symbol.parse::<u8>().unwrap()] = count.parse().unwrap();
+                rows += 1;
+            }
+            assert_eq!(rows, 256);
+            assert_eq!(parsed.0, fb.0);
+        }
+    }
+
+    #[test]
+    fn filter_frequency_buffer_by_min_count() {
+        let fb = tally_frequency(b"aaaabbbccd");
+        let filtered = fb.filter_by_min_count(3);
+
+        assert_eq!(filtered.count_of(b'a'), 4);
+        assert_eq!(filtered.count_of(b'b'), 3);
+        assert_eq!(filtered.count_of(b'c'), 0);
+        assert_eq!(filtered.count_of(b'd'), 0);
+        assert_eq!(build_huffman_array(&filtered), b"ab");
+
+        assert_eq!(fb.filter_by_min_count(0).0, fb.0);
+        assert!(fb.filter_by_min_count(5).0.iter().all(|count| *count == 0));
+    }
+
+    #[test]
+    fn encoded_from_canonical() {
+        let encoded = Encoded::from_canonical(b'a', 3, 0b101);
+        assert_eq!(encoded.bits, bitvec![u8, Msb0; 1, 0, 1]);
+        assert_eq!(encoded.num_bits_sequence, 3);
+        assert_eq!(encoded.value, b'a');
+
+        // Bits above the length are ignored
+        let encoded = Encoded::from_canonical(b'b', 2, 0b1110);
+        assert_eq!(encoded.bits, bitvec![u8, Msb0; 1, 0]);
+
+        let encoded = Encoded::from_canonical(b'c', 34, 1);
+        assert_eq!(encoded.bits.len(), 34);
+        assert_eq!(encoded.bits.count_ones(), 1);
+        assert!(encoded.bits[33]);
+
+        // Matches the codes of encode_huffman_array
+        let encoded_map = encode_huffman_array(b"xyz");
+        assert_eq!(Encoded::from_canonical(b'y', 2, 0b01), encoded_map[&b'y']);
+        assert_eq!(Encoded::from_canonical(b'z', 2, 0b00), encoded_map[&b'z']);
+    }
+
+    #[test]
+    fn non_zero_count_and_total_symbols() {
+        let fb = tally_frequency(b"abracadabra");
+        assert_eq!(fb.non_zero_count(), 5);
+        assert_eq!(fb.total_symbols(), 11);
+
+        assert_eq!(FrequencyBuffer::default().non_zero_count(), 0);
+        assert_eq!(FrequencyBuffer::default().total_symbols(), 0);
+
+        let full = FrequencyBuffer([u64::MAX; 256]);
+        assert_eq!(full.non_zero_count(), 256);
+        assert_eq!(full.total_symbols(), u64::MAX);
+    }
+
+    #[test]
+    fn frequency_buffer_count_accessors() {
+        let mut fb = tally_frequency(b"abracadabra");
+        assert_eq!(fb.count_of(b'a'), 5);
+        assert_eq!(fb.count_of(b'z'), 0);
+
+        fb.set_count(b'z', 7).unwrap();
+        assert_eq!(fb.count_of(b'z'), 7);
+        assert!(matches!(
+            fb.set_count(b'a', u64::MAX),
+            Err(HuffErr::InvalidFrequencyTable(_))
+        ));
+        assert_eq!(fb.count_of(b'a'), 5);
+    }
+
+    #[test]
+    fn index_mut_updates_the_counts_build_huffman_array_sees() {
+        let mut fb = tally_frequency(b"abracadabra");
+        assert_eq!(fb[b'a'], 5);
+        assert_eq!(build_huffman_array(&fb)[0], b'a');
+
+        fb[b'r'] += 10;
+        fb[b'z'] = 1;
+        assert_eq!(fb.count_of(b'r'), 12);
+        assert_eq!(build_huffman_array(&fb), b"rabcdz");
+    }
+
+    #[test]
+    fn build_huffman_array_from_unsorted_counts() {
+        let counts = [(b'c', 5), (b'a', 20), (b'z', 0), (b'b', 5), (b'd', 1)];
+        let actual = build_huffman_array_from_counts(&counts).unwrap();
+        assert_eq!(actual, b"abcd");
+
+        let bytes = b"mississippi river";
+        let mut counts: Vec<(u8, u64)> = tally_frequency(bytes).into_iter().collect();
+        counts.reverse();
+        assert_eq!(
+            build_huffman_array_from_counts(&counts).unwrap(),
+            build_huffman_array(&tally_frequency(bytes))
+        );
+    }
+
+    #[test]
+    fn build_huffman_array_two_queue_matches_heap_order() {
+        // Distinct counts, so the order is fully determined by frequency
+        let bytes: Vec<u8> = (0..20u8)
+            .flat_map(|symbol| std::iter::repeat_n(symbol, 1 << (symbol % 12) | symbol as usize))
+            .collect();
+        assert_eq!(
+            build_huffman_array_two_queue(tally_frequency(&bytes)),
+            build_huffman_array(&tally_frequency(&bytes))
+        );
+
+        let mut with_ties = build_huffman_array_two_queue(tally_frequency(b"abracadabra"));
+        assert_eq!(with_ties[0], b'a');
+        with_ties.sort_unstable();
+        assert_eq!(with_ties, b"abcdr");
+
+        assert!(build_huffman_array_two_queue(FrequencyBuffer([0; 256])).is_empty());
+    }
+
+    #[test]
+    fn build_huffman_array_from_counts_rejects_duplicates() {
+        let result = build_huffman_array_from_counts(&[(b'a', 1), (b'b', 2), (b'a', 3)]);
+        assert!(matches!(result, Err(HuffErr::InvalidFrequencyTable(_))));
+    }
+
     #[test]
     fn build_encoded_map_from_huffman_array() {
         let huff_arr = vec![1, 3, 2];
@@ -326,42 +2088,694 @@ mod tests {
     fn build_huffman_tree_test_simple() {
         let bytes = [1, 2, 1, 1, 1, 1, 1, 1, 1, 3, 1];
         let freq_buff = tally_frequency(&bytes);
-        let huffnode = build_huffman_array(freq_buff);
+        let huffnode = build_huffman_array(&freq_buff);
         let encode_map = encode_huffman_array(&huffnode);
-        let (encoded_buffer, total_bits) = huff_encode_bitvec(&bytes, &encode_map);
-        let expected_buffer = "1001111111011000";
+        let (encoded_buffer, total_bits) = huff_encode_bitvec(&bytes, &encode_map).unwrap();
+        let expected_buffer = "1011111111001000";
         assert_eq!(encoded_buffer_to_string(&encoded_buffer), expected_buffer);
         let expected_total_bits = 13;
         assert_eq!(total_bits, expected_total_bits);
     }
 
+    /// Prepends the magic bytes, the current version and the checksum to a Huffman or stored
+    /// frame.
+    fn with_magic(frame: &[u8]) -> Vec<u8> {
+        with_version(HUFF_FORMAT_VERSION, frame)
+    }
+
+    /// Prepends the magic bytes and `version`, and for the current version the checksum, to a
+    /// Huffman or stored frame.
+    fn with_version(version: u8, frame: &[u8]) -> Vec<u8> {
+        let mut buffer = HUFF_MAGIC.to_vec();
+        buffer.push(version);
+        if version == HUFF_FORMAT_VERSION {
+            buffer.extend_from_slice(&crc32fast::hash(frame).to_be_bytes());
+        }
+        buffer.extend_from_slice(frame);
+        buffer
+    }
+
+    /// The frame of `[1, 3, 1, 2]` with the codes 1: `1`, 2: `01` and 3: `00`.
+    const FRAME_1312: [u8; 30] = [
+        1, 0, 0, 0, 0, 0, 0, 0, 6, 0, 0, 0, 0, 0, 0, 0, 12, 1, 0, 1, 128, 2, 0, 2, 64, 3, 0, 2, 0,
+        148,
+    ];
+
+    #[test]
+    fn compress_input_with_fewer_than_two_distinct_bytes() {
+        let empty = compress(b"").unwrap();
+        assert!(is_stored_passthrough(&empty));
+        assert!(deserialize_huffman(&empty).unwrap().is_empty());
+
+        let single_symbol = [b'z'; 40];
+        let compressed = compress(&single_symbol).unwrap();
+        assert!(!is_stored_passthrough(&compressed));
+        assert_eq!(deserialize_huffman(&compressed).unwrap(), single_symbol);
+
+        let mut freq_buffer = FrequencyBuffer([0; 256]);
+        freq_buffer[b'a'] = 1;
+        assert!(matches!(
+            compress_with_frequencies(b"ab", freq_buffer),
+            Err(HuffErr::SymbolNotInCodebook(b'b'))
+        ));
+    }
+
+    #[test]
+    fn single_symbol_round_trips_with_one_bit_codes() {
+        let bytes = [42u8; 500];
+        let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(&bytes)));
+        assert_eq!(encoded_map[&42].bits, bits![u8, Msb0; 0]);
+
+        let (encoded_buffer, total_bits) = huff_encode_bitvec(&bytes, &encoded_map).unwrap();
+        assert_eq!(total_bits, 500);
+        let serialized_buffer = serialize_huffman(&encoded_map, encoded_buffer, total_bits);
+        assert_eq!(deserialize_huffman(&serialized_buffer).unwrap(), bytes);
+        assert_eq!(decompress(&compress(&bytes).unwrap()).unwrap(), bytes);
+    }
+
     #[test]
     fn serialize_huffman_test() {
         let bytes = [1, 3, 1, 2];
         let freq_buff = tally_frequency(&bytes);
-        let huffnode = build_huffman_array(freq_buff);
+        let huffnode = build_huffman_array(&freq_buff);
         let encode_map = encode_huffman_array(&huffnode);
-        let (encoded_buffer, total_bits) = huff_encode_bitvec(&bytes, &encode_map);
-        let mut serialized_buffer = serialize_huffman(&encode_map, encoded_buffer, total_bits);
-        serialized_buffer.sort();
-        let mut expected = [
-            0, 0, 0, 0, 0, 0, 0, 6, 0, 0, 0, 0, 0, 0, 0, 9, 1, 1, 1, 3, 2, 1, 2, 2, 0, 176,
-        ];
-        expected.sort();
+        let (encoded_buffer, total_bits) = huff_encode_bitvec(&bytes, &encode_map).unwrap();
+        let serialized_buffer = serialize_huffman(&encode_map, encoded_buffer, total_bits);
+
+        assert_eq!(serialized_buffer, with_magic(&FRAME_1312));
+    }
+
+    #[test]
+    fn huff_encode_bitvec_rejects_bytes_without_a_code() {
+        // Short codes take the packed path, long codes the bit by bit one
+        let short_codes = encode_huffman_array(&build_huffman_array(&tally_frequency(b"aab")));
+        let long_codes = encode_huffman_array(b"abcdefghij");
+        for encoded_map in [short_codes, long_codes] {
+            assert!(matches!(
+                huff_encode_bitvec(b"abz", &encoded_map),
+                Err(HuffErr::SymbolNotInCodebook(b'z'))
+            ));
+        }
+    }
+
+    #[test]
+    fn huff_encode_bitvec_rejects_empty_input() {
+        let encoded_map = canonical_huffman_codes(&tally_frequency(b"")).unwrap();
+        assert!(matches!(
+            huff_encode_bitvec(b"", &encoded_map),
+            Err(HuffErr::NullInput("huff_encode_bitvec"))
+        ));
+    }
+
+    fn code_string(encoded: &Encoded) -> String {
+        encoded
+            .bits
+            .iter()
+            .by_vals()
+            .map(|bit| if bit { '1' } else { '0' })
+            .collect()
+    }
+
+    #[test]
+    fn canonical_codes() {
+        let mut freq_buffer = FrequencyBuffer([0; 256]);
+        for (symbol, freq) in [(b'a', 5), (b'b', 2), (b'c', 1), (b'd', 1)] {
+            freq_buffer[symbol] = freq;
+        }
+        let mut encoded_map = encode_huffman_tree(&build_huffman_tree(&freq_buffer).unwrap());
+        canonicalize_encoding(&mut encoded_map).unwrap();
+        let codes: Vec<String> = [b'a', b'b', b'c', b'd']
+            .iter()
+            .map(|symbol| code_string(&encoded_map[symbol]))
+            .collect();
+        assert_eq!(codes, ["0", "10", "110", "111"]);
+
+        // Equal lengths are ordered by symbol, whatever the original codes were
+        let mut unary = encode_huffman_array(b"xyz");
+        canonicalize_encoding(&mut unary).unwrap();
+        assert_eq!(code_string(&unary[&b'x']), "0");
+        assert_eq!(code_string(&unary[&b'y']), "10");
+        assert_eq!(code_string(&unary[&b'z']), "11");
+        assert!(decoder::HuffmanDecoder::new(&unary).is_ok());
+
+        // Three one bit codes cannot form a prefix code
+        let mut too_short = encode_huffman_array(b"xyz");
+        too_short
+            .values_mut()
+            .for_each(|encoded| encoded.bits.truncate(1));
+        assert!(matches!(
+            canonicalize_encoding(&mut too_short),
+            Err(HuffErr::MalformedData(_))
+        ));
+    }
+
+    #[test]
+    fn compress_ratio_and_space_saved() {
+        assert_eq!(compress_ratio(200, 50), 0.25);
+        assert_eq!(space_saved_percent(200, 50), 75.0);
+        assert_eq!(compress_ratio(100, 150), 1.5);
+        assert_eq!(space_saved_percent(100, 150), -50.0);
 
-        assert_eq!(serialized_buffer, expected);
+        // Nothing to save on empty input
+        assert_eq!(compress_ratio(0, 10), 1.0);
+        assert_eq!(compress_ratio(0, 0), 1.0);
+        assert_eq!(space_saved_percent(0, 10), 0.0);
+    }
+
+    #[test]
+    fn encoding_table_display() {
+        let encoded = Encoded::from_canonical(b'A', 3, 0b101);
+        assert_eq!(encoded.to_string(), "'A' (0x41) -> 101 (3 bits)");
+        let encoded = Encoded::from_canonical(b'\n', 1, 0);
+        assert_eq!(encoded.to_string(), "'\\n' (0x0a) -> 0 (1 bit)");
+
+        let table = EncodingTable(encode_huffman_array(b"zyx"));
+        assert_eq!(
+            table.to_string(),
+            "'z' (0x7a) -> 1 (1 bit)\n\
+             'x' (0x78) -> 00 (2 bits)\n\
+             'y' (0x79) -> 01 (2 bits)\n"
+        );
+
+        // A single symbol gets the one bit code 0
+        let table = EncodingTable(encode_huffman_array(b"\xff"));
+        assert_eq!(table.to_string(), "'\\xff' (0xff) -> 0 (1 bit)\n");
+    }
+
+    #[test]
+    fn serialized_output_is_reproducible() {
+        let original = std::fs::read("./tests/resources/dna_seq_test.txt").unwrap();
+        let compress = || {
+            // Every map gets its own hasher state, so its iteration order differs between runs
+            let encoded_map =
+                encode_huffman_array(&build_huffman_array(&tally_frequency(&original)));
+            let (bit_buffer, total_bits) = huff_encode_bitvec(&original, &encoded_map).unwrap();
+            serialize_huffman(&encoded_map, bit_buffer, total_bits)
+        };
+        let first = compress();
+        for _ in 0..8 {
+            assert_eq!(compress(), first);
+        }
     }
 
     #[test]
     fn test_deserialize_huffman() {
         let target = [1, 3, 1, 2];
 
-        let serialized_bytes = [
+        let actual = deserialize_huffman(&with_magic(&FRAME_1312)).unwrap();
+        assert_eq!(actual, target);
+
+        // Version 1 stores the last bit of each code, here 1: `1`, 2: `00` and 3: `01`
+        let last_bit_bytes = with_version(
+            1,
+            &[
+                1, 0, 0, 0, 0, 0, 0, 0, 6, 0, 0, 0, 0, 0, 0, 0, 9, 1, 1, 1, 2, 2, 0, 3, 2, 1, 176,
+            ],
+        );
+        assert_eq!(deserialize_huffman(&last_bit_bytes).unwrap(), target);
+    }
+
+    #[test]
+    fn serialize_huffman_stores_codes_longer_than_a_byte() {
+        // The unary code of the rarest of 11 symbols is 10 zero bits
+        let bytes = b"aaaaaaaaaaabcdefghijk";
+        let encoded_map = encode_huffman_array(b"abcdefghijk");
+        assert_eq!(encoded_map[&b'k'].num_bits_sequence, 10);
+
+        let (bit_buffer, total_bits) = huff_encode_bitvec(bytes, &encoded_map).unwrap();
+        let serialized = serialize_huffman(&encoded_map, bit_buffer, total_bits);
+        // The entry of `k` is the last of the header: symbol, length and 2 bytes of code
+        let sizes = &serialized[HUFF_HEADER_LEN + 1..HUFF_HEADER_LEN + 17];
+        let header_end = HUFF_HEADER_LEN + 17 + u8_to_u64(&sizes[8..]) as usize;
+        assert_eq!(serialized[header_end - 5..header_end], [b'k', 0, 10, 0, 0]);
+        assert_eq!(deserialize_huffman(&serialized).unwrap(), bytes);
+    }
+
+    #[test]
+    fn serialize_huffman_stores_codes_of_equal_length() {
+        // Equal frequencies give every symbol a code of 2 bits, which differ in more than the
+        // last bit
+        let bytes = b"abcdabcdabcdabcd";
+        let encoded_map =
+            encode_huffman_tree(&build_huffman_tree(&tally_frequency(bytes)).unwrap());
+        assert!(encoded_map.values().all(|encoded| encoded.bits.len() == 2));
+
+        let (bit_buffer, total_bits) = huff_encode_bitvec(bytes, &encoded_map).unwrap();
+        let serialized = serialize_huffman(&encoded_map, bit_buffer, total_bits);
+        assert_eq!(deserialize_huffman(&serialized).unwrap(), bytes);
+    }
+
+    #[test]
+    fn deserialize_huffman_rejects_empty_and_unknown_buffers() {
+        assert!(matches!(
+            deserialize_huffman(&[]),
+            Err(HuffErr::NullInput("deserialize_huffman"))
+        ));
+        assert!(matches!(
+            deserialize_huffman(&with_magic(&[0x7f, 1, 2, 3])),
+            Err(HuffErr::MalformedData(_))
+        ));
+    }
+
+    #[test]
+    fn validate_huff_bytes_summarizes_the_header() {
+        assert_eq!(
+            validate_huff_bytes(&with_magic(&FRAME_1312)).unwrap(),
+            HuffHeader {
+                format_version: HUFF_FORMAT_VERSION,
+                format: FORMAT_HUFFMAN,
+                total_bits: 6,
+                symbol_count: 3,
+                compressed_payload_bytes: 1,
+            }
+        );
+        assert_eq!(
+            validate_huff_bytes(&compress(b"").unwrap()).unwrap(),
+            HuffHeader {
+                format_version: HUFF_FORMAT_VERSION,
+                format: FORMAT_STORED,
+                total_bits: 0,
+                symbol_count: 0,
+                compressed_payload_bytes: 0,
+            }
+        );
+
+        let version_1 = [
+            1, 0, 0, 0, 0, 0, 0, 0, 6, 0, 0, 0, 0, 0, 0, 0, 9, 1, 1, 1, 2, 2, 0, 3, 2, 1, 176,
+        ];
+        let header = validate_huff_bytes(&with_version(1, &version_1)).unwrap();
+        assert_eq!((header.format_version, header.symbol_count), (1, 3));
+    }
+
+    #[test]
+    fn deserialize_huffman_detects_a_flipped_bit() {
+        let original = b"abracadabra".repeat(10);
+        let compressed = compress(&original).unwrap();
+        assert!(!is_stored_passthrough(&compressed));
+
+        // A bit of the bit buffer, which still decodes with the unary codes
+        let mut flipped = compressed.clone();
+        let last = flipped.len() - 1;
+        flipped[last] ^= 0x80;
+        let actual = crc32fast::hash(&flipped[HUFF_HEADER_LEN..]);
+        match deserialize_huffman(&flipped) {
+            Err(HuffErr::ChecksumMismatch {
+                expected,
+                actual: found,
+            }) => {
+                assert_eq!(expected.to_be_bytes(), compressed[5..9]);
+                assert_eq!(found, actual);
+            }
+            other => panic!("expected ChecksumMismatch, got {:?}", other),
+        }
+
+        let mut stored = compress(b"xyz").unwrap();
+        stored[HUFF_HEADER_LEN + 1] ^= 1;
+        assert!(matches!(
+            deserialize_huffman(&stored),
+            Err(HuffErr::ChecksumMismatch { .. })
+        ));
+
+        // The CRC-32/ISO-HDLC check value, version 2 files have always used this CRC
+        assert_eq!(crc32fast::hash(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn validate_huff_bytes_rejects_inconsistent_buffers() {
+        let zero_bits = serialize_huffman(&HashMap::new(), Vec::new(), 0);
+        let truncated_bits = with_magic(&FRAME_1312[..FRAME_1312.len() - 1]);
+        let truncated_header = with_magic(&FRAME_1312[..20]);
+        for bytes in [zero_bits, truncated_bits, truncated_header] {
+            assert!(matches!(
+                validate_huff_bytes(&bytes),
+                Err(HuffErr::MalformedData(_))
+            ));
+            assert!(matches!(
+                deserialize_huffman(&bytes),
+                Err(HuffErr::MalformedData(_))
+            ));
+        }
+        assert!(matches!(
+            validate_huff_bytes(&with_version(9, &FRAME_1312)),
+            Err(HuffErr::UnsupportedVersion(9))
+        ));
+    }
+
+    #[test]
+    fn deserialize_huffman_checks_magic_and_version() {
+        // A version 0 buffer, written by huffc 0.1.1
+        let version_0 = [
+            0, 0, 0, 0, 0, 0, 0, 6, 0, 0, 0, 0, 0, 0, 0, 9, 1, 1, 1, 2, 2, 0, 3, 2, 1, 176,
+        ];
+        assert!(matches!(
+            deserialize_huffman(&version_0),
+            Err(HuffErr::NotAHuffFile)
+        ));
+        assert!(matches!(
+            deserialize_huffman(b"HUF"),
+            Err(HuffErr::NotAHuffFile)
+        ));
+
+        // Migrating it is prepending the magic bytes, version 1 and the Huffman format byte
+        assert_eq!(
+            deserialize_huffman(&with_version(
+                1,
+                &[&[FORMAT_HUFFMAN][..], &version_0].concat()
+            ))
+            .unwrap(),
+            [1, 3, 1, 2]
+        );
+
+        let mut future = with_magic(&FRAME_1312);
+        future[4] = 7;
+        assert!(matches!(
+            deserialize_huffman(&future),
+            Err(HuffErr::UnsupportedVersion(7))
+        ));
+        assert!(matches!(
+            deserialize_huffman(b"HUFF"),
+            Err(HuffErr::MalformedData(_))
+        ));
+    }
+
+    #[test]
+    fn deserialize_huffman_rejects_truncated_header() {
+        let valid = FRAME_1312;
+        // Shorter than the fixed header
+        assert!(matches!(
+            deserialize_huffman(&with_magic(&valid[..12])),
+            Err(HuffErr::MalformedData(_))
+        ));
+        // Header size pointing past the end of the buffer
+        let mut oversized_header = valid;
+        oversized_header[16] = 200;
+        assert!(matches!(
+            deserialize_huffman(&with_magic(&oversized_header)),
+            Err(HuffErr::MalformedData(_))
+        ));
+    }
+
+    #[test]
+    fn deserialize_huffman_rejects_truncated_bits() {
+        let mut too_many_bits = FRAME_1312;
+        too_many_bits[8] = 60;
+        assert!(matches!(
+            deserialize_huffman(&with_magic(&too_many_bits)),
+            Err(HuffErr::MalformedData(_))
+        ));
+    }
+
+    #[test]
+    fn deserialize_huffman_rejects_legacy_layout() {
+        // The test vector of test_deserialize_huffman without the format byte
+        let legacy_bytes = [
             0, 0, 0, 0, 0, 0, 0, 6, 0, 0, 0, 0, 0, 0, 0, 9, 1, 1, 1, 2, 2, 0, 3, 2, 1, 176,
         ];
+        assert!(matches!(
+            deserialize_huffman(&legacy_bytes),
+            Err(HuffErr::NotAHuffFile)
+        ));
+    }
+
+    #[test]
+    fn decode_huffman_frame_rejects_malformed_buffers() {
+        let valid = FRAME_1312;
+        assert_eq!(decode_huffman_frame(&valid).unwrap(), [1, 3, 1, 2]);
 
-        let actual = deserialize_huffman(&serialized_bytes);
+        // Header larger than the buffer
+        let mut oversized_header = valid;
+        oversized_header[9..17].copy_from_slice(&u64_to_u8(u64::MAX));
+        assert!(decode_huffman_frame(&oversized_header).is_err());
 
-        assert_eq!(actual, target);
+        // More bits than the bit buffer holds
+        let mut too_many_bits = valid;
+        too_many_bits[1..9].copy_from_slice(&u64_to_u8(u64::MAX));
+        assert!(matches!(
+            decode_huffman_frame(&too_many_bits),
+            Err(HuffErr::MalformedData(_))
+        ));
+
+        // Code of 2 (`01` made `10`) starting with the code of 1
+        let mut not_prefix_free = valid;
+        not_prefix_free[24] = 128;
+        assert!(matches!(
+            decode_huffman_frame(&not_prefix_free),
+            Err(HuffErr::MalformedData(_))
+        ));
+
+        // Code of 3 (`00` made `000`), the bits `00` match no code
+        let mut unknown_code = valid;
+        unknown_code[27] = 3;
+        assert!(matches!(
+            decode_huffman_frame(&unknown_code),
+            Err(HuffErr::CorruptedBitstream { bit_offset: 1 })
+        ));
+
+        // Code length running past the header
+        let mut long_code = valid;
+        long_code[27] = 9;
+        assert!(matches!(
+            decode_huffman_frame(&long_code),
+            Err(HuffErr::MalformedData(_))
+        ));
+
+        assert!(decode_huffman_frame(&valid[..10]).is_err());
+    }
+
+    #[test]
+    fn laplace_smoothing_gives_every_symbol_a_count() {
+        let mut fb = tally_frequency(&[b'a'; 2560]);
+        fb.apply_laplace_smoothing(1.0);
+
+        assert_eq!(fb[b'a'], 2570);
+        assert!(fb
+            .0
+            .iter()
+            .enumerate()
+            .all(|(s, c)| s == b'a' as usize || *c == 10));
+
+        let mut small = tally_frequency(b"ab");
+        small.apply_laplace_smoothing(1.0);
+        assert!(small.0.iter().all(|count| *count >= 1));
+        assert_eq!(build_huffman_array(&small).len(), 256);
+    }
+
+    #[test]
+    fn value_bit_map_accessors() {
+        let value_bit_map = ValueBitMap::new(b'x', true);
+
+        assert_eq!(value_bit_map.value(), b'x');
+        assert!(value_bit_map.ends_in_1());
+        assert_ne!(value_bit_map, ValueBitMap::new(b'x', false));
+    }
+
+    #[test]
+    fn serialize_huffman_passthrough_when_expanding() {
+        let bytes = [1, 3, 1, 2];
+        let freq_buff = tally_frequency(&bytes);
+        let huffnode = build_huffman_array(&freq_buff);
+        let encode_map = encode_huffman_array(&huffnode);
+        let (encoded_buffer, total_bits) = huff_encode_bitvec(&bytes, &encode_map).unwrap();
+        let serialized_buffer =
+            serialize_huffman_or_passthrough(&encode_map, encoded_buffer, total_bits, &bytes);
+
+        assert!(is_stored_passthrough(&serialized_buffer));
+        assert_eq!(serialized_buffer, with_magic(&[0, 1, 3, 1, 2]));
+        assert_eq!(deserialize_huffman(&serialized_buffer).unwrap(), bytes);
+    }
+
+    #[test]
+    fn serialize_huffman_no_passthrough_when_compressing() {
+        let mut bytes = vec![b'a'; 1000];
+        bytes.extend_from_slice(b"bc");
+        let freq_buff = tally_frequency(&bytes);
+        let huffnode = build_huffman_array(&freq_buff);
+        let encode_map = encode_huffman_array(&huffnode);
+        let (encoded_buffer, total_bits) = huff_encode_bitvec(&bytes, &encode_map).unwrap();
+        let serialized_buffer =
+            serialize_huffman_or_passthrough(&encode_map, encoded_buffer, total_bits, &bytes);
+
+        assert!(!is_stored_passthrough(&serialized_buffer));
+        assert!(serialized_buffer.len() < bytes.len());
+    }
+
+    #[test]
+    fn frequency_buffer_iterates_by_descending_frequency() {
+        let fb = tally_frequency(&[1, 2, 1, 1, 4, 1, 3, 4, 3, 1, 4]);
+
+        let borrowed: Vec<(u8, u64)> = (&fb).into_iter().collect();
+        assert_eq!(borrowed, vec![(1, 5), (4, 3), (3, 2), (2, 1)]);
+
+        let owned: Vec<(u8, u64)> = fb.into_iter().collect();
+        assert_eq!(owned, borrowed);
+    }
+
+    #[test]
+    fn frequency_buffer_iterator_breaks_ties_by_symbol() {
+        let fb = tally_frequency(&[9, 7, 8, 7, 9, 8]);
+        let symbols: Vec<u8> = fb.into_iter().map(|(symbol, _)| symbol).collect();
+
+        assert_eq!(symbols, vec![7, 8, 9]);
+    }
+
+    #[test]
+    fn frequency_buffer_from_uniform_distribution() {
+        let probs = [(b'a', 0.25), (b'b', 0.25), (b'c', 0.25), (b'd', 0.25)];
+        let fb = FrequencyBuffer::from_probability_distribution(&probs).unwrap();
+
+        let counts: Vec<u64> = fb.0.iter().copied().filter(|c| *c != 0).collect();
+        assert_eq!(counts.len(), 4);
+        assert!(counts.iter().all(|c| *c == counts[0]));
+
+        // A full binary tree of height 2 with 4 leaves, so every code is 2 bits long
+        let tree = build_huffman_tree(&fb).unwrap();
+        assert_eq!(tree.symbol_count(), 4);
+        assert_eq!(tree.height(), 2);
+    }
+
+    #[test]
+    fn frequency_buffer_from_invalid_distribution() {
+        let not_normalised = [(b'a', 0.5), (b'b', 0.25)];
+        assert!(matches!(
+            FrequencyBuffer::from_probability_distribution(&not_normalised),
+            Err(HuffErr::InvalidProbabilities)
+        ));
+
+        let negative = [(b'a', 1.5), (b'b', -0.5)];
+        assert!(matches!(
+            FrequencyBuffer::from_probability_distribution(&negative),
+            Err(HuffErr::InvalidProbabilities)
+        ));
+
+        let duplicate = [(b'a', 0.5), (b'a', 0.5)];
+        assert!(matches!(
+            FrequencyBuffer::from_probability_distribution(&duplicate),
+            Err(HuffErr::InvalidProbabilities)
+        ));
+
+        assert!(matches!(
+            FrequencyBuffer::from_probability_distribution(&[]),
+            Err(HuffErr::NullInput(_))
+        ));
+    }
+
+    #[test]
+    fn bounded_encoding_matches_unbounded_when_loose() {
+        for huff_arr in [vec![1, 3, 2], (0..10).collect(), (0..=255).collect()] {
+            let bounded = encode_huffman_array_bounded(&huff_arr, 255).unwrap();
+            assert_eq!(bounded, encode_huffman_array(&huff_arr));
+        }
+    }
+
+    #[test]
+    fn bounded_encoding_limits_code_length() {
+        let huff_arr: Vec<u8> = (0..=255).collect();
+        let bounded = encode_huffman_array_bounded(&huff_arr, 8).unwrap();
+        assert!(bounded.values().all(|encoded| encoded.bits.len() == 8));
+
+        let huff_arr: Vec<u8> = (0..20).collect();
+        let bounded = encode_huffman_array_bounded(&huff_arr, 6).unwrap();
+        let kraft_sum: f64 = bounded
+            .values()
+            .map(|encoded| 0.5f64.powi(encoded.bits.len() as i32))
+            .sum();
+        assert_eq!(bounded.values().map(|e| e.bits.len()).max(), Some(6));
+        assert_eq!(kraft_sum, 1.0);
+        // More frequent symbols never get longer codes
+        assert!(huff_arr
+            .windows(2)
+            .all(|pair| bounded[&pair[0]].bits.len() <= bounded[&pair[1]].bits.len()));
+
+        let bytes: Vec<u8> = (0..20).cycle().take(200).collect();
+        let (bit_buffer, total_bits) = huff_encode_bitvec(&bytes, &bounded).unwrap();
+        let mut decoder = decoder::HuffmanDecoder::new(&bounded)
+            .unwrap()
+            .with_total_bits(total_bits);
+        decoder.feed(&bit_buffer);
+        let mut decoded = Vec::new();
+        while let Some(symbol) = decoder.try_decode_symbol().unwrap() {
+            decoded.push(symbol);
+        }
+        assert_eq!(decoded, bytes);
+    }
+
+    #[test]
+    fn bounded_encoding_impossible_depth() {
+        assert!(matches!(
+            encode_huffman_array_bounded(&[1, 2, 3, 4, 5], 2),
+            Err(HuffErr::DepthConstraintImpossible {
+                max_depth: 2,
+                num_symbols: 5
+            })
+        ));
+        assert!(encode_huffman_array_bounded(&[1, 2, 3, 4], 2).is_ok());
+        assert!(encode_huffman_array_bounded(&[7], 0).is_err());
+        assert!(matches!(
+            encode_huffman_array_bounded(&[], 8),
+            Err(HuffErr::NullInput("encode_huffman_array_bounded"))
+        ));
+        assert_eq!(
+            encode_huffman_array_bounded(&[7], 1).unwrap()[&7]
+                .bits
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn build_encoded_array_from_map() {
+        let encode_map = encode_huffman_array(&[1, 3, 2]);
+        let encoded_array = build_encoded_array(encode_map);
+
+        assert_eq!(encoded_array.iter().filter(|e| e.is_some()).count(), 3);
+        assert_eq!(encoded_array[3].as_ref().unwrap().num_bits_sequence, 2);
+        assert!(encoded_array[0].is_none());
+    }
+
+    #[test]
+    fn differential_frequency_buffer() {
+        let diff = DifferentialFrequencyBuffer::new(
+            tally_frequency(b"aaabbc"),
+            tally_frequency(b"aabbbbbd"),
+        );
+
+        assert_eq!(
+            diff.changed_symbols(),
+            vec![(b'b', 3), (b'a', -1), (b'c', -1), (b'd', 1)]
+        );
+
+        let mut fb = tally_frequency(b"ab");
+        diff.apply_to(&mut fb);
+        assert_eq!(fb[b'a'], 0);
+        assert_eq!(fb[b'b'], 4);
+        assert_eq!(fb[b'c'], 0);
+        assert_eq!(fb[b'd'], 1);
+    }
+
+    #[test]
+    fn frequency_buffer_fingerprint() {
+        let a = tally_frequency(&[1, 2, 1, 3]);
+        let b = tally_frequency(&[3, 1, 2, 1]);
+        let c = tally_frequency(&[1, 2, 2, 3]);
+
+        assert_eq!(a.fingerprint(), b.fingerprint());
+        assert_ne!(a.fingerprint(), c.fingerprint());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn frequency_buffer_json_round_trip() {
+        let fb = tally_frequency(&[1, 2, 1, 1, 1, 1, 1, 1, 1, 3, 1]);
+        let json = fb.to_json();
+        assert!(json.contains("\"1\":9"));
+        assert!(!json.contains("\"0\""));
+
+        let parsed = FrequencyBuffer::from_json(&json).unwrap();
+        assert_eq!(parsed.0, fb.0);
+
+        assert!(matches!(
+            FrequencyBuffer::from_json("{\"256\": 1}"),
+            Err(HuffErr::InvalidFrequencyTable(_))
+        ));
     }
 }
@@ -1,83 +1,282 @@
 use huffc::{
-    build_huffman_array,
-    cli::{validate_inputs, Args, Mode},
-    deserialize_huffman, encode_huffman_array,
+    cli::{compressed_file_path, decompressed_file_path, validate_inputs, Args, HuffInfo, Mode},
+    codec::{Codebook, HuffmanCodec},
+    compress_ratio, decompress,
     fs::{read_file, write_file},
-    huff_encode_bitvec, serialize_huffman, tally_frequency,
+    is_stored_passthrough,
+    level::{compress_level, CompressionLevel},
+    parallel::compress_split,
+    space_saved_percent, tally_frequency,
+    timing::StageTimer,
+    CompressOptions, FrequencyBuffer, HuffErr,
 };
 
-use std::{ffi::OsString, io::Read};
+use std::{
+    io::{Read, Write},
+    path::Path,
+    process::ExitCode,
+};
 
 use clap::Parser;
 
-fn main() {
+fn main() -> ExitCode {
     let args = Args::parse();
     let mode = match validate_inputs(&args) {
         Ok(mode) => mode,
         Err(e) => {
-            println!("{}", e);
-            return;
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
         }
     };
 
+    let mut timer = StageTimer::new();
     let buffer: &'static [u8] = match mode {
         Mode::Stdin => {
             let mut buffer = Vec::new();
             let mut stdin = std::io::stdin();
             if let Err(e) = stdin.read_to_end(&mut buffer) {
-                println!("Failed to read stdin: {}", e);
-                return;
+                eprintln!("Failed to read stdin: {}", e);
+                return ExitCode::FAILURE;
             }
             Box::leak(buffer.into_boxed_slice())
         }
         Mode::FileIO => {
             let path_buffer = args.input.as_ref().unwrap();
-            let mmap = read_file(path_buffer);
+            let mmap = match read_file(path_buffer) {
+                Ok(mmap) => mmap,
+                Err(e) => {
+                    eprintln!("Failed to read {}: {}", path_buffer.display(), e);
+                    return ExitCode::FAILURE;
+                }
+            };
             Box::leak(Box::new(mmap))
         }
     };
+    timer.lap("file read");
+
+    if args.info {
+        match HuffInfo::from_bytes(buffer) {
+            Ok(info) if args.json => println!("{}", info.to_json()),
+            Ok(info) => print!("{}", info),
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    if args.test {
+        let codebook = match Codebook::from_frequencies(&tally_frequency(buffer)) {
+            Ok(codebook) => codebook,
+            // Nothing to encode, empty input always round trips
+            Err(HuffErr::NullInput(_)) => {
+                println!("OK, 0 bytes");
+                return ExitCode::SUCCESS;
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        };
+        timer.lap("build codebook");
+        let result = HuffmanCodec::verify_round_trip(&codebook, buffer);
+        timer.lap("round trip");
+        if args.profile {
+            eprint!("{}", timer.report());
+        }
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+        println!("OK, {} bytes", buffer.len());
+        return ExitCode::SUCCESS;
+    }
 
     if args.compress {
-        let freq_buff = tally_frequency(buffer);
-        let huffnode = build_huffman_array(freq_buff);
-        let encoded_map = encode_huffman_array(&huffnode);
-        let (bit_buffer, total_bits) = huff_encode_bitvec(buffer, &encoded_map);
-        let serialized_buffer = serialize_huffman(&encoded_map, bit_buffer, total_bits);
-        let base_file_path = match mode {
-            Mode::Stdin => args.out_file.as_ref().unwrap(),
-            Mode::FileIO => {
-                if let Some(ref out_file) = args.out_file {
-                    out_file
-                } else {
-                    args.input.as_ref().unwrap()
+        if let Some(ref path) = args.dump_frequencies {
+            let freq_buff = tally_frequency(buffer);
+            let table = if path.extension().is_some_and(|ext| ext == "tsv") {
+                freq_buff.to_tsv()
+            } else {
+                freq_buff.to_csv()
+            };
+            if !write_or_report(path, table.into_bytes()) {
+                return ExitCode::FAILURE;
+            }
+        }
+
+        if let Some(split_size) = args.split_size {
+            let parts = match compress_split(buffer, split_size) {
+                Ok(parts) => parts,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return ExitCode::FAILURE;
                 }
+            };
+            timer.lap("compress_split");
+            if args.verbose {
+                eprintln!(
+                    "Compressed {} bytes into {} parts",
+                    buffer.len(),
+                    parts.len()
+                );
             }
-        };
 
-        let mut write_file_path: OsString = base_file_path.into();
-        write_file_path.push(".huff");
+            if args.stats {
+                let compressed_len = parts.iter().map(Vec::len).sum();
+                print_stats(&tally_frequency(buffer), compressed_len);
+            }
 
-        write_file(write_file_path, serialized_buffer);
-    } else if args.decompress {
-        let base_file_path = match mode {
-            Mode::Stdin => args.out_file.as_ref().unwrap(),
-            Mode::FileIO => {
-                if let Some(ref out_file) = args.out_file {
-                    out_file
-                } else {
-                    args.input.as_ref().unwrap()
+            let write_file_path = compressed_file_path(&args, &mode);
+            for (idx, part) in parts.into_iter().enumerate() {
+                let mut part_path = write_file_path.clone();
+                part_path.push(format!(".{:03}", idx + 1));
+                if !write_or_report(part_path, part) {
+                    return ExitCode::FAILURE;
+                }
+            }
+            timer.lap("file write");
+            if args.profile {
+                eprint!("{}", timer.report());
+            }
+            return ExitCode::SUCCESS;
+        }
+
+        let serialized_buffer = if let Some(level) = args.level {
+            let serialized_buffer = compress_level(buffer, CompressionLevel::from_u8(level));
+            timer.lap("compress_level");
+            serialized_buffer
+        } else {
+            #[cfg(feature = "serde")]
+            let freq_buff = match args.frequency_file {
+                Some(ref path) => {
+                    let freq_buff = match std::fs::read_to_string(path)
+                        .map_err(|e| HuffErr::InvalidFrequencyTable(e.to_string()))
+                        .and_then(|json| FrequencyBuffer::from_json(&json))
+                    {
+                        Ok(freq_buff) => freq_buff,
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            return ExitCode::FAILURE;
+                        }
+                    };
+
+                    if let Some(byte) = buffer.iter().find(|b| freq_buff[**b] == 0) {
+                        eprintln!(
+                            "Frequency table has no entry for byte {:#04x} present in the input.",
+                            byte
+                        );
+                        return ExitCode::FAILURE;
+                    }
+                    freq_buff
+                }
+                None => tally_frequency(buffer),
+            };
+            #[cfg(not(feature = "serde"))]
+            let freq_buff = tally_frequency(buffer);
+            timer.lap("tally_frequency");
+            #[cfg(feature = "terminal_size")]
+            if args.verbose {
+                if let Some((terminal_size::Width(columns), _)) = terminal_size::terminal_size() {
+                    // Leave room for the symbol, count and percentage around the bar
+                    let width = (columns as usize).saturating_sub(40).max(10);
+                    eprint!("{}", freq_buff.to_ascii_histogram(width));
+                }
+            }
+            match CompressOptions::new()
+                .with_frequencies(freq_buff)
+                .encode_timed(buffer, &mut timer)
+            {
+                Ok(serialized_buffer) => serialized_buffer,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return ExitCode::FAILURE;
                 }
             }
         };
+        if args.verbose {
+            let stored = if args.level.is_none() && is_stored_passthrough(&serialized_buffer) {
+                " (stored, no compression gain)"
+            } else {
+                ""
+            };
+            eprintln!(
+                "Compressed {} bytes into {} bytes{}",
+                buffer.len(),
+                serialized_buffer.len(),
+                stored
+            );
+        }
+        if args.stats {
+            print_stats(&tally_frequency(buffer), serialized_buffer.len());
+        }
 
-        let mut base_file_clone = base_file_path.clone();
+        if args.stdout {
+            if let Err(e) = std::io::stdout().write_all(&serialized_buffer) {
+                eprintln!("Failed to write stdout: {}", e);
+                return ExitCode::FAILURE;
+            }
+        } else if !write_or_report(compressed_file_path(&args, &mode), serialized_buffer) {
+            return ExitCode::FAILURE;
+        }
+        timer.lap("file write");
+    } else if args.decompress {
+        let deserialized_bytes = match decompress(buffer) {
+            Ok(deserialized_bytes) => deserialized_bytes,
+            Err(e) => {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        };
+        timer.lap("decompress");
+        if args.stats {
+            print_stats(&tally_frequency(&deserialized_bytes), buffer.len());
+        }
 
-        if base_file_clone.extension().unwrap() == "huff" {
-            base_file_clone.set_extension("");
+        if args.stdout {
+            if let Err(e) = std::io::stdout().write_all(&deserialized_bytes) {
+                eprintln!("Failed to write stdout: {}", e);
+                return ExitCode::FAILURE;
+            }
+        } else if !write_or_report(decompressed_file_path(&args, &mode), deserialized_bytes) {
+            return ExitCode::FAILURE;
         }
+        timer.lap("file write");
+    }
 
-        let deserialized_bytes = deserialize_huffman(buffer);
+    if args.profile {
+        eprint!("{}", timer.report());
+    }
+    ExitCode::SUCCESS
+}
+
+/// Prints the entropy and expected code length of the original bytes tallied in `freq_buff` and
+/// the ratio of the compressed to the original size for `--stats`.
+fn print_stats(freq_buff: &FrequencyBuffer, compressed_len: usize) {
+    eprintln!("entropy: {:.4} bits/symbol", freq_buff.entropy());
+    eprintln!(
+        "expected code length: {:.4} bits/symbol",
+        freq_buff.expected_bits_per_symbol()
+    );
+    let original_len = freq_buff.total_symbols() as usize;
+    eprintln!(
+        "compression ratio: {:.4} ({} -> {} bytes), {:.2}% space saved",
+        compress_ratio(original_len, compressed_len),
+        original_len,
+        compressed_len,
+        space_saved_percent(original_len, compressed_len)
+    );
+}
 
-        write_file(base_file_clone, deserialized_bytes);
+/// Writes `buffer` to `path` with `write_file`, printing the error if that fails. Returns whether
+/// the file was written.
+fn write_or_report<P: AsRef<Path>>(path: P, buffer: Vec<u8>) -> bool {
+    match write_file(path.as_ref(), buffer) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Failed to write {}: {}", path.as_ref().display(), e);
+            false
+        }
     }
 }
@@ -0,0 +1,181 @@
+//! Huffc N-gram Analysis - Frequency Analysis over N-byte Symbols
+//!
+//! Generalizes the byte frequency analysis to symbols made of `N` consecutive bytes. The symbol
+//! space grows to `256^N` entries (65536 for bigrams, 16M for trigrams), so counts are stored
+//! sparsely and the Huffman code is built from the non-zero entries only.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use huffc::ngram::tally_ngram_frequency;
+//!
+//! let bigrams = tally_ngram_frequency::<2>(b"abababcd");
+//! assert_eq!(bigrams.count_of(b"ab"), 3);
+//! let codes = bigrams.build_codes();
+//! assert_eq!(codes.len(), 2);
+//! ```
+//!
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+use bitvec::{order::Msb0, vec::BitVec};
+
+use crate::FrequencyBuffer;
+
+/// Frequency counts of `N`-byte symbols, `N` must be in `1..=4`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HuffNgramFrequencyBuffer<const N: usize>(pub HashMap<[u8; N], u64>);
+
+/// Tallies the frequency of every `N`-byte symbol in `bytes`.
+///
+/// The input is split into consecutive, non-overlapping `N`-byte symbols as they would be
+/// encoded, trailing bytes that do not fill a whole symbol are not counted.
+pub fn tally_ngram_frequency<const N: usize>(bytes: &[u8]) -> HuffNgramFrequencyBuffer<N> {
+    const { assert!(N >= 1 && N <= 4, "N-gram size must be in 1..=4") };
+
+    let mut counts = HashMap::new();
+    bytes.chunks_exact(N).for_each(|chunk| {
+        let symbol: [u8; N] = chunk.try_into().unwrap();
+        *counts.entry(symbol).or_insert(0) += 1;
+    });
+    HuffNgramFrequencyBuffer(counts)
+}
+
+impl<const N: usize> HuffNgramFrequencyBuffer<N> {
+    /// Returns the count of `symbol`, 0 if it was never seen.
+    pub fn count_of(&self, symbol: &[u8; N]) -> u64 {
+        self.0.get(symbol).copied().unwrap_or(0)
+    }
+
+    /// Number of distinct symbols with a non-zero count.
+    pub fn len(&self) -> usize {
+        self.0.values().filter(|count| **count != 0).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Builds a Huffman code for the non-zero entries.
+    ///
+    /// Ties between equal frequencies are broken by symbol value so the result is deterministic.
+    /// A single distinct symbol is given the one bit code `0`.
+    pub fn build_codes(&self) -> HashMap<[u8; N], BitVec<u8, Msb0>> {
+        let mut symbols: Vec<([u8; N], u64)> = self
+            .0
+            .iter()
+            .filter(|(_, count)| **count != 0)
+            .map(|(symbol, count)| (*symbol, *count))
+            .collect();
+        symbols.sort_unstable();
+
+        let mut codes = HashMap::with_capacity(symbols.len());
+        if symbols.len() == 1 {
+            let mut bits = BitVec::new();
+            bits.push(false);
+            codes.insert(symbols[0].0, bits);
+            return codes;
+        }
+
+        // Nodes are stored as (left, right) child indices, leaves are the first `symbols.len()`
+        // entries and have no children.
+        let mut children: Vec<Option<(usize, usize)>> = vec![None; symbols.len()];
+        let mut heap: BinaryHeap<Reverse<(u64, usize)>> = symbols
+            .iter()
+            .enumerate()
+            .map(|(idx, (_, count))| Reverse((*count, idx)))
+            .collect();
+
+        while heap.len() > 1 {
+            let Reverse((left_freq, left)) = heap.pop().unwrap();
+            let Reverse((right_freq, right)) = heap.pop().unwrap();
+            children.push(Some((left, right)));
+            heap.push(Reverse((
+                left_freq.saturating_add(right_freq),
+                children.len() - 1,
+            )));
+        }
+
+        let Some(Reverse((_, root))) = heap.pop() else {
+            return codes;
+        };
+
+        let mut stack = vec![(root, BitVec::<u8, Msb0>::new())];
+        while let Some((node, prefix)) = stack.pop() {
+            match children[node] {
+                Some((left, right)) => {
+                    let mut left_prefix = prefix.clone();
+                    left_prefix.push(false);
+                    let mut right_prefix = prefix;
+                    right_prefix.push(true);
+                    stack.push((left, left_prefix));
+                    stack.push((right, right_prefix));
+                }
+                None => {
+                    codes.insert(symbols[node].0, prefix);
+                }
+            }
+        }
+        codes
+    }
+}
+
+impl HuffNgramFrequencyBuffer<1> {
+    /// Converts unigram counts into the dense `FrequencyBuffer` representation.
+    pub fn into_frequency_buffer(self) -> FrequencyBuffer {
+        let mut fb = FrequencyBuffer([0; 256]);
+        self.0
+            .into_iter()
+            .for_each(|([symbol], count)| fb[symbol] = count);
+        fb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tally_frequency;
+
+    #[test]
+    fn tally_bigrams() {
+        let bigrams = tally_ngram_frequency::<2>(b"abababcdx");
+
+        assert_eq!(bigrams.count_of(b"ab"), 3);
+        assert_eq!(bigrams.count_of(b"cd"), 1);
+        assert_eq!(bigrams.count_of(b"ba"), 0);
+        assert_eq!(bigrams.len(), 2);
+    }
+
+    #[test]
+    fn unigrams_match_frequency_buffer() {
+        let bytes = [1, 2, 1, 1, 1, 1, 1, 1, 1, 3, 1];
+        let unigrams = tally_ngram_frequency::<1>(&bytes).into_frequency_buffer();
+
+        assert_eq!(unigrams.0, tally_frequency(&bytes).0);
+    }
+
+    #[test]
+    fn ngram_codes_are_prefix_free() {
+        let trigrams = tally_ngram_frequency::<3>(b"aaaaaaaaabbbbbbcccdddeeefff");
+        let codes = trigrams.build_codes();
+
+        assert_eq!(codes.len(), trigrams.len());
+        assert!(codes[b"aaa"].len() <= codes[b"fff"].len());
+        for (a, code_a) in &codes {
+            for (b, code_b) in &codes {
+                if a != b {
+                    assert!(!code_b.starts_with(code_a));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn single_ngram_gets_one_bit() {
+        let codes = tally_ngram_frequency::<2>(b"zzzzzz").build_codes();
+
+        assert_eq!(codes[b"zz"].len(), 1);
+    }
+}
@@ -0,0 +1,445 @@
+//! Huffc Parallel - Multi-threaded Block Compression
+//!
+//! The input is split into one block per thread and every block is Huffman encoded
+//! independently, with its own header. Blocks that Huffman coding cannot shrink, such as a short
+//! block of many distinct bytes, are stored instead. The blocks are stored back to back behind a
+//! small table of block lengths, so they can also be decoded independently.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use huffc::parallel::{compress_parallel, decompress_parallel};
+//!
+//! let bytes = b"abracadabra".repeat(100);
+//! let compressed = compress_parallel(&bytes, 4);
+//! assert_eq!(decompress_parallel(&compressed).unwrap(), bytes);
+//! ```
+//!
+//! `tally_frequency_parallel` counts the byte frequencies of a large buffer in slices on the rayon
+//! thread pool.
+//!
+use std::{collections::HashMap, num::NonZeroUsize, thread};
+
+use rayon::prelude::*;
+
+use crate::{
+    canonical_huffman_codes, huff_encode_bitvec, huff_payload_offset,
+    level::{compress_level_frame, decompress_in_block, CompressionLevel},
+    serialize_huffman_frame, tally_frequency, u64_to_u8, u8_to_u64, validate_huff_bytes,
+    wrap_huff_payload, Encoded, FrequencyBuffer, HuffErr,
+};
+
+/// Format byte of a buffer holding independently compressed blocks.
+pub(crate) const FORMAT_BLOCKS: u8 = 0x10;
+
+/// Huffman encodes `block` with `encoded_map`, or stores it if encoding it would not make it
+/// smaller.
+///
+/// # Errors
+///
+/// Returns `HuffErr::SymbolNotInCodebook` if `block` contains a byte without a code.
+pub(crate) fn compress_block(
+    block: &[u8],
+    encoded_map: &HashMap<u8, Encoded>,
+) -> Result<Vec<u8>, HuffErr> {
+    if block.is_empty() {
+        return Ok(compress_level_frame(block, CompressionLevel::Store));
+    }
+    let (bit_buffer, total_bits) = huff_encode_bitvec(block, encoded_map)?;
+    let encoded = serialize_huffman_frame(encoded_map, bit_buffer, total_bits);
+    if encoded.len() <= block.len() {
+        return Ok(encoded);
+    }
+    Ok(compress_level_frame(block, CompressionLevel::Store))
+}
+
+/// `compress_block` with the canonical code of `block` itself.
+pub(crate) fn compress_own_block(block: &[u8]) -> Vec<u8> {
+    let encoded_map = canonical_huffman_codes(&tally_frequency(block))
+        .expect("the code lengths of a Huffman tree satisfy the Kraft inequality");
+    compress_block(block, &encoded_map).expect("the encoding is built from the same bytes")
+}
+
+/// Applies `f` to every block on at most `num_threads` threads, each thread handles a contiguous
+/// run of blocks. The results are in block order.
+fn map_blocks<T: Send>(blocks: &[&[u8]], num_threads: usize, f: fn(&[u8]) -> T) -> Vec<T> {
+    if blocks.is_empty() {
+        return Vec::new();
+    }
+
+    let blocks_per_thread = blocks.len().div_ceil(num_threads.max(1));
+    thread::scope(|scope| {
+        let handles: Vec<_> = blocks
+            .chunks(blocks_per_thread)
+            .map(|run| scope.spawn(move || run.iter().map(|block| f(block)).collect::<Vec<T>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+/// Counts the byte frequencies of `bytes` in one equal slice per thread of the rayon thread pool,
+/// `rayon::current_num_threads`, each slice is tallied independently and the tables are summed.
+/// Run it inside `rayon::ThreadPool::install` to use another number of threads. The result is
+/// identical to `tally_frequency`.
+pub fn tally_frequency_parallel(bytes: &[u8]) -> FrequencyBuffer {
+    let chunk_size = bytes.len().div_ceil(rayon::current_num_threads()).max(1);
+
+    bytes
+        .par_chunks(chunk_size)
+        .map(tally_frequency)
+        .reduce(|| FrequencyBuffer([0; 256]), |total, local| total + local)
+}
+
+fn available_threads() -> usize {
+    thread::available_parallelism()
+        .map(NonZeroUsize::get)
+        .unwrap_or(1)
+}
+
+/// Compresses `bytes` on `num_threads` threads, one block per thread. A `num_threads` of 0 is
+/// treated as 1.
+///
+/// Layout: `HUFF_MAGIC`, the format version and a checksum like those of `serialize_huffman`,
+/// then the block buffer: format byte (`0x10`), number of blocks (8 bytes), the length of every
+/// compressed block (8 bytes each), then the compressed blocks.
+pub fn compress_parallel(bytes: &[u8], num_threads: usize) -> Vec<u8> {
+    let num_threads = num_threads.max(1);
+    let block_size = bytes.len().div_ceil(num_threads).max(1);
+    let blocks: Vec<&[u8]> = bytes.chunks(block_size).collect();
+    let blocks = map_blocks(&blocks, num_threads, compress_own_block);
+
+    wrap_huff_payload(&block_buffer(blocks))
+}
+
+pub(crate) fn block_buffer(blocks: Vec<Vec<u8>>) -> Vec<u8> {
+    let mut compressed = vec![FORMAT_BLOCKS];
+    compressed.extend_from_slice(&u64_to_u8(blocks.len() as u64));
+    blocks
+        .iter()
+        .for_each(|block| compressed.extend_from_slice(&u64_to_u8(block.len() as u64)));
+    blocks
+        .into_iter()
+        .for_each(|block| compressed.extend(block));
+    compressed
+}
+
+/// Smallest part `compress_split` can write: a single stored byte in a one block buffer, behind
+/// the magic bytes, version and checksum.
+const MIN_SPLIT_SIZE: u64 = 28;
+
+/// Compresses `bytes` into standalone parts of at most `max_part_bytes` each.
+///
+/// The input is cut into blocks sized by the ratio achieved on its start, which are compressed on
+/// all available threads like the blocks of `compress_parallel`, every block becomes a part. Only
+/// a block whose part does not fit is halved and compressed again. A part is a Huffman buffer, or
+/// a block buffer holding the stored block, behind the magic bytes, version and checksum, so
+/// every part is self delimiting. Every part decompresses on its own with `decompress_level`, the
+/// parts concatenated in order decompress to the whole input.
+///
+/// # Errors
+///
+/// * `HuffErr::NullInput` - `bytes` is empty, there is nothing to split.
+/// * `HuffErr::SplitSizeTooSmall` - `max_part_bytes` cannot hold a single stored byte.
+pub fn compress_split(bytes: &[u8], max_part_bytes: u64) -> Result<Vec<Vec<u8>>, HuffErr> {
+    if bytes.is_empty() {
+        return Err(HuffErr::NullInput("compress_split"));
+    }
+    if max_part_bytes < MIN_SPLIT_SIZE {
+        return Err(HuffErr::SplitSizeTooSmall(max_part_bytes));
+    }
+    let max_part_bytes = usize::try_from(max_part_bytes).unwrap_or(usize::MAX);
+
+    // The block size is scaled by the ratio achieved on a probe block, with some headroom as the
+    // ratio varies along the input
+    let probe_len = bytes.len().min(max_part_bytes);
+    let probe_part_len = split_part(&bytes[..probe_len]).len();
+    let block_size = (probe_len as u128 * max_part_bytes as u128 * 9
+        / (probe_part_len as u128 * 10))
+        .clamp(1, usize::MAX as u128) as usize;
+
+    let blocks: Vec<&[u8]> = bytes.chunks(block_size).collect();
+    let parts = map_blocks(&blocks, available_threads(), split_part);
+
+    let mut fitted = Vec::with_capacity(parts.len());
+    for (block, part) in blocks.into_iter().zip(parts) {
+        fit_part(block, part, max_part_bytes, &mut fitted);
+    }
+    Ok(fitted)
+}
+
+fn split_part(block: &[u8]) -> Vec<u8> {
+    let part = compress_own_block(block);
+    // A stored buffer runs to the end of its input, wrapping it gives it a length
+    if part.first() == Some(&(CompressionLevel::Store as u8)) {
+        return wrap_huff_payload(&block_buffer(vec![part]));
+    }
+    wrap_huff_payload(&part)
+}
+
+/// Pushes `part`, the compressed `block`, if it fits, otherwise halves `block` until every half
+/// fits. A single byte always fits in `MIN_SPLIT_SIZE`.
+fn fit_part(block: &[u8], part: Vec<u8>, max_part_bytes: usize, parts: &mut Vec<Vec<u8>>) {
+    if part.len() <= max_part_bytes {
+        parts.push(part);
+        return;
+    }
+
+    let (left, right) = block.split_at(block.len() / 2);
+    for half in [left, right] {
+        fit_part(half, split_part(half), max_part_bytes, parts);
+    }
+}
+
+/// Decompresses a buffer produced by `compress_parallel`, decoding the blocks in parallel on at
+/// most as many threads as the machine provides.
+///
+/// # Errors
+///
+/// * `HuffErr::NullInput` - `bytes` is empty.
+/// * `HuffErr::NotAHuffFile` - The buffer does not start with `HUFF_MAGIC`.
+/// * `HuffErr::ChecksumMismatch` - The block buffer does not match its checksum.
+/// * `HuffErr::MalformedData` - The block table is truncated or inconsistent, a block does not
+///   decode, or bytes follow the last block.
+pub fn decompress_parallel(bytes: &[u8]) -> Result<Vec<u8>, HuffErr> {
+    if bytes.is_empty() {
+        return Err(HuffErr::NullInput("decompress_parallel"));
+    }
+    let header = validate_huff_bytes(bytes)?;
+    if header.format != FORMAT_BLOCKS {
+        return Err(HuffErr::MalformedData("not a block buffer".to_string()));
+    }
+    decompress_blocks(&bytes[huff_payload_offset(header.format_version)..])
+}
+
+/// Decodes a block buffer without the magic bytes, version and checksum.
+pub(crate) fn decompress_blocks(bytes: &[u8]) -> Result<Vec<u8>, HuffErr> {
+    let (blocks, end) = split_blocks(bytes)?;
+    if end != bytes.len() {
+        return Err(HuffErr::MalformedData(
+            "trailing bytes after the last block".to_string(),
+        ));
+    }
+    let decoded = map_blocks(&blocks, available_threads(), decompress_in_block);
+
+    let mut decompressed = Vec::new();
+    for block in decoded {
+        decompressed.extend(block?);
+    }
+    Ok(decompressed)
+}
+
+/// Length in bytes of the block buffer at the start of `bytes`, `None` if it is not a block
+/// buffer or is truncated.
+pub(crate) fn blocks_frame_len(bytes: &[u8]) -> Option<usize> {
+    split_blocks(bytes).ok().map(|(_, end)| end)
+}
+
+/// Splits a block buffer into the compressed blocks it holds and the offset of its end.
+pub(crate) fn split_blocks(bytes: &[u8]) -> Result<(Vec<&[u8]>, usize), HuffErr> {
+    let truncated = || HuffErr::MalformedData("truncated block table".to_string());
+    if bytes.first() != Some(&FORMAT_BLOCKS) || bytes.len() < 9 {
+        return Err(HuffErr::MalformedData("not a block buffer".to_string()));
+    }
+
+    // Every block takes 8 bytes in the table and at least its format byte, so the block count is
+    // bounded by the buffer length before anything is allocated
+    let num_blocks = u8_to_u64(&bytes[1..9]);
+    if num_blocks > (bytes.len() as u64 - 9) / 9 {
+        return Err(truncated());
+    }
+    let num_blocks = num_blocks as usize;
+    let table_end = 9 + num_blocks * 8;
+
+    let mut blocks = Vec::with_capacity(num_blocks);
+    let mut start = table_end;
+    for len in bytes[9..table_end].chunks_exact(8).map(u8_to_u64) {
+        if len == 0 {
+            return Err(HuffErr::MalformedData("empty block".to_string()));
+        }
+        let end = start
+            .checked_add(len as usize)
+            .filter(|end| *end <= bytes.len())
+            .ok_or_else(truncated)?;
+        blocks.push(&bytes[start..end]);
+        start = end;
+    }
+    Ok((blocks, start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parallel_tally_matches_serial() {
+        let original = std::fs::read("./tests/resources/dna_seq_test.txt").unwrap();
+        for num_threads in [1, 3, 4, 64] {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(num_threads)
+                .build()
+                .unwrap();
+            for bytes in [&original[..], b"", b"a"] {
+                assert_eq!(
+                    pool.install(|| tally_frequency_parallel(bytes)).0,
+                    tally_frequency(bytes).0
+                );
+            }
+        }
+    }
+
+    const FILE_PATH: &str = "./tests/resources/dna_seq_test.txt";
+
+    #[test]
+    fn parallel_round_trip() {
+        let original = std::fs::read(FILE_PATH).unwrap();
+        for num_threads in [0, 1, 3, 4] {
+            let compressed = compress_parallel(&original, num_threads);
+            assert_eq!(decompress_parallel(&compressed).unwrap(), original);
+        }
+    }
+
+    #[test]
+    fn parallel_blocks_decode_independently() {
+        let original = std::fs::read(FILE_PATH).unwrap();
+        let compressed = compress_parallel(&original, 4);
+        let (blocks, _) = split_blocks(&compressed[crate::HUFF_HEADER_LEN..]).unwrap();
+
+        assert_eq!(blocks.len(), 4);
+        let second = crate::level::decompress_in_block(blocks[1]).unwrap();
+        assert_eq!(second, original[2500..5000]);
+    }
+
+    #[test]
+    fn parallel_degenerate_input() {
+        let single_symbol = [b'z'; 1000];
+        let compressed = compress_parallel(&single_symbol, 4);
+        assert_eq!(decompress_parallel(&compressed).unwrap(), single_symbol);
+
+        let compressed = compress_parallel(b"x", 4);
+        assert_eq!(decompress_parallel(&compressed).unwrap(), b"x");
+
+        let compressed = compress_parallel(&[], 4);
+        assert!(decompress_parallel(&compressed).unwrap().is_empty());
+    }
+
+    #[test]
+    fn crafted_block_tables_are_rejected() {
+        // Many empty blocks
+        let num_blocks = 200_000u64;
+        let mut crafted = vec![FORMAT_BLOCKS];
+        crafted.extend_from_slice(&u64_to_u8(num_blocks));
+        crafted.resize(9 + num_blocks as usize * 8, 0);
+        assert!(decompress_parallel(&crafted).is_err());
+
+        // More blocks than the buffer can hold
+        let mut crafted = vec![FORMAT_BLOCKS];
+        crafted.extend_from_slice(&u64_to_u8(u64::MAX));
+        assert!(decompress_parallel(&crafted).is_err());
+
+        // A block buffer nested in a block buffer
+        let inner = compress_parallel(b"abracadabra", 1);
+        let mut nested = vec![FORMAT_BLOCKS];
+        nested.extend_from_slice(&u64_to_u8(1));
+        nested.extend_from_slice(&u64_to_u8(inner.len() as u64));
+        nested.extend(inner);
+        assert!(decompress_parallel(&nested).is_err());
+    }
+
+    #[test]
+    fn split_parts_fit_and_concatenate() {
+        let original = std::fs::read(FILE_PATH).unwrap();
+        let parts = compress_split(&original, 1000).unwrap();
+
+        assert!(parts.len() > 1);
+        assert!(parts.iter().all(|part| part.len() <= 1000));
+        assert_eq!(
+            crate::level::decompress_level(&parts[0]).unwrap(),
+            original[..crate::level::decompress_level(&parts[0]).unwrap().len()]
+        );
+        assert_eq!(
+            crate::level::decompress_level(&parts.concat()).unwrap(),
+            original
+        );
+    }
+
+    #[test]
+    fn split_size_too_small() {
+        assert!(matches!(
+            compress_split(b"abc", 27),
+            Err(HuffErr::SplitSizeTooSmall(27))
+        ));
+        assert!(matches!(
+            compress_split(&[], 100),
+            Err(HuffErr::NullInput("compress_split"))
+        ));
+    }
+
+    #[test]
+    fn split_degenerate_input() {
+        let decompress = |parts: Vec<Vec<u8>>| crate::level::decompress_level(&parts.concat());
+
+        let parts = compress_split(b"abc", MIN_SPLIT_SIZE).unwrap();
+        assert!(parts
+            .iter()
+            .all(|part| part.len() <= MIN_SPLIT_SIZE as usize));
+        assert_eq!(decompress(parts).unwrap(), b"abc");
+
+        let single_symbol = [b'a'; 100];
+        let parts = compress_split(&single_symbol, 1000).unwrap();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(decompress(parts).unwrap(), single_symbol);
+
+        let mut mixed = vec![b'a'; 100];
+        mixed.extend_from_slice(b"abracadabra".repeat(20).as_slice());
+        let parts = compress_split(&mixed, 100).unwrap();
+        assert!(parts.iter().all(|part| part.len() <= 100));
+        assert_eq!(decompress(parts).unwrap(), mixed);
+    }
+
+    #[test]
+    fn checksum_detects_a_flipped_bit() {
+        let original = std::fs::read(FILE_PATH).unwrap();
+
+        let mut compressed = compress_parallel(&original, 4);
+        compressed[crate::HUFF_HEADER_LEN + 40] ^= 0x10;
+        assert!(matches!(
+            decompress_parallel(&compressed),
+            Err(HuffErr::ChecksumMismatch { .. })
+        ));
+
+        let mut parts = compress_split(&original, 1000).unwrap();
+        let last = parts[1].len() - 1;
+        parts[1][last] ^= 0x01;
+        assert!(matches!(
+            crate::level::decompress_level(&parts.concat()),
+            Err(HuffErr::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn unwrapped_block_buffers_are_rejected() {
+        let original = std::fs::read(FILE_PATH).unwrap();
+        let compressed = compress_parallel(&original, 4);
+        let block_buffer = &compressed[crate::HUFF_HEADER_LEN..];
+        assert!(matches!(
+            decompress_parallel(block_buffer),
+            Err(HuffErr::NotAHuffFile)
+        ));
+        assert!(matches!(
+            crate::level::decompress_level(block_buffer),
+            Err(HuffErr::NotAHuffFile)
+        ));
+    }
+
+    #[test]
+    fn truncated_block_table() {
+        let original = std::fs::read(FILE_PATH).unwrap();
+        let compressed = compress_parallel(&original, 4);
+
+        assert!(decompress_parallel(&compressed[..20]).is_err());
+        assert!(decompress_parallel(&[FORMAT_BLOCKS, 0, 0]).is_err());
+    }
+}
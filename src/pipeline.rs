@@ -0,0 +1,216 @@
+//! Huffc Pipeline - Configurable Transforms Before Huffman Encoding
+//!
+//! `CompressionPipeline` chains reversible transforms, e.g. `input -> RLE -> delta -> Huffman`,
+//! without glue code. The built `ComposedCompressor` runs the transforms in order before
+//! Huffman encoding and their inverses in reverse order after decoding.
+//!
+//! The compressed buffer starts with a bitmask of the built-in transforms that were applied,
+//! followed by a Huffman or stored buffer. The mask records which transforms were used, not
+//! their order, decompression always uses the compressor's own transforms and rejects buffers
+//! whose mask does not match.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use huffc::pipeline::{CompressionPipeline, DeltaTransform, RleTransform};
+//!
+//! let compressor = CompressionPipeline::new()
+//!     .add_transform(Box::new(RleTransform))
+//!     .add_transform(Box::new(DeltaTransform))
+//!     .build();
+//!
+//! let bytes = b"aaaaaaaabbbbbbbbccccccccdddddddd";
+//! let compressed = compressor.compress(bytes);
+//! assert_eq!(compressor.decompress(&compressed).unwrap(), bytes);
+//! ```
+//!
+use crate::{
+    level::decompress_in_block,
+    parallel::compress_own_block,
+    transform::{delta_decode, delta_encode, rle_decode, rle_encode},
+    HuffErr,
+};
+
+/// Reversible transform applied before Huffman encoding.
+pub trait Transform: Send + Sync {
+    /// Transforms the input.
+    fn forward(&self, data: &[u8]) -> Vec<u8>;
+
+    /// Reverts `forward`, failing if `data` could not have been produced by it.
+    fn inverse(&self, data: &[u8]) -> Result<Vec<u8>, HuffErr>;
+
+    /// Bit recorded in the header when the transform is applied, 0 for transforms without one.
+    fn flag(&self) -> u8 {
+        0
+    }
+}
+
+/// Run-length encoding, two equal bytes in a row are followed by a count of further repeats.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RleTransform;
+
+impl Transform for RleTransform {
+    fn forward(&self, data: &[u8]) -> Vec<u8> {
+        rle_encode(data)
+    }
+
+    fn inverse(&self, data: &[u8]) -> Result<Vec<u8>, HuffErr> {
+        rle_decode(data).ok_or(HuffErr::MalformedData("truncated run".to_string()))
+    }
+
+    fn flag(&self) -> u8 {
+        0x01
+    }
+}
+
+/// Delta encoding, every byte is replaced by its wrapping difference to the previous one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeltaTransform;
+
+impl Transform for DeltaTransform {
+    fn forward(&self, data: &[u8]) -> Vec<u8> {
+        delta_encode(data)
+    }
+
+    fn inverse(&self, data: &[u8]) -> Result<Vec<u8>, HuffErr> {
+        Ok(delta_decode(data))
+    }
+
+    fn flag(&self) -> u8 {
+        0x02
+    }
+}
+
+/// Builder of a `ComposedCompressor`.
+#[derive(Default)]
+pub struct CompressionPipeline {
+    transforms: Vec<Box<dyn Transform>>,
+}
+
+impl CompressionPipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends `transform`, transforms run in the order they were added.
+    pub fn add_transform(mut self, transform: Box<dyn Transform>) -> Self {
+        self.transforms.push(transform);
+        self
+    }
+
+    pub fn build(self) -> ComposedCompressor {
+        let mask = self
+            .transforms
+            .iter()
+            .fold(0, |mask, transform| mask | transform.flag());
+        ComposedCompressor {
+            transforms: self.transforms,
+            mask,
+        }
+    }
+}
+
+/// Runs the transforms of a `CompressionPipeline` around Huffman encoding.
+pub struct ComposedCompressor {
+    transforms: Vec<Box<dyn Transform>>,
+    mask: u8,
+}
+
+impl ComposedCompressor {
+    /// Bitmask of the built-in transforms, written as the first byte of every buffer.
+    pub fn mask(&self) -> u8 {
+        self.mask
+    }
+
+    /// Applies the transforms in order and Huffman encodes the result, which is stored instead
+    /// if encoding would not make it smaller.
+    pub fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let transformed = self
+            .transforms
+            .iter()
+            .fold(data.to_vec(), |data, transform| transform.forward(&data));
+
+        let mut compressed = vec![self.mask];
+        compressed.extend(compress_own_block(&transformed));
+        compressed
+    }
+
+    /// Decodes a buffer produced by `compress` and applies the inverse transforms in reverse
+    /// order.
+    ///
+    /// # Errors
+    ///
+    /// * `HuffErr::NullInput` - `data` is empty.
+    /// * `HuffErr::MalformedData` - The mask does not match the transforms of this compressor,
+    ///   or the buffer or a transform's output is malformed.
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, HuffErr> {
+        let Some((mask, compressed)) = data.split_first() else {
+            return Err(HuffErr::NullInput("ComposedCompressor::decompress"));
+        };
+        if *mask != self.mask {
+            return Err(HuffErr::MalformedData(format!(
+                "buffer was compressed with transforms {:#04x}, expected {:#04x}",
+                mask, self.mask
+            )));
+        }
+
+        self.transforms
+            .iter()
+            .rev()
+            .try_fold(decompress_in_block(compressed)?, |data, transform| {
+                transform.inverse(&data)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipeline_round_trip() {
+        let compressor = CompressionPipeline::new()
+            .add_transform(Box::new(RleTransform))
+            .add_transform(Box::new(DeltaTransform))
+            .build();
+        assert_eq!(compressor.mask(), 0x03);
+
+        let original = std::fs::read("./tests/resources/dna_seq_test.txt").unwrap();
+        for bytes in [&original[..], b"", b"a", b"aaaaaaaaaaaaaaaaaaaaaaaaaa"] {
+            let compressed = compressor.compress(bytes);
+            assert_eq!(compressed[0], 0x03);
+            assert_eq!(compressor.decompress(&compressed).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn transforms_run_in_order() {
+        let compressor = CompressionPipeline::new()
+            .add_transform(Box::new(DeltaTransform))
+            .add_transform(Box::new(RleTransform))
+            .build();
+
+        // Delta turns the ramp into a run of ones, which RLE collapses
+        let ramp: Vec<u8> = (0..=255).collect();
+        let compressed = compressor.compress(&ramp);
+        assert!(compressed.len() < 16);
+        assert_eq!(compressor.decompress(&compressed).unwrap(), ramp);
+    }
+
+    #[test]
+    fn decompress_rejects_other_pipelines() {
+        let rle = CompressionPipeline::new()
+            .add_transform(Box::new(RleTransform))
+            .build();
+        let delta = CompressionPipeline::new()
+            .add_transform(Box::new(DeltaTransform))
+            .build();
+
+        let compressed = rle.compress(b"aaaabbbb");
+        assert!(matches!(
+            delta.decompress(&compressed),
+            Err(HuffErr::MalformedData(_))
+        ));
+        assert!(matches!(rle.decompress(&[]), Err(HuffErr::NullInput(_))));
+    }
+}
@@ -0,0 +1,290 @@
+//! Huffc Proto - Protobuf Symbol Header
+//!
+//! An alternative to the binary layout of `serialize_huffman` whose symbol header is a protobuf
+//! message, so other languages can read it with their protobuf runtime instead of a hand written
+//! parser. The schema is in `proto/huffman.proto`, the buffer is laid out as: format byte
+//! (`0x02`), size of the encoded header in bytes (8 bytes, big endian), the encoded
+//! `HuffmanHeader`, then the bit buffer. It has the same magic bytes, version and checksum in
+//! front as `serialize_huffman`, `decompress_level` reads both layouts.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use huffc::{
+//!     build_huffman_array, encode_huffman_array, huff_encode_bitvec, level::decompress_level,
+//!     proto::serialize_huffman_proto, tally_frequency,
+//! };
+//!
+//! let bytes = b"abracadabra";
+//! let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(bytes)));
+//! let (bit_buffer, total_bits) = huff_encode_bitvec(bytes, &encoded_map).unwrap();
+//!
+//! let serialized = serialize_huffman_proto(&encoded_map, bit_buffer, total_bits).unwrap();
+//! assert_eq!(decompress_level(&serialized).unwrap(), bytes);
+//! ```
+//!
+use std::collections::HashMap;
+
+use bitvec::{order::Msb0, slice::BitSlice, vec::BitVec};
+use prost::Message;
+
+use crate::{
+    decoder::DecodeTrie, huff_payload_offset, u64_to_u8, u8_to_u64, validate_huff_bytes,
+    wrap_huff_payload, Encoded, HuffErr,
+};
+
+/// Format byte of a Huffman encoded buffer with a protobuf symbol header.
+pub const FORMAT_HUFFMAN_PROTO: u8 = 0x02;
+
+/// Symbol header of the buffer, see `proto/huffman.proto`.
+#[derive(Clone, PartialEq, Message)]
+pub struct HuffmanHeader {
+    #[prost(uint64, tag = "1")]
+    pub total_bits: u64,
+    #[prost(message, repeated, tag = "2")]
+    pub entries: Vec<SymbolEntry>,
+}
+
+/// Code of a single symbol, `codeword` holds the code in its low `code_length` bits.
+#[derive(Clone, PartialEq, Message)]
+pub struct SymbolEntry {
+    #[prost(uint32, tag = "1")]
+    pub symbol: u32,
+    #[prost(uint32, tag = "2")]
+    pub code_length: u32,
+    #[prost(uint64, tag = "3")]
+    pub codeword: u64,
+}
+
+/// Serializes the encoded data with a protobuf symbol header, the entries are sorted by symbol
+/// so equal input always gives equal output.
+///
+/// # Errors
+///
+/// Returns `HuffErr::CodewordTooLong` if a code longer than 64 bits has a set bit beyond the
+/// 64 bits the `codeword` field holds.
+pub fn serialize_huffman_proto(
+    encoded_map: &HashMap<u8, Encoded>,
+    bit_buffer: Vec<u8>,
+    total_bits: u64,
+) -> Result<Vec<u8>, HuffErr> {
+    let mut entries = encoded_map
+        .values()
+        .map(|encoded| {
+            let overflow = encoded.bits.len().saturating_sub(64);
+            if encoded.bits[..overflow].any() {
+                return Err(HuffErr::CodewordTooLong {
+                    symbol: encoded.value,
+                    code_length: encoded.num_bits_sequence,
+                });
+            }
+            let codeword = encoded.bits[overflow..]
+                .iter()
+                .by_vals()
+                .fold(0u64, |codeword, bit| codeword << 1 | bit as u64);
+            Ok(SymbolEntry {
+                symbol: encoded.value as u32,
+                code_length: encoded.bits.len() as u32,
+                codeword,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.symbol);
+
+    let header = HuffmanHeader {
+        total_bits,
+        entries,
+    }
+    .encode_to_vec();
+
+    let mut frame = Vec::with_capacity(9 + header.len() + bit_buffer.len());
+    frame.push(FORMAT_HUFFMAN_PROTO);
+    frame.extend_from_slice(&u64_to_u8(header.len() as u64));
+    frame.extend(header);
+    frame.extend(bit_buffer);
+    Ok(wrap_huff_payload(&frame))
+}
+
+/// Decodes a buffer produced by `serialize_huffman_proto`.
+///
+/// # Errors
+///
+/// Returns the errors of `validate_huff_bytes`, `HuffErr::MalformedData` if the buffer is
+/// truncated, the header cannot be decoded or the codes are invalid, and
+/// `HuffErr::CorruptedBitstream` if the bit buffer contains a code that matches no symbol.
+pub fn deserialize_huffman_proto(huff_bytes: &[u8]) -> Result<Vec<u8>, HuffErr> {
+    let header = validate_huff_bytes(huff_bytes)?;
+    decode_proto_frame(&huff_bytes[huff_payload_offset(header.format_version)..])
+}
+
+/// Decodes the payload of a `serialize_huffman_proto` buffer, starting with its format byte.
+pub(crate) fn decode_proto_frame(huff_bytes: &[u8]) -> Result<Vec<u8>, HuffErr> {
+    if huff_bytes.len() < 9 || huff_bytes[0] != FORMAT_HUFFMAN_PROTO {
+        return Err(HuffErr::MalformedData(
+            "not a protobuf header buffer".to_string(),
+        ));
+    }
+
+    let header_len = usize::try_from(u8_to_u64(&huff_bytes[1..9]))
+        .ok()
+        .filter(|header_len| *header_len <= huff_bytes.len() - 9)
+        .ok_or(HuffErr::MalformedData("truncated header".to_string()))?;
+    let header = HuffmanHeader::decode(&huff_bytes[9..9 + header_len])
+        .map_err(|err| HuffErr::MalformedData(format!("invalid header: {}", err)))?;
+
+    let encoded_map = header
+        .entries
+        .iter()
+        .map(symbol_entry_to_encoded)
+        .collect::<Result<HashMap<_, _>, _>>()?;
+    let trie = DecodeTrie::new(&encoded_map)?;
+
+    let bits = BitSlice::<u8, Msb0>::from_slice(&huff_bytes[9 + header_len..]);
+    let total_bits = usize::try_from(header.total_bits)
+        .ok()
+        .filter(|total_bits| *total_bits <= bits.len())
+        .ok_or(HuffErr::MalformedData("truncated bit buffer".to_string()))?;
+
+    let mut decoded_buffer = Vec::new();
+    let mut read_bits = 0;
+    while read_bits < total_bits {
+        match trie.decode(&bits[read_bits..total_bits]) {
+            Ok(Some((symbol, code_len))) => {
+                decoded_buffer.push(symbol);
+                read_bits += code_len;
+            }
+            Ok(None) => {
+                return Err(HuffErr::MalformedData(
+                    "bit buffer ends inside a code".to_string(),
+                ))
+            }
+            Err(_) => {
+                return Err(HuffErr::CorruptedBitstream {
+                    bit_offset: read_bits as u64,
+                })
+            }
+        }
+    }
+    Ok(decoded_buffer)
+}
+
+fn symbol_entry_to_encoded(entry: &SymbolEntry) -> Result<(u8, Encoded), HuffErr> {
+    let value = u8::try_from(entry.symbol)
+        .map_err(|_| HuffErr::MalformedData(format!("symbol {} out of range", entry.symbol)))?;
+    let num_bits_sequence = u16::try_from(entry.code_length)
+        .ok()
+        .filter(|code_length| *code_length > 0)
+        .ok_or(HuffErr::MalformedData(format!(
+            "invalid code length {} for byte {:#04x}",
+            entry.code_length, value
+        )))?;
+    if num_bits_sequence < 64 && entry.codeword >> num_bits_sequence != 0 {
+        return Err(HuffErr::MalformedData(format!(
+            "codeword of byte {:#04x} is longer than its code",
+            value
+        )));
+    }
+
+    let mut bits: BitVec<u8, Msb0> = BitVec::repeat(false, num_bits_sequence as usize);
+    (0..num_bits_sequence.min(64) as usize).for_each(|idx| {
+        bits.set(
+            num_bits_sequence as usize - 1 - idx,
+            entry.codeword >> idx & 1 == 1,
+        )
+    });
+    Ok((
+        value,
+        Encoded {
+            bits,
+            num_bits_sequence,
+            value,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        build_huffman_array, encode_huffman_array, huff_encode_bitvec, level::decompress_level,
+        tally_frequency,
+    };
+
+    fn encode(bytes: &[u8]) -> Vec<u8> {
+        let encoded_map = encode_huffman_array(&build_huffman_array(&tally_frequency(bytes)));
+        let (bit_buffer, total_bits) = huff_encode_bitvec(bytes, &encoded_map).unwrap();
+        serialize_huffman_proto(&encoded_map, bit_buffer, total_bits).unwrap()
+    }
+
+    #[test]
+    fn proto_round_trip() {
+        let bytes = b"the quick brown fox jumps over the lazy dog";
+        let serialized = encode(bytes);
+        assert_eq!(serialized[crate::HUFF_HEADER_LEN], FORMAT_HUFFMAN_PROTO);
+        assert_eq!(deserialize_huffman_proto(&serialized).unwrap(), bytes);
+        assert_eq!(decompress_level(&serialized).unwrap(), bytes);
+    }
+
+    #[test]
+    fn proto_header_decodes_with_schema() {
+        let serialized = encode(b"aaab");
+        let frame = &serialized[crate::HUFF_HEADER_LEN..];
+        let header_len = u8_to_u64(&frame[1..9]) as usize;
+        let header = HuffmanHeader::decode(&frame[9..9 + header_len]).unwrap();
+
+        assert_eq!(header.total_bits, 4);
+        assert_eq!(
+            header.entries,
+            vec![
+                SymbolEntry {
+                    symbol: b'a' as u32,
+                    code_length: 1,
+                    codeword: 1,
+                },
+                SymbolEntry {
+                    symbol: b'b' as u32,
+                    code_length: 1,
+                    codeword: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn proto_rejects_malformed_buffers() {
+        let serialized = encode(b"abracadabra");
+        assert!(deserialize_huffman_proto(&serialized[..5]).is_err());
+        assert!(matches!(
+            deserialize_huffman_proto(&serialized[..serialized.len() - 1]),
+            Err(HuffErr::ChecksumMismatch { .. })
+        ));
+        assert!(matches!(
+            deserialize_huffman_proto(&crate::compress(b"abracadabra").unwrap()),
+            Err(HuffErr::MalformedData(_))
+        ));
+
+        let frame = &serialized[crate::HUFF_HEADER_LEN..];
+        assert!(decode_proto_frame(&frame[..frame.len() - 1]).is_err());
+        let mut oversized_header = frame.to_vec();
+        oversized_header[1..9].copy_from_slice(&u64_to_u8(u64::MAX));
+        assert!(decode_proto_frame(&oversized_header).is_err());
+
+        let header = HuffmanHeader {
+            total_bits: 1,
+            entries: vec![SymbolEntry {
+                symbol: 300,
+                code_length: 1,
+                codeword: 1,
+            }],
+        }
+        .encode_to_vec();
+        let mut bad_symbol = vec![FORMAT_HUFFMAN_PROTO];
+        bad_symbol.extend_from_slice(&u64_to_u8(header.len() as u64));
+        bad_symbol.extend(header);
+        bad_symbol.push(0x80);
+        assert!(matches!(
+            deserialize_huffman_proto(&wrap_huff_payload(&bad_symbol)),
+            Err(HuffErr::MalformedData(_))
+        ));
+    }
+}
@@ -0,0 +1,274 @@
+//! Huffc SIMD - Vectorized Bit Packing for Short Codes
+//!
+//! When every code is at most 8 bits, eight codes always fit in a 64 bit word, so the encoder
+//! can pack a word at a time instead of appending code by code. `huff_encode_bitvec` uses
+//! `huff_encode_packed` automatically when the codes allow it.
+//!
+//! On x86-64 CPUs with AVX2 and BMI2, `huff_encode_avx2` looks up the codes of 32 bytes at once
+//! with `VPSHUFB`. The 256 entry tables are too large for a single 16 entry shuffle, so each
+//! table is split in 16 rows selected by the high nibble of the byte. The codes are then packed
+//! with `PEXT`, the gather counterpart of `PDEP`, which drops the unused high bits of each code
+//! byte. Other CPUs and targets use a scalar loop producing the same output.
+//!
+use std::collections::HashMap;
+
+use crate::Encoded;
+
+/// Builds the code and length tables for `huff_encode_packed`, `None` if a code is empty or
+/// longer than 8 bits. Symbols without a code have a length of 0.
+pub fn short_code_tables(encoded_map: &HashMap<u8, Encoded>) -> Option<([u8; 256], [u8; 256])> {
+    let mut table = [0; 256];
+    let mut lengths = [0; 256];
+    for encoded in encoded_map.values() {
+        if encoded.bits.is_empty() || encoded.bits.len() > 8 {
+            return None;
+        }
+        table[encoded.value as usize] = encoded
+            .bits
+            .iter()
+            .by_vals()
+            .fold(0, |code, bit| code << 1 | bit as u8);
+        lengths[encoded.value as usize] = encoded.bits.len() as u8;
+    }
+    Some((table, lengths))
+}
+
+/// Encodes `bytes` with codes of at most 8 bits, `table[byte]` holds the code of `byte` in its
+/// low `lengths[byte]` bits. The output is identical to `huff_encode_bitvec`, the last byte is
+/// padded with zero bits. Uses `huff_encode_avx2` when the CPU supports it.
+///
+/// # Panics
+///
+/// Panics if a byte of the input has a length of 0.
+pub fn huff_encode_packed(bytes: &[u8], table: &[u8; 256], lengths: &[u8; 256]) -> (Vec<u8>, u64) {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("bmi2") {
+        // SAFETY: the CPU supports AVX2 and BMI2
+        return unsafe { huff_encode_avx2(bytes, table, lengths) };
+    }
+
+    huff_encode_scalar(bytes, table, lengths)
+}
+
+/// The portable version of `huff_encode_packed`.
+fn huff_encode_scalar(bytes: &[u8], table: &[u8; 256], lengths: &[u8; 256]) -> (Vec<u8>, u64) {
+    let mut sink = BitSink::with_capacity(bytes.len());
+    for chunk in bytes.chunks(8) {
+        let mut word: u64 = 0;
+        let mut word_bits: u32 = 0;
+        for byte in chunk {
+            let len = lengths[*byte as usize] as u32;
+            assert!(len != 0, "no code for byte {:#04x}", byte);
+            word = word << len | table[*byte as usize] as u64;
+            word_bits += len;
+        }
+        sink.push(word, word_bits);
+    }
+    sink.finish()
+}
+
+/// `huff_encode_packed` with AVX2 lookups and BMI2 packing, 32 bytes per iteration. The tail
+/// shorter than 32 bytes is packed like `huff_encode_scalar`.
+///
+/// # Safety
+///
+/// The CPU must support AVX2 and BMI2, check with `is_x86_feature_detected!`.
+///
+/// # Panics
+///
+/// Panics if a byte of the input has a length of 0.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,bmi2")]
+pub unsafe fn huff_encode_avx2(
+    bytes: &[u8],
+    table: &[u8; 256],
+    lengths: &[u8; 256],
+) -> (Vec<u8>, u64) {
+    use std::arch::x86_64::*;
+
+    // Low `len` bits set for each length, lengths above 8 never occur
+    const MASKS: [u8; 16] = [0, 1, 3, 7, 15, 31, 63, 127, 255, 0, 0, 0, 0, 0, 0, 0];
+
+    let code_rows = nibble_rows(table);
+    let length_rows = nibble_rows(lengths);
+    let mask_row = _mm256_broadcastsi128_si256(_mm_loadu_si128(MASKS.as_ptr().cast()));
+
+    let mut sink = BitSink::with_capacity(bytes.len());
+    let mut chunks = bytes.chunks_exact(32);
+    for chunk in &mut chunks {
+        let input = _mm256_loadu_si256(chunk.as_ptr().cast());
+        let codes = lookup_256(&code_rows, input);
+        let byte_lengths = lookup_256(&length_rows, input);
+
+        let missing = _mm256_movemask_epi8(_mm256_cmpeq_epi8(byte_lengths, _mm256_setzero_si256()));
+        if missing != 0 {
+            let byte = chunk[missing.trailing_zeros() as usize];
+            panic!("no code for byte {:#04x}", byte);
+        }
+        let masks = _mm256_shuffle_epi8(mask_row, byte_lengths);
+
+        let mut code_bytes = [0u8; 32];
+        let mut mask_bytes = [0u8; 32];
+        _mm256_storeu_si256(code_bytes.as_mut_ptr().cast(), codes);
+        _mm256_storeu_si256(mask_bytes.as_mut_ptr().cast(), masks);
+
+        // Big endian puts the first code in the top byte, so it ends up first in the stream
+        for (code, mask) in code_bytes.chunks_exact(8).zip(mask_bytes.chunks_exact(8)) {
+            let code = u64::from_be_bytes(code.try_into().unwrap());
+            let mask = u64::from_be_bytes(mask.try_into().unwrap());
+            sink.push(_pext_u64(code, mask), mask.count_ones());
+        }
+    }
+
+    let (tail_buffer, tail_bits) = huff_encode_scalar(chunks.remainder(), table, lengths);
+    sink.push_bytes(&tail_buffer, tail_bits);
+    sink.finish()
+}
+
+/// Splits a 256 entry table in 16 rows of 16 entries, each copied to both 128 bit lanes.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+fn nibble_rows(table: &[u8; 256]) -> [std::arch::x86_64::__m256i; 16] {
+    use std::arch::x86_64::*;
+
+    std::array::from_fn(|row| {
+        let row: &[u8; 16] = table[row * 16..row * 16 + 16].try_into().unwrap();
+        // SAFETY: `row` is 16 readable bytes
+        _mm256_broadcastsi128_si256(unsafe { _mm_loadu_si128(row.as_ptr().cast()) })
+    })
+}
+
+/// Looks up each of the 32 bytes of `input` in the table split by `nibble_rows`: a `VPSHUFB` of
+/// every row by the low nibble, kept where the high nibble selects that row.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+fn lookup_256(
+    rows: &[std::arch::x86_64::__m256i; 16],
+    input: std::arch::x86_64::__m256i,
+) -> std::arch::x86_64::__m256i {
+    use std::arch::x86_64::*;
+
+    let nibble = _mm256_set1_epi8(0x0f);
+    let low = _mm256_and_si256(input, nibble);
+    let high = _mm256_and_si256(_mm256_srli_epi16(input, 4), nibble);
+    rows.iter()
+        .enumerate()
+        .fold(_mm256_setzero_si256(), |out, (row, entries)| {
+            let selected = _mm256_cmpeq_epi8(high, _mm256_set1_epi8(row as i8));
+            let found = _mm256_shuffle_epi8(*entries, low);
+            _mm256_or_si256(out, _mm256_and_si256(selected, found))
+        })
+}
+
+/// Appends right aligned words of up to 64 bits to a byte buffer.
+struct BitSink {
+    out: Vec<u8>,
+    /// Bits not yet written, right aligned, fewer than 8 between words
+    acc: u128,
+    acc_bits: u32,
+    total_bits: u64,
+}
+
+impl BitSink {
+    fn with_capacity(input_len: usize) -> Self {
+        BitSink {
+            out: Vec::with_capacity(input_len + 16),
+            acc: 0,
+            acc_bits: 0,
+            total_bits: 0,
+        }
+    }
+
+    /// Appends the low `bits` bits of `word`.
+    fn push(&mut self, word: u64, bits: u32) {
+        self.acc = self.acc << bits | word as u128;
+        self.acc_bits += bits;
+        self.total_bits += bits as u64;
+
+        let full_bytes = self.acc_bits / 8;
+        let remaining = self.acc_bits % 8;
+        let full = (self.acc >> remaining).to_be_bytes();
+        self.out
+            .extend_from_slice(&full[16 - full_bytes as usize..]);
+        self.acc &= (1 << remaining) - 1;
+        self.acc_bits = remaining;
+    }
+
+    /// Appends the first `bits` bits of a buffer returned by `finish`.
+    #[cfg(target_arch = "x86_64")]
+    fn push_bytes(&mut self, buffer: &[u8], bits: u64) {
+        for (idx, byte) in buffer.iter().enumerate() {
+            let bits = (bits - idx as u64 * 8).min(8) as u32;
+            self.push((*byte >> (8 - bits)) as u64, bits);
+        }
+    }
+
+    fn finish(mut self) -> (Vec<u8>, u64) {
+        if self.acc_bits > 0 {
+            self.out.push((self.acc << (8 - self.acc_bits)) as u8);
+        }
+        (self.out, self.total_bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{build_huffman_array, encode_huffman_array, huff_encode_bitvec, tally_frequency};
+
+    #[test]
+    fn packing_matches_bit_by_bit_encoding() {
+        let original = std::fs::read("./tests/resources/dna_seq_test.txt").unwrap();
+        for len in [1, 7, 8, 9, 100, original.len()] {
+            let bytes = &original[..len];
+            let encoded_map =
+                encode_huffman_array(&build_huffman_array(&tally_frequency(&original)));
+            let (table, lengths) = short_code_tables(&encoded_map).unwrap();
+
+            let (fast_buffer, fast_bits) = huff_encode_packed(bytes, &table, &lengths);
+            let mut expected = bitvec::vec::BitVec::<u8, bitvec::order::Msb0>::new();
+            bytes
+                .iter()
+                .for_each(|byte| expected.extend_from_bitslice(&encoded_map[byte].bits));
+            expected.set_uninitialized(false);
+
+            assert_eq!(fast_bits, expected.len() as u64);
+            assert_eq!(fast_buffer, expected.into_vec());
+            assert_eq!(
+                huff_encode_bitvec(bytes, &encoded_map).unwrap().1,
+                fast_bits
+            );
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn avx2_matches_scalar_packing() {
+        if !(is_x86_feature_detected!("avx2") && is_x86_feature_detected!("bmi2")) {
+            return;
+        }
+        // 64 symbols with codes of 1 to 8 bits
+        let mut freq_buffer = crate::FrequencyBuffer([0; 256]);
+        for (idx, count) in freq_buffer.0.iter_mut().enumerate().take(64) {
+            *count = 1 << 7_usize.saturating_sub(idx);
+        }
+        let encoded_map = crate::canonical_huffman_codes(&freq_buffer).unwrap();
+        let (table, lengths) = short_code_tables(&encoded_map).unwrap();
+        assert_eq!((lengths[0], lengths[63]), (1, 8));
+
+        let bytes: Vec<u8> = (0..1000u32).map(|idx| (idx * 37 % 64) as u8).collect();
+        for len in [0, 1, 31, 32, 33, 64, 95, bytes.len()] {
+            let bytes = &bytes[..len];
+            // SAFETY: checked above
+            let avx2 = unsafe { huff_encode_avx2(bytes, &table, &lengths) };
+            assert_eq!(avx2, huff_encode_scalar(bytes, &table, &lengths));
+        }
+    }
+
+    #[test]
+    fn long_codes_have_no_short_tables() {
+        let encoded_map = encode_huffman_array(b"abcdefghij");
+        assert!(short_code_tables(&encoded_map).is_none());
+        assert!(short_code_tables(&encode_huffman_array(b"abcdefghi")).is_some());
+    }
+}
@@ -0,0 +1,105 @@
+//! Huffc Timing - Per-Stage Wall Clock Times
+//!
+//! `StageTimer` records how long each stage of a pipeline took, `CompressOptions::encode_timed`
+//! laps it after every compression stage and `huffc --profile` prints its report.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use huffc::{timing::StageTimer, CompressOptions};
+//!
+//! let mut timer = StageTimer::new();
+//! CompressOptions::new()
+//!     .encode_timed(b"abracadabra", &mut timer)
+//!     .unwrap();
+//! print!("{}", timer.report());
+//! ```
+//!
+use std::time::{Duration, Instant};
+
+/// Wall clock time of each pipeline stage, printed by `--profile`.
+#[derive(Debug)]
+pub struct StageTimer {
+    stages: Vec<(&'static str, Duration)>,
+    lap_start: Instant,
+}
+
+impl Default for StageTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StageTimer {
+    /// Starts timing the first stage.
+    pub fn new() -> Self {
+        Self {
+            stages: Vec::new(),
+            lap_start: Instant::now(),
+        }
+    }
+
+    /// Records the time since the previous lap, or since creation, as `stage`.
+    pub fn lap(&mut self, stage: &'static str) {
+        let now = Instant::now();
+        self.stages.push((stage, now - self.lap_start));
+        self.lap_start = now;
+    }
+
+    /// The recorded stages in order.
+    pub fn stages(&self) -> &[(&'static str, Duration)] {
+        &self.stages
+    }
+
+    /// Formats the stages as a `Stage | Time (ms) | % of total` table.
+    pub fn report(&self) -> String {
+        let total: Duration = self.stages.iter().map(|(_, elapsed)| *elapsed).sum();
+        let mut report = format!(
+            "{:<20} | {:>10} | {:>10}\n",
+            "Stage", "Time (ms)", "% of total"
+        );
+        for (stage, elapsed) in &self.stages {
+            let share = if total.is_zero() {
+                0.0
+            } else {
+                100.0 * elapsed.as_secs_f64() / total.as_secs_f64()
+            };
+            report.push_str(&format!(
+                "{:<20} | {:>10.3} | {:>10.1}\n",
+                stage,
+                elapsed.as_secs_f64() * 1000.0,
+                share
+            ));
+        }
+        report.push_str(&format!(
+            "{:<20} | {:>10.3} | {:>10.1}\n",
+            "total",
+            total.as_secs_f64() * 1000.0,
+            100.0
+        ));
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stage_timer_report_lists_stages_in_order() {
+        let mut timer = StageTimer::new();
+        timer.lap("file read");
+        timer.lap("tally_frequency");
+
+        let stages: Vec<&str> = timer.stages().iter().map(|(stage, _)| *stage).collect();
+        assert_eq!(stages, ["file read", "tally_frequency"]);
+
+        let report = timer.report();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("Stage"));
+        assert!(lines[1].starts_with("file read"));
+        assert!(lines[2].starts_with("tally_frequency"));
+        assert!(lines[3].starts_with("total"));
+    }
+}
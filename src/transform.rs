@@ -0,0 +1,233 @@
+//! Huffc Transforms - Reversible Pre-processing Transforms
+//!
+//! Transforms that reshape the input so the Huffman coder sees a more skewed frequency
+//! distribution. Every transform has an exact inverse.
+//!
+//! ## Features
+//! - Run-length encoding for inputs with long runs of the same byte
+//! - Burrows-Wheeler transform to group bytes with similar context
+//! - Move-to-front encoding to turn grouped bytes into runs of small values
+//! - Delta encoding for slowly changing values such as samples or counters
+//!
+
+/// Run-length encodes `bytes`, two equal bytes in a row are followed by a count of further
+/// repeats (0..=255), so input without runs only grows where a byte is repeated exactly twice.
+pub(crate) fn rle_encode(bytes: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let byte = bytes[idx];
+        let mut run = 1;
+        while idx + run < bytes.len() && bytes[idx + run] == byte && run < 257 {
+            run += 1;
+        }
+
+        if run == 1 {
+            encoded.push(byte);
+        } else {
+            encoded.push(byte);
+            encoded.push(byte);
+            encoded.push((run - 2) as u8);
+        }
+        idx += run;
+    }
+    encoded
+}
+
+/// Inverse of `rle_encode`, returns `None` if a run is missing its count byte.
+pub(crate) fn rle_decode(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let byte = bytes[idx];
+        if bytes.get(idx + 1) == Some(&byte) {
+            let extra = *bytes.get(idx + 2)? as usize;
+            decoded.extend(std::iter::repeat_n(byte, extra + 2));
+            idx += 3;
+        } else {
+            decoded.push(byte);
+            idx += 1;
+        }
+    }
+    Some(decoded)
+}
+
+/// Move-to-front encodes `bytes`, recently seen bytes are mapped to small values.
+pub(crate) fn mtf_encode(bytes: &[u8]) -> Vec<u8> {
+    let mut order: Vec<u8> = (0..=255).collect();
+    bytes
+        .iter()
+        .map(|byte| {
+            let position = order.iter().position(|b| b == byte).unwrap();
+            order.remove(position);
+            order.insert(0, *byte);
+            position as u8
+        })
+        .collect()
+}
+
+/// Inverse of `mtf_encode`.
+pub(crate) fn mtf_decode(bytes: &[u8]) -> Vec<u8> {
+    let mut order: Vec<u8> = (0..=255).collect();
+    bytes
+        .iter()
+        .map(|position| {
+            let byte = order.remove(*position as usize);
+            order.insert(0, byte);
+            byte
+        })
+        .collect()
+}
+
+/// Delta encodes `bytes`, every byte is replaced by its wrapping difference to the previous one.
+pub(crate) fn delta_encode(bytes: &[u8]) -> Vec<u8> {
+    let mut previous = 0u8;
+    bytes
+        .iter()
+        .map(|byte| {
+            let delta = byte.wrapping_sub(previous);
+            previous = *byte;
+            delta
+        })
+        .collect()
+}
+
+/// Inverse of `delta_encode`.
+pub(crate) fn delta_decode(bytes: &[u8]) -> Vec<u8> {
+    let mut previous = 0u8;
+    bytes
+        .iter()
+        .map(|delta| {
+            previous = previous.wrapping_add(*delta);
+            previous
+        })
+        .collect()
+}
+
+/// Burrows-Wheeler transform of `bytes`.
+///
+/// Returns the last column of the sorted rotations and the row holding the original input.
+/// Rotations are sorted by prefix doubling, O(n log^2 n), so repetitive input does not degrade
+/// into quadratic string comparisons.
+pub(crate) fn bwt_encode(bytes: &[u8]) -> (Vec<u8>, usize) {
+    let n = bytes.len();
+    if n == 0 {
+        return (Vec::new(), 0);
+    }
+
+    let mut rows: Vec<usize> = (0..n).collect();
+    let mut rank: Vec<usize> = bytes.iter().map(|b| *b as usize).collect();
+    let mut next_rank = vec![0; n];
+    let mut k = 1;
+    loop {
+        let key = |i: usize| (rank[i], rank[(i + k) % n]);
+        rows.sort_unstable_by_key(|i| key(*i));
+
+        next_rank[rows[0]] = 0;
+        for w in 1..n {
+            let bump = (key(rows[w - 1]) != key(rows[w])) as usize;
+            next_rank[rows[w]] = next_rank[rows[w - 1]] + bump;
+        }
+        std::mem::swap(&mut rank, &mut next_rank);
+
+        // Either every rotation is distinct or the rotations have been compared in full
+        if rank[rows[n - 1]] == n - 1 || k >= n {
+            break;
+        }
+        k *= 2;
+    }
+
+    let last_column = rows.iter().map(|i| bytes[(i + n - 1) % n]).collect();
+    let primary = rows.iter().position(|i| *i == 0).unwrap();
+    (last_column, primary)
+}
+
+/// Inverse of `bwt_encode`, returns `None` if `primary` is out of range.
+pub(crate) fn bwt_decode(last_column: &[u8], primary: usize) -> Option<Vec<u8>> {
+    let n = last_column.len();
+    if n == 0 {
+        return Some(Vec::new());
+    }
+    if primary >= n {
+        return None;
+    }
+
+    let mut starts = [0usize; 256];
+    for byte in last_column {
+        starts[*byte as usize] += 1;
+    }
+    let mut sum = 0;
+    for start in starts.iter_mut() {
+        let count = *start;
+        *start = sum;
+        sum += count;
+    }
+
+    let mut seen = [0usize; 256];
+    let lf: Vec<usize> = last_column
+        .iter()
+        .map(|byte| {
+            let row = starts[*byte as usize] + seen[*byte as usize];
+            seen[*byte as usize] += 1;
+            row
+        })
+        .collect();
+
+    let mut decoded = vec![0; n];
+    let mut row = primary;
+    for slot in decoded.iter_mut().rev() {
+        *slot = last_column[row];
+        row = lf[row];
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delta_round_trip() {
+        let bytes = [10, 11, 12, 12, 250, 3, 0, 255];
+        let encoded = delta_encode(&bytes);
+        assert_eq!(encoded, [10, 1, 1, 0, 238, 9, 253, 255]);
+        assert_eq!(delta_decode(&encoded), bytes);
+        assert!(delta_encode(&[]).is_empty());
+    }
+
+    #[test]
+    fn rle_round_trip() {
+        let bytes = [vec![7; 600], vec![1, 2, 2, 3, 3, 3], vec![9; 257]].concat();
+        let encoded = rle_encode(&bytes);
+
+        assert!(encoded.len() < 20);
+        assert_eq!(rle_decode(&encoded).unwrap(), bytes);
+        assert!(rle_decode(&[4, 4]).is_none());
+    }
+
+    #[test]
+    fn mtf_round_trip() {
+        let bytes = b"bananaaa";
+        let encoded = mtf_encode(bytes);
+
+        assert_eq!(&encoded[6..], &[0, 0]);
+        assert_eq!(mtf_decode(&encoded), bytes);
+    }
+
+    #[test]
+    fn bwt_known_value() {
+        let (last_column, primary) = bwt_encode(b"banana");
+
+        assert_eq!(last_column, b"nnbaaa");
+        assert_eq!(primary, 3);
+        assert_eq!(bwt_decode(&last_column, primary).unwrap(), b"banana");
+    }
+
+    #[test]
+    fn bwt_round_trip_periodic_input() {
+        for bytes in [&b"abababab"[..], b"aaaa", b"a", b""] {
+            let (last_column, primary) = bwt_encode(bytes);
+            assert_eq!(bwt_decode(&last_column, primary).unwrap(), bytes);
+        }
+    }
+}
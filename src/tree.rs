@@ -0,0 +1,856 @@
+//! Huffc Tree - Binary Huffman Tree
+//!
+//! This module builds a proper binary Huffman tree from a `FrequencyBuffer` by repeatedly
+//! merging the two least frequent nodes.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! use huffc::{build_huffman_tree, tally_frequency};
+//!
+//! let tree = build_huffman_tree(&tally_frequency(b"abracadabra")).unwrap();
+//! assert_eq!(tree.freq(), 11);
+//! assert_eq!(tree.symbol_count(), 5);
+//! ```
+//!
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, VecDeque},
+};
+
+use bitvec::{order::Msb0, slice::BitSlice, vec::BitVec, view::BitView};
+
+use crate::{Encoded, FrequencyBuffer, HuffErr};
+
+/// Node of a Huffman tree, the left child is reached with a 0 bit and the right child with a 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HuffNode {
+    Leaf {
+        symbol: u8,
+        freq: u64,
+    },
+    Internal {
+        freq: u64,
+        left: Box<HuffNode>,
+        right: Box<HuffNode>,
+    },
+}
+
+impl HuffNode {
+    /// Combined frequency of every symbol below this node.
+    pub fn freq(&self) -> u64 {
+        match self {
+            HuffNode::Leaf { freq, .. } | HuffNode::Internal { freq, .. } => *freq,
+        }
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        matches!(self, HuffNode::Leaf { .. })
+    }
+
+    /// Height of the tree, which is the maximum code length. A single leaf has height 0.
+    pub fn height(&self) -> usize {
+        match self {
+            HuffNode::Leaf { .. } => 0,
+            HuffNode::Internal { left, right, .. } => 1 + left.height().max(right.height()),
+        }
+    }
+
+    /// Joins `a` (left, 0 bit) and `b` (right, 1 bit) under a new internal node whose frequency
+    /// is the sum of theirs, saturating at `u64::MAX`. This is the step the Huffman algorithm
+    /// repeats on the two least frequent nodes.
+    pub fn merge(a: HuffNode, b: HuffNode) -> HuffNode {
+        HuffNode::Internal {
+            freq: a.freq().saturating_add(b.freq()),
+            left: Box::new(a),
+            right: Box::new(b),
+        }
+    }
+
+    /// Number of distinct symbols, i.e. leaves, in the tree.
+    pub fn symbol_count(&self) -> usize {
+        match self {
+            HuffNode::Leaf { .. } => 1,
+            HuffNode::Internal { left, right, .. } => left.symbol_count() + right.symbol_count(),
+        }
+    }
+}
+
+impl HuffNode {
+    /// Serializes the tree structure in pre-order: a 0 bit for an internal node, a 1 bit
+    /// followed by the 8 bits of the symbol for a leaf. A tree of K leaves takes `10K - 1` bits,
+    /// padded with zero bits to whole bytes. Frequencies are not stored.
+    pub fn serialize_tree(&self) -> Vec<u8> {
+        let mut bits: BitVec<u8, Msb0> = BitVec::new();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            match node {
+                HuffNode::Leaf { symbol, .. } => {
+                    bits.push(true);
+                    bits.extend_from_bitslice(symbol.view_bits::<Msb0>());
+                }
+                HuffNode::Internal { left, right, .. } => {
+                    bits.push(false);
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+        bits.into_vec()
+    }
+
+    /// Rebuilds a tree serialized with `serialize_tree`, bits after the tree are ignored. Every
+    /// frequency of the rebuilt tree is 0.
+    ///
+    /// # Errors
+    ///
+    /// Returns `HuffErr::MalformedData` if the bits end inside the tree or a symbol appears
+    /// more than once.
+    pub fn deserialize_tree(bits: &BitSlice<u8, Msb0>) -> Result<HuffNode, HuffErr> {
+        let truncated = || HuffErr::MalformedData("tree ends early".to_string());
+        let mut seen = [false; 256];
+        // Internal nodes waiting for their children, holding the left child once it is complete
+        let mut pending: Vec<Option<HuffNode>> = Vec::new();
+        let mut idx = 0;
+        loop {
+            let is_leaf = *bits.get(idx).ok_or_else(truncated)?;
+            idx += 1;
+            if !is_leaf {
+                pending.push(None);
+                continue;
+            }
+
+            let symbol_bits = bits.get(idx..idx + 8).ok_or_else(truncated)?;
+            idx += 8;
+            let symbol = symbol_bits
+                .iter()
+                .by_vals()
+                .fold(0u8, |symbol, bit| symbol << 1 | bit as u8);
+            if std::mem::replace(&mut seen[symbol as usize], true) {
+                return Err(HuffErr::MalformedData(format!(
+                    "duplicate symbol {:#04x} in tree",
+                    symbol
+                )));
+            }
+
+            let mut node = HuffNode::Leaf { symbol, freq: 0 };
+            loop {
+                match pending.last_mut() {
+                    None => return Ok(node),
+                    Some(left @ None) => {
+                        *left = Some(node);
+                        break;
+                    }
+                    Some(Some(_)) => {
+                        let left = pending.pop().unwrap().unwrap();
+                        node = HuffNode::merge(left, node);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Outcome of `HuffNode::rebalance`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RebalanceStats {
+    /// Adjacent swaps needed to order the nodes of the original tree, read top to bottom and right
+    /// to left, by non-increasing frequency. Zero means the sibling property already held.
+    pub swaps: usize,
+    /// Longest code length before rebalancing.
+    pub max_length_before: u8,
+    /// Longest code length after rebalancing.
+    pub max_length_after: u8,
+}
+
+impl HuffNode {
+    /// Leaves of the tree as `(symbol, freq)` pairs, left to right.
+    pub fn leaves(&self) -> Vec<(u8, u64)> {
+        let mut leaves = Vec::new();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            match node {
+                HuffNode::Leaf { symbol, freq } => leaves.push((*symbol, *freq)),
+                HuffNode::Internal { left, right, .. } => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+        leaves
+    }
+
+    /// Depth of every leaf, i.e. its code length, as `(symbol, depth)` pairs, left to right.
+    pub fn leaf_depths(&self) -> Vec<(u8, usize)> {
+        let mut depths = Vec::new();
+        let mut stack = vec![(self, 0)];
+        while let Some((node, depth)) = stack.pop() {
+            match node {
+                HuffNode::Leaf { symbol, .. } => depths.push((*symbol, depth)),
+                HuffNode::Internal { left, right, .. } => {
+                    stack.push((right, depth + 1));
+                    stack.push((left, depth + 1));
+                }
+            }
+        }
+        depths
+    }
+
+    /// Nodes in preorder: the node, its left subtree, then its right subtree. Uses an explicit
+    /// stack, so deep trees cannot overflow the call stack.
+    pub fn preorder_traversal(&self) -> impl Iterator<Item = &HuffNode> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            if let HuffNode::Internal { left, right, .. } = node {
+                stack.push(right);
+                stack.push(left);
+            }
+            Some(node)
+        })
+    }
+
+    /// Nodes in inorder: the left subtree, the node, then the right subtree.
+    pub fn inorder_traversal(&self) -> impl Iterator<Item = &HuffNode> {
+        let mut stack = Vec::new();
+        let mut next = Some(self);
+        std::iter::from_fn(move || {
+            while let Some(node) = next {
+                stack.push(node);
+                next = match node {
+                    HuffNode::Internal { left, .. } => Some(left),
+                    HuffNode::Leaf { .. } => None,
+                };
+            }
+            let node = stack.pop()?;
+            if let HuffNode::Internal { right, .. } = node {
+                next = Some(right);
+            }
+            Some(node)
+        })
+    }
+
+    /// Nodes in postorder: the left subtree, the right subtree, then the node.
+    pub fn postorder_traversal(&self) -> impl Iterator<Item = &HuffNode> {
+        // A node is yielded once its children have been pushed above it
+        let mut stack = vec![(self, false)];
+        std::iter::from_fn(move || loop {
+            let (node, children_visited) = stack.pop()?;
+            match node {
+                HuffNode::Internal { left, right, .. } if !children_visited => {
+                    stack.push((node, true));
+                    stack.push((right, false));
+                    stack.push((left, false));
+                }
+                _ => return Some(node),
+            }
+        })
+    }
+
+    /// Symbols ordered by code length, shortest first, ties by ascending symbol, for use with
+    /// `encode_huffman_array`. The unary codes it assigns only keep the order of the tree's code
+    /// lengths, not the lengths themselves.
+    pub fn to_huffman_array(&self) -> Vec<u8> {
+        let mut depths = self.leaf_depths();
+        depths.sort_unstable_by_key(|(symbol, depth)| (*depth, *symbol));
+        depths.into_iter().map(|(symbol, _)| symbol).collect()
+    }
+
+    /// Rebuilds the tree structure from the leaf frequencies, repairing a manually built tree
+    /// that violates the sibling property. The symbols and their frequencies are kept, only their
+    /// position in the tree, and so their codes, change. Internal frequencies are recomputed from
+    /// the leaves.
+    pub fn rebalance(&mut self) -> RebalanceStats {
+        let swaps = count_inversions(&self.breadth_first_freqs());
+        let max_length_before = self.height().min(u8::MAX as usize) as u8;
+
+        let leaves = self
+            .leaves()
+            .into_iter()
+            .map(|(symbol, freq)| HuffNode::Leaf { symbol, freq })
+            .collect();
+        *self = build_from_nodes(leaves).expect("a tree has at least one leaf");
+
+        RebalanceStats {
+            swaps,
+            max_length_before,
+            max_length_after: self.height().min(u8::MAX as usize) as u8,
+        }
+    }
+
+    fn breadth_first_freqs(&self) -> Vec<u64> {
+        let mut freqs = Vec::new();
+        let mut queue = VecDeque::from([self]);
+        while let Some(node) = queue.pop_front() {
+            freqs.push(node.freq());
+            if let HuffNode::Internal { left, right, .. } = node {
+                queue.push_back(right);
+                queue.push_back(left);
+            }
+        }
+        freqs
+    }
+}
+
+/// Number of pairs `i < j` with `freqs[i] < freqs[j]`.
+fn count_inversions(freqs: &[u64]) -> usize {
+    freqs
+        .iter()
+        .enumerate()
+        .map(|(i, freq)| freqs[i + 1..].iter().filter(|later| *later > freq).count())
+        .sum()
+}
+
+/// Builds a Huffman tree from the non-zero entries of `freq_buffer` using a min-heap.
+///
+/// Ties are broken by creation order, leaves first in ascending symbol order, so the same
+/// frequencies always produce the same tree. Returns `None` if every count is zero.
+pub fn build_huffman_tree(freq_buffer: &FrequencyBuffer) -> Option<HuffNode> {
+    let leaves = freq_buffer
+        .0
+        .iter()
+        .enumerate()
+        .filter(|(_, freq)| **freq != 0)
+        .map(|(symbol, freq)| HuffNode::Leaf {
+            symbol: symbol as u8,
+            freq: *freq,
+        })
+        .collect();
+    build_from_nodes(leaves)
+}
+
+/// Builds a Huffman tree from the non-zero entries of `freq_buffer` with the two-queue
+/// algorithm: the leaves sorted by frequency form one queue and the merged nodes, which are
+/// created in non-decreasing frequency order, the other, so the minimum is always at the front
+/// of one of them. Building is O(n) once the leaves are sorted. On equal frequencies the leaf is
+/// taken first, which keeps the tree shallow. Returns `None` if every count is zero.
+pub fn build_huffman_tree_two_queue(freq_buffer: &FrequencyBuffer) -> Option<HuffNode> {
+    let mut leaves: Vec<(u64, u8)> = freq_buffer
+        .0
+        .iter()
+        .enumerate()
+        .filter(|(_, freq)| **freq != 0)
+        .map(|(symbol, freq)| (*freq, symbol as u8))
+        .collect();
+    leaves.sort_unstable();
+    let mut leaves: VecDeque<HuffNode> = leaves
+        .into_iter()
+        .map(|(freq, symbol)| HuffNode::Leaf { symbol, freq })
+        .collect();
+    let mut merged: VecDeque<HuffNode> = VecDeque::with_capacity(leaves.len());
+
+    while leaves.len() + merged.len() > 1 {
+        let left = pop_min(&mut leaves, &mut merged).unwrap();
+        let right = pop_min(&mut leaves, &mut merged).unwrap();
+        merged.push_back(HuffNode::merge(left, right));
+    }
+    leaves.pop_front().or_else(|| merged.pop_front())
+}
+
+/// Pops the less frequent front node of the two queues, the leaf on equal frequencies.
+fn pop_min(leaves: &mut VecDeque<HuffNode>, merged: &mut VecDeque<HuffNode>) -> Option<HuffNode> {
+    match (leaves.front(), merged.front()) {
+        (Some(leaf), Some(node)) if node.freq() < leaf.freq() => merged.pop_front(),
+        (Some(_), _) => leaves.pop_front(),
+        (None, _) => merged.pop_front(),
+    }
+}
+
+/// Repeatedly merges the two least frequent nodes, ties go to the node created first.
+fn build_from_nodes(nodes: Vec<HuffNode>) -> Option<HuffNode> {
+    let mut nodes: Vec<Option<HuffNode>> = nodes.into_iter().map(Some).collect();
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> = nodes
+        .iter()
+        .enumerate()
+        .map(|(idx, node)| Reverse((node.as_ref().unwrap().freq(), idx)))
+        .collect();
+
+    while heap.len() > 1 {
+        let Reverse((_, left)) = heap.pop().unwrap();
+        let Reverse((_, right)) = heap.pop().unwrap();
+        let left = nodes[left].take().unwrap();
+        let right = nodes[right].take().unwrap();
+
+        let merged = HuffNode::merge(left, right);
+        heap.push(Reverse((merged.freq(), nodes.len())));
+        nodes.push(Some(merged));
+    }
+
+    let Reverse((_, root)) = heap.pop()?;
+    nodes[root].take()
+}
+
+/// Assigns every leaf of `tree` its binary Huffman code, the path from the root with a 0 bit for
+/// every left and a 1 bit for every right branch. Unlike the unary codes of
+/// `encode_huffman_array` the code lengths are optimal for the frequencies of the tree. A tree of
+/// a single leaf gives its symbol the one bit code `0`, as an empty code cannot be decoded.
+#[must_use = "the encoded map must be passed to huff_encode_bitvec"]
+pub fn encode_huffman_tree(tree: &HuffNode) -> HashMap<u8, Encoded> {
+    let mut encoded_map = HashMap::new();
+    let mut stack: Vec<(&HuffNode, BitVec<u8, Msb0>)> = vec![(tree, BitVec::new())];
+    while let Some((node, code)) = stack.pop() {
+        match node {
+            HuffNode::Leaf { symbol, .. } => {
+                let bits = if code.is_empty() {
+                    BitVec::repeat(false, 1)
+                } else {
+                    code
+                };
+                let encoded = Encoded {
+                    num_bits_sequence: bits.len() as u16,
+                    bits,
+                    value: *symbol,
+                };
+                encoded_map.insert(*symbol, encoded);
+            }
+            HuffNode::Internal { left, right, .. } => {
+                let mut right_code = code.clone();
+                right_code.push(true);
+                stack.push((right, right_code));
+                let mut left_code = code;
+                left_code.push(false);
+                stack.push((left, left_code));
+            }
+        }
+    }
+    encoded_map
+}
+
+/// Node of a `HuffNodeArena`, children and parent are indices into the arena.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HuffNodeData {
+    pub freq: u64,
+    pub parent: Option<usize>,
+    pub left: Option<usize>,
+    pub right: Option<usize>,
+    /// The symbol of a leaf, `None` for internal nodes.
+    pub symbol: Option<u8>,
+}
+
+/// Huffman tree stored in a `Vec` with parent links, so a leaf can be walked up to the root,
+/// e.g. to propagate a count update in adaptive Huffman coding. Built by `build_huffman_arena`,
+/// the leaves come first in ascending symbol order and the root is the last node.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HuffNodeArena {
+    nodes: Vec<HuffNodeData>,
+}
+
+impl HuffNodeArena {
+    pub fn nodes(&self) -> &[HuffNodeData] {
+        &self.nodes
+    }
+
+    /// Index of the root, `None` for an empty arena.
+    pub fn root(&self) -> Option<usize> {
+        self.nodes.len().checked_sub(1)
+    }
+
+    /// Index of the leaf of `symbol`.
+    pub fn leaf(&self, symbol: u8) -> Option<usize> {
+        self.nodes
+            .iter()
+            .position(|node| node.symbol == Some(symbol))
+    }
+
+    /// Depth of the node at `idx`, found by following the parent links to the root. For a leaf
+    /// this is its code length.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `idx` is out of bounds.
+    pub fn depth(&self, idx: usize) -> usize {
+        let mut depth = 0;
+        let mut node = &self.nodes[idx];
+        while let Some(parent) = node.parent {
+            depth += 1;
+            node = &self.nodes[parent];
+        }
+        depth
+    }
+
+    /// Code length of every symbol as `(symbol, length)` pairs in ascending symbol order.
+    pub fn code_lengths(&self) -> Vec<(u8, usize)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, node)| node.symbol.map(|symbol| (symbol, self.depth(idx))))
+            .collect()
+    }
+
+    /// Adds `amount` to the frequency of the node at `idx` and of every node above it,
+    /// saturating at `u64::MAX`. The tree is not rebalanced.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `idx` is out of bounds.
+    pub fn increment(&mut self, idx: usize, amount: u64) {
+        let mut next = Some(idx);
+        while let Some(idx) = next {
+            let node = &mut self.nodes[idx];
+            node.freq = node.freq.saturating_add(amount);
+            next = node.parent;
+        }
+    }
+}
+
+/// Builds a `HuffNodeArena` from the non-zero entries of `freq_buffer` using a min-heap of
+/// arena indices. Ties are broken like `build_huffman_tree`, so both build the same tree. Every
+/// count being zero gives an empty arena.
+pub fn build_huffman_arena(freq_buffer: FrequencyBuffer) -> HuffNodeArena {
+    let mut nodes: Vec<HuffNodeData> = freq_buffer
+        .0
+        .iter()
+        .enumerate()
+        .filter(|(_, freq)| **freq != 0)
+        .map(|(symbol, freq)| HuffNodeData {
+            freq: *freq,
+            parent: None,
+            left: None,
+            right: None,
+            symbol: Some(symbol as u8),
+        })
+        .collect();
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> = nodes
+        .iter()
+        .enumerate()
+        .map(|(idx, node)| Reverse((node.freq, idx)))
+        .collect();
+
+    while heap.len() > 1 {
+        let Reverse((left_freq, left)) = heap.pop().unwrap();
+        let Reverse((right_freq, right)) = heap.pop().unwrap();
+        let parent = nodes.len();
+        nodes[left].parent = Some(parent);
+        nodes[right].parent = Some(parent);
+
+        let freq = left_freq.saturating_add(right_freq);
+        nodes.push(HuffNodeData {
+            freq,
+            parent: None,
+            left: Some(left),
+            right: Some(right),
+            symbol: None,
+        });
+        heap.push(Reverse((freq, parent)));
+    }
+    HuffNodeArena { nodes }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tally_frequency;
+
+    #[test]
+    fn small_tree_height_and_symbol_count() {
+        let bytes = [1, 2, 1, 1, 1, 1, 1, 1, 1, 3, 1];
+        let tree = build_huffman_tree(&tally_frequency(&bytes)).unwrap();
+
+        assert_eq!(tree.freq(), 11);
+        assert_eq!(tree.height(), 2);
+        assert_eq!(tree.symbol_count(), 3);
+    }
+
+    #[test]
+    fn single_leaf_tree() {
+        let tree = build_huffman_tree(&tally_frequency(&[42; 10])).unwrap();
+
+        assert_eq!(
+            tree,
+            HuffNode::Leaf {
+                symbol: 42,
+                freq: 10
+            }
+        );
+        assert_eq!(tree.height(), 0);
+        assert_eq!(tree.symbol_count(), 1);
+    }
+
+    fn leaf(symbol: u8, freq: u64) -> Box<HuffNode> {
+        Box::new(HuffNode::Leaf { symbol, freq })
+    }
+
+    #[test]
+    fn rebalance_degenerate_tree() {
+        // A chain of equal frequencies, optimal is a balanced tree of height 2
+        let mut tree = HuffNode::Internal {
+            freq: 4,
+            left: leaf(b'a', 1),
+            right: Box::new(HuffNode::Internal {
+                freq: 3,
+                left: leaf(b'b', 1),
+                right: Box::new(HuffNode::Internal {
+                    freq: 2,
+                    left: leaf(b'c', 1),
+                    right: leaf(b'd', 1),
+                }),
+            }),
+        };
+
+        let stats = tree.rebalance();
+
+        assert_eq!(stats.max_length_before, 3);
+        assert_eq!(stats.max_length_after, 2);
+        assert!(stats.swaps > 0);
+        assert_eq!(tree.freq(), 4);
+        let mut symbols: Vec<u8> = tree.leaves().iter().map(|(symbol, _)| *symbol).collect();
+        symbols.sort();
+        assert_eq!(symbols, b"abcd");
+    }
+
+    #[test]
+    fn rebalance_optimal_tree_is_unchanged() {
+        let bytes = [1, 2, 1, 1, 1, 1, 1, 1, 1, 3, 1];
+        let mut tree = build_huffman_tree(&tally_frequency(&bytes)).unwrap();
+        let original = tree.clone();
+
+        let stats = tree.rebalance();
+
+        assert_eq!(stats.swaps, 0);
+        assert_eq!(stats.max_length_before, stats.max_length_after);
+        assert_eq!(tree, original);
+    }
+
+    #[test]
+    fn merge_sums_child_frequencies() {
+        let merged = HuffNode::merge(*leaf(b'a', 3), *leaf(b'b', 5));
+        assert_eq!(merged.freq(), 8);
+        assert_eq!(merged.leaves(), [(b'a', 3), (b'b', 5)]);
+
+        let root = HuffNode::merge(merged, *leaf(b'c', 9));
+        assert_eq!(root.freq(), 17);
+        assert_eq!(root.height(), 2);
+        let HuffNode::Internal { left, right, .. } = &root else {
+            panic!("merge must return an internal node");
+        };
+        assert_eq!(root.freq(), left.freq() + right.freq());
+
+        let saturated = HuffNode::merge(*leaf(b'x', u64::MAX), *leaf(b'y', 1));
+        assert_eq!(saturated.freq(), u64::MAX);
+    }
+
+    fn weighted_path_length(tree: &HuffNode) -> u64 {
+        let freqs = tree.leaves();
+        tree.leaf_depths()
+            .iter()
+            .zip(freqs)
+            .map(|((_, depth), (_, freq))| *depth as u64 * freq)
+            .sum()
+    }
+
+    #[test]
+    fn two_queue_tree_is_optimal() {
+        let inputs: [&[u8]; 4] = [
+            b"abracadabra",
+            b"aaaabbbbccccdddd",
+            b"the quick brown fox jumps over the lazy dog",
+            &[7; 5],
+        ];
+        for input in inputs {
+            let freq_buffer = tally_frequency(input);
+            let heap_tree = build_huffman_tree(&freq_buffer).unwrap();
+            let two_queue_tree = build_huffman_tree_two_queue(&freq_buffer).unwrap();
+
+            assert_eq!(two_queue_tree.freq(), input.len() as u64);
+            assert_eq!(two_queue_tree.symbol_count(), heap_tree.symbol_count());
+            assert_eq!(
+                weighted_path_length(&two_queue_tree),
+                weighted_path_length(&heap_tree)
+            );
+        }
+        assert!(build_huffman_tree_two_queue(&tally_frequency(&[])).is_none());
+    }
+
+    #[test]
+    fn traversal_orders() {
+        // root(freq 4) -> left: leaf a, right: internal(freq 3) -> b, c
+        let tree = HuffNode::merge(
+            *leaf(b'a', 1),
+            HuffNode::merge(*leaf(b'b', 1), *leaf(b'c', 2)),
+        );
+        let order = |nodes: Vec<&HuffNode>| -> Vec<String> {
+            nodes
+                .into_iter()
+                .map(|node| match node {
+                    HuffNode::Leaf { symbol, .. } => (*symbol as char).to_string(),
+                    HuffNode::Internal { freq, .. } => freq.to_string(),
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            order(tree.preorder_traversal().collect()),
+            ["4", "a", "3", "b", "c"]
+        );
+        assert_eq!(
+            order(tree.inorder_traversal().collect()),
+            ["a", "4", "b", "3", "c"]
+        );
+        assert_eq!(
+            order(tree.postorder_traversal().collect()),
+            ["a", "b", "c", "3", "4"]
+        );
+
+        let single = *leaf(b'z', 1);
+        assert_eq!(single.preorder_traversal().count(), 1);
+        assert_eq!(single.inorder_traversal().count(), 1);
+        assert_eq!(single.postorder_traversal().count(), 1);
+    }
+
+    #[test]
+    fn deep_tree_traversal() {
+        let mut freq_buffer = FrequencyBuffer([0; 256]);
+        // Fibonacci-like counts give a chain of 255 levels
+        let (mut a, mut b) = (1u64, 1u64);
+        for count in freq_buffer.0.iter_mut() {
+            *count = a;
+            (a, b) = (b, a.saturating_add(b));
+        }
+        let tree = build_huffman_tree(&freq_buffer).unwrap();
+        assert!(tree.height() > 64);
+        assert_eq!(tree.preorder_traversal().count(), 511);
+        assert_eq!(tree.inorder_traversal().count(), 511);
+        assert_eq!(tree.postorder_traversal().count(), 511);
+    }
+
+    #[test]
+    fn tree_to_huffman_array() {
+        let tree = build_huffman_tree(&tally_frequency(b"abracadabra")).unwrap();
+        let array = tree.to_huffman_array();
+        assert_eq!(array[0], b'a');
+        let mut symbols = array.clone();
+        symbols.sort_unstable();
+        assert_eq!(symbols, b"abcdr");
+
+        let depth = |symbol: u8| {
+            tree.leaf_depths()
+                .into_iter()
+                .find(|(leaf, _)| *leaf == symbol)
+                .unwrap()
+                .1
+        };
+        for pair in array.windows(2) {
+            assert!((depth(pair[0]), pair[0]) < (depth(pair[1]), pair[1]));
+        }
+
+        let encoded_map = crate::encode_huffman_array(&array);
+        assert_eq!(encoded_map.len(), 5);
+        assert_eq!(
+            HuffNode::Leaf { symbol: 9, freq: 1 }.to_huffman_array(),
+            [9]
+        );
+    }
+
+    #[test]
+    fn serialized_tree_round_trip() {
+        let full: Vec<u8> = (0..=255).collect();
+        let inputs: [&[u8]; 4] = [b"abracadabra", b"the quick brown fox", &full, &[7; 5]];
+        for input in inputs {
+            let tree = build_huffman_tree(&tally_frequency(input)).unwrap();
+            let serialized = tree.serialize_tree();
+            let num_leaves = tree.symbol_count();
+            assert_eq!(serialized.len(), (10 * num_leaves - 1).div_ceil(8));
+
+            let rebuilt = HuffNode::deserialize_tree(BitSlice::from_slice(&serialized)).unwrap();
+            assert_eq!(rebuilt.leaf_depths(), tree.leaf_depths());
+            assert_eq!(rebuilt.serialize_tree(), serialized);
+        }
+    }
+
+    #[test]
+    fn deserialize_tree_rejects_malformed_bits() {
+        let tree = build_huffman_tree(&tally_frequency(b"abracadabra")).unwrap();
+        let serialized = tree.serialize_tree();
+        let truncated = &BitSlice::<u8, Msb0>::from_slice(&serialized)[..10 * 5 - 2];
+        assert!(HuffNode::deserialize_tree(truncated).is_err());
+        assert!(HuffNode::deserialize_tree(BitSlice::empty()).is_err());
+
+        // An internal node with two leaves of the same symbol
+        let duplicate: BitVec<u8, Msb0> = [false, true]
+            .into_iter()
+            .chain(b'a'.view_bits::<Msb0>().iter().by_vals())
+            .chain([true])
+            .chain(b'a'.view_bits::<Msb0>().iter().by_vals())
+            .collect();
+        assert!(matches!(
+            HuffNode::deserialize_tree(&duplicate),
+            Err(HuffErr::MalformedData(_))
+        ));
+    }
+
+    #[test]
+    fn empty_tree() {
+        assert!(build_huffman_tree(&tally_frequency(&[])).is_none());
+    }
+
+    #[test]
+    fn arena_matches_boxed_tree() {
+        let original = std::fs::read("./tests/resources/dna_seq_test.txt").unwrap();
+        for input in [&b"abracadabra"[..], b"the quick brown fox", &original] {
+            let arena = build_huffman_arena(tally_frequency(input));
+            let tree = build_huffman_tree(&tally_frequency(input)).unwrap();
+
+            let mut depths = tree.leaf_depths();
+            depths.sort_unstable();
+            assert_eq!(arena.code_lengths(), depths);
+            assert_eq!(
+                arena.nodes()[arena.root().unwrap()].freq,
+                input.len() as u64
+            );
+        }
+        assert!(build_huffman_arena(tally_frequency(&[])).root().is_none());
+    }
+
+    #[test]
+    fn arena_increment_propagates_to_root() {
+        let mut arena = build_huffman_arena(tally_frequency(b"aaaabbc"));
+        let root = arena.root().unwrap();
+        let c = arena.leaf(b'c').unwrap();
+        let parent = arena.nodes()[c].parent.unwrap();
+        assert_eq!(arena.depth(c), 2);
+
+        arena.increment(c, 3);
+        assert_eq!(arena.nodes()[c].freq, 4);
+        assert_eq!(arena.nodes()[parent].freq, 6);
+        assert_eq!(arena.nodes()[root].freq, 10);
+        assert_eq!(arena.nodes()[arena.leaf(b'a').unwrap()].freq, 4);
+    }
+
+    #[test]
+    fn tree_codes_have_optimal_lengths() {
+        let mut freq_buffer = FrequencyBuffer([0; 256]);
+        for (symbol, freq) in [(b'a', 5), (b'b', 2), (b'c', 1), (b'd', 1)] {
+            freq_buffer[symbol] = freq;
+        }
+        let encoded_map = encode_huffman_tree(&build_huffman_tree(&freq_buffer).unwrap());
+
+        let lengths: Vec<usize> = [b'a', b'b', b'c', b'd']
+            .iter()
+            .map(|symbol| encoded_map[symbol].bits.len())
+            .collect();
+        assert_eq!(lengths, [1, 2, 3, 3]);
+        // The codes are prefix free, so they can be decoded
+        assert!(crate::decoder::HuffmanDecoder::new(&encoded_map).is_ok());
+
+        let single = encode_huffman_tree(&build_huffman_tree(&tally_frequency(b"zzz")).unwrap());
+        assert_eq!(single[&b'z'].bits.len(), 1);
+    }
+
+    #[test]
+    fn tree_codes_beat_unary_codes_on_text() {
+        let text = b"It was the best of times, it was the worst of times, it was the age of \
+            wisdom, it was the age of foolishness, it was the epoch of belief.";
+        let tree_map = encode_huffman_tree(&build_huffman_tree(&tally_frequency(text)).unwrap());
+        let unary_map =
+            crate::encode_huffman_array(&crate::build_huffman_array(&tally_frequency(text)));
+
+        let (_, tree_bits) = crate::huff_encode_bitvec(text, &tree_map).unwrap();
+        let (_, unary_bits) = crate::huff_encode_bitvec(text, &unary_map).unwrap();
+        assert!(
+            tree_bits * 4 < unary_bits * 3,
+            "{} vs {}",
+            tree_bits,
+            unary_bits
+        );
+    }
+}
@@ -2,20 +2,55 @@ use huffc::*;
 
 const FILE_PATH: &str = "./tests/resources/dna_seq_test.txt";
 
-/// Serialize to deserialize test
+/// Compress to decompress test
 #[test]
 fn serialize_and_deserialize_test() {
     let original: String = std::fs::read_to_string(FILE_PATH).unwrap();
 
-    let bytes = original.as_bytes();
-    let freq_buff = tally_frequency(bytes);
-    let huffnode = build_huffman_array(freq_buff);
-    let encoded_map = encode_huffman_array(&huffnode);
-    let (bit_buffer, total_bits) = huff_encode_bitvec(bytes, &encoded_map);
-    let serialized_buffer = serialize_huffman(&encoded_map, bit_buffer, total_bits);
-    let deserialized_bytes = deserialize_huffman(&serialized_buffer);
+    let compressed = compress(original.as_bytes()).unwrap();
+    let decompressed = decompress(&compressed).unwrap();
 
-    let actual = String::from_utf8_lossy(&deserialized_bytes);
+    let actual = String::from_utf8_lossy(&decompressed);
 
     assert_eq!(actual, original);
 }
+
+/// Bytes of the test binary compressed by `test_binary_file_round_trip`, the whole debug binary
+/// takes minutes to round trip.
+const BINARY_SAMPLE_LEN: usize = 1 << 20;
+
+/// Compresses the start of the running test binary, binary files exercise all 256 byte values and
+/// have far more complex frequency distributions than text. The output may only grow by the
+/// header, data that does not shrink is stored.
+#[test]
+fn test_binary_file_round_trip() {
+    let mut original = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+    original.truncate(BINARY_SAMPLE_LEN);
+    binary_round_trip(&original);
+}
+
+/// `test_binary_file_round_trip` on the whole test binary, run with `cargo test -- --ignored`.
+#[test]
+#[ignore = "round trips the whole test binary, which takes minutes in debug builds"]
+fn test_whole_binary_file_round_trip() {
+    binary_round_trip(&std::fs::read(std::env::current_exe().unwrap()).unwrap());
+}
+
+fn binary_round_trip(original: &[u8]) {
+    let compressed = compress(original).unwrap();
+    let decompressed = decompress(&compressed).unwrap();
+
+    println!(
+        "binary round trip: original {} bytes, compressed {} bytes",
+        original.len(),
+        compressed.len()
+    );
+
+    assert!(decompressed == original);
+    assert!(
+        compressed.len() <= original.len() * 11 / 10,
+        "compressed size {} is more than 10% above the original size {}",
+        compressed.len(),
+        original.len()
+    );
+}
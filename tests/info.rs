@@ -0,0 +1,52 @@
+use std::{
+    fs::File,
+    process::{Command, Stdio},
+};
+
+/// Compresses a small file with `huffc` and inspects the result with `--info`, both outputs
+/// report the symbols and bits of the Huffman codes of `abracadabra`.
+#[test]
+fn info_reports_symbols_and_bits_of_a_compressed_file() {
+    let dir = std::env::temp_dir().join(format!("huffc_info_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input_path = dir.join("abracadabra.txt");
+    // The output extension is appended to the `--out-file` path
+    let huff_path = dir.join("abracadabra.txt.huff");
+    std::fs::write(&input_path, "abracadabra".repeat(10)).unwrap();
+
+    let compress = Command::new(env!("CARGO_BIN_EXE_huffc"))
+        .args(["--compress", "--stdin", "-o"])
+        .arg(&input_path)
+        .stdin(File::open(&input_path).unwrap())
+        .status()
+        .unwrap();
+    assert!(compress.success());
+
+    let info = |json: bool| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_huffc"));
+        command.args(["--info", "--stdin"]);
+        if json {
+            command.arg("--json");
+        }
+        let output = command
+            .stdin(File::open(&huff_path).unwrap())
+            .stdout(Stdio::piped())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    // a: 1 bit x 50, b, r, c and d: 3 bits x 20, 20, 10 and 10
+    let text = info(false);
+    assert!(text.contains("format version: 2"), "{}", text);
+    assert!(text.contains("symbols: 5"), "{}", text);
+    assert!(text.contains("total bits: 230"), "{}", text);
+
+    let json = info(true);
+    assert!(json.starts_with('{'), "{}", json);
+    assert!(json.contains("\"symbol_count\":5"), "{}", json);
+    assert!(json.contains("\"total_bits\":230"), "{}", json);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
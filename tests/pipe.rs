@@ -0,0 +1,89 @@
+use std::{
+    io::{Read, Write},
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[test]
+fn piped_compress_decompress() {
+    let original = std::fs::read("./tests/resources/dna_seq_test.txt").unwrap();
+
+    let mut compress = Command::new(env!("CARGO_BIN_EXE_huffc"))
+        .args(["--compress", "--stdin", "--stdout"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut decompress = Command::new(env!("CARGO_BIN_EXE_huffc"))
+        .args(["--decompress", "--stdin", "--stdout"])
+        .stdin(compress.stdout.take().unwrap())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Feed and drain on separate threads so neither process blocks on a full pipe
+    let mut compress_stdin = compress.stdin.take().unwrap();
+    let input = original.clone();
+    let writer = thread::spawn(move || compress_stdin.write_all(&input));
+    let mut decompress_stdout = decompress.stdout.take().unwrap();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = tx.send(decompress_stdout.read_to_end(&mut output).map(|_| output));
+    });
+
+    let output = match rx.recv_timeout(TIMEOUT) {
+        Ok(output) => output.unwrap(),
+        Err(_) => {
+            let _ = compress.kill();
+            let _ = decompress.kill();
+            panic!("huffc pipe did not finish within {:?}", TIMEOUT);
+        }
+    };
+    writer.join().unwrap().unwrap();
+    assert!(compress.wait().unwrap().success());
+    assert!(decompress.wait().unwrap().success());
+
+    assert_eq!(output, original);
+}
+
+#[test]
+fn errors_go_to_stderr_with_a_failing_status() {
+    let mut decompress = Command::new(env!("CARGO_BIN_EXE_huffc"))
+        .args(["--decompress", "--stdin", "--stdout"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    decompress
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"not a huff file")
+        .unwrap();
+
+    let output = decompress.wait_with_output().unwrap();
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(!output.stderr.is_empty());
+}
+
+#[test]
+fn test_mode_round_trips_the_input() {
+    let path = "./tests/resources/dna_seq_test.txt";
+    let output = Command::new(env!("CARGO_BIN_EXE_huffc"))
+        .args(["--test", "--stdin"])
+        .stdin(std::fs::File::open(path).unwrap())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("OK, {} bytes\n", std::fs::metadata(path).unwrap().len())
+    );
+}
@@ -0,0 +1,26 @@
+use huffc::*;
+use proptest::{
+    collection::vec,
+    num::u8::ANY,
+    prop_assert_eq, proptest,
+    test_runner::{Config, RngSeed},
+};
+
+proptest! {
+    // The default 256 cases, from a fixed seed so a failure reproduces on every run
+    #![proptest_config(Config {
+        rng_seed: RngSeed::Fixed(0x4855_4646),
+        ..Config::default()
+    })]
+
+    /// Runs every step of the pipeline by hand, `compress` would store most random input as is
+    #[test]
+    fn arbitrary_bytes_round_trip(original in vec(ANY, 1..=65536)) {
+        let huffman_array = build_huffman_array(&tally_frequency(&original));
+        let encoded_map = encode_huffman_array(&huffman_array);
+        let (bit_buffer, total_bits) = huff_encode_bitvec(&original, &encoded_map).unwrap();
+        let serialized = serialize_huffman(&encoded_map, bit_buffer, total_bits);
+
+        prop_assert_eq!(deserialize_huffman(&serialized).unwrap(), original);
+    }
+}
@@ -0,0 +1,108 @@
+use std::io::{Read, Write};
+
+use huffc::{
+    build_huffman_array, decoder::HuffmanReader, encode_huffman_array, encoder::HuffmanEncoder,
+    serialize_huffman, tally_frequency, FrequencyBuffer,
+};
+
+/// 10 MB of skewed pseudo random text, so the unary codes are of many lengths
+fn skewed_text() -> Vec<u8> {
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    (0..10 * 1024 * 1024)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            b"eeeeeeeetttttaaaoinshrdlu"[(state % 25) as usize]
+        })
+        .collect()
+}
+
+/// Compresses in 64 KB chunks with the streaming encoder, then decompresses with 8 byte reads,
+/// so reads end inside codes and the last read ends exactly at the total number of bits
+#[test]
+fn streaming_encoder_and_reader_round_trip() {
+    let original = skewed_text();
+
+    let mut freq_buffer = FrequencyBuffer([0; 256]);
+    for chunk in original.chunks(64 * 1024) {
+        freq_buffer.merge(&tally_frequency(chunk));
+    }
+    let huffman_array = build_huffman_array(&freq_buffer);
+    let mut encoder = HuffmanEncoder::new(encode_huffman_array(&huffman_array), Vec::new());
+    for chunk in original.chunks(64 * 1024) {
+        encoder.write_all(chunk).unwrap();
+    }
+    let (bit_buffer, total_bits) = encoder.finish().unwrap();
+    let compressed = serialize_huffman(
+        &encode_huffman_array(&huffman_array),
+        bit_buffer,
+        total_bits,
+    );
+
+    let mut reader = HuffmanReader::new(compressed.as_slice());
+    let mut decompressed = Vec::with_capacity(original.len());
+    let mut chunk = [0u8; 8];
+    loop {
+        let read = reader.read(&mut chunk).unwrap();
+        if read == 0 {
+            break;
+        }
+        decompressed.extend_from_slice(&chunk[..read]);
+    }
+    assert_eq!(decompressed.len(), original.len());
+    assert!(decompressed == original);
+}
+
+#[test]
+fn reader_reads_stored_and_rejects_other_streams() {
+    let stored = huffc::compress(b"").unwrap();
+    let mut decompressed = Vec::new();
+    HuffmanReader::new(stored.as_slice())
+        .read_to_end(&mut decompressed)
+        .unwrap();
+    assert!(decompressed.is_empty());
+
+    let original = b"abababababababababababababababababababab";
+    let compressed = huffc::compress(original).unwrap();
+    assert!(!huffc::is_stored_passthrough(&compressed));
+    let mut decompressed = Vec::new();
+    HuffmanReader::new(compressed.as_slice())
+        .read_to_end(&mut decompressed)
+        .unwrap();
+    assert_eq!(decompressed, original);
+
+    let err = HuffmanReader::new(&b"not huff"[..])
+        .read_to_end(&mut Vec::new())
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+    // The bit buffer ends before the total number of bits
+    let err = HuffmanReader::new(&compressed[..compressed.len() - 1])
+        .read_to_end(&mut Vec::new())
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn reader_verifies_the_checksum_at_the_end() {
+    let original = b"abababababababababababababababababababab";
+    for mut compressed in [
+        huffc::compress(original).unwrap(),
+        huffc::compress(b"xyz").unwrap(),
+    ] {
+        let last = compressed.len() - 1;
+        compressed[last] ^= 0x01;
+        let err = HuffmanReader::new(compressed.as_slice())
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(matches!(
+            err.into_inner()
+                .unwrap()
+                .downcast::<huffc::HuffErr>()
+                .as_deref(),
+            Ok(huffc::HuffErr::ChecksumMismatch { .. })
+        ));
+    }
+}